@@ -0,0 +1,10 @@
+CREATE TABLE lists (
+    list_id uuid NOT NULL,
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY(list_id)
+);
+
+INSERT INTO lists (list_id, slug, name, created_at)
+VALUES ('3a1c9d5e-2f64-4b8e-9a70-5d1e8c4b2f10', 'newsletter', 'Newsletter', now());
@@ -0,0 +1,12 @@
+CREATE TABLE list_subscriptions (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    list_id uuid NOT NULL REFERENCES lists (list_id),
+    status TEXT NOT NULL,
+    subscribed_at timestamptz NOT NULL,
+    PRIMARY KEY(subscriber_id, list_id)
+);
+
+-- Everyone who subscribed before lists existed belongs to the default list.
+INSERT INTO list_subscriptions (subscriber_id, list_id, status, subscribed_at)
+SELECT id, '3a1c9d5e-2f64-4b8e-9a70-5d1e8c4b2f10', status, subscribed_at
+FROM subscriptions;
@@ -0,0 +1,7 @@
+BEGIN;
+    ALTER TABLE subscription_tokens ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+    UPDATE subscription_tokens
+    SET list_id = '3a1c9d5e-2f64-4b8e-9a70-5d1e8c4b2f10'
+    WHERE list_id IS NULL;
+    ALTER TABLE subscription_tokens ALTER COLUMN list_id SET NOT NULL;
+COMMIT;
@@ -0,0 +1,7 @@
+CREATE TABLE subscriber_attributes (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY(subscriber_id, key)
+);
+CREATE INDEX subscriber_attributes_key_value_idx ON subscriber_attributes (key, value);
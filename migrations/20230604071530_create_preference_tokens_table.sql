@@ -0,0 +1,13 @@
+CREATE TABLE preference_tokens (
+    preference_token TEXT NOT NULL,
+    subscriber_id uuid NOT NULL UNIQUE REFERENCES subscriptions (id),
+    PRIMARY KEY (preference_token)
+);
+
+-- The tokens need no login to change a subscription: draw them from a
+-- cryptographically secure source.
+CREATE EXTENSION IF NOT EXISTS pgcrypto;
+
+INSERT INTO preference_tokens (preference_token, subscriber_id)
+SELECT encode(gen_random_bytes(16), 'hex'), id
+FROM subscriptions;
@@ -0,0 +1,7 @@
+CREATE TABLE email_change_requests (
+    confirmation_token TEXT NOT NULL,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    new_email TEXT NOT NULL,
+    requested_at timestamptz NOT NULL,
+    PRIMARY KEY (confirmation_token)
+);
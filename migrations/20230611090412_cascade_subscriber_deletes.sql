@@ -0,0 +1,22 @@
+BEGIN;
+    ALTER TABLE subscription_tokens
+        DROP CONSTRAINT subscription_tokens_subscription_id_fkey,
+        ADD CONSTRAINT subscription_tokens_subscription_id_fkey
+            FOREIGN KEY (subscription_id) REFERENCES subscriptions (id) ON DELETE CASCADE;
+    ALTER TABLE list_subscriptions
+        DROP CONSTRAINT list_subscriptions_subscriber_id_fkey,
+        ADD CONSTRAINT list_subscriptions_subscriber_id_fkey
+            FOREIGN KEY (subscriber_id) REFERENCES subscriptions (id) ON DELETE CASCADE;
+    ALTER TABLE subscriber_attributes
+        DROP CONSTRAINT subscriber_attributes_subscriber_id_fkey,
+        ADD CONSTRAINT subscriber_attributes_subscriber_id_fkey
+            FOREIGN KEY (subscriber_id) REFERENCES subscriptions (id) ON DELETE CASCADE;
+    ALTER TABLE preference_tokens
+        DROP CONSTRAINT preference_tokens_subscriber_id_fkey,
+        ADD CONSTRAINT preference_tokens_subscriber_id_fkey
+            FOREIGN KEY (subscriber_id) REFERENCES subscriptions (id) ON DELETE CASCADE;
+    ALTER TABLE email_change_requests
+        DROP CONSTRAINT email_change_requests_subscriber_id_fkey,
+        ADD CONSTRAINT email_change_requests_subscriber_id_fkey
+            FOREIGN KEY (subscriber_id) REFERENCES subscriptions (id) ON DELETE CASCADE;
+COMMIT;
@@ -0,0 +1,7 @@
+CREATE TABLE data_requests (
+    request_token TEXT NOT NULL,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    requested_at timestamptz NOT NULL,
+    PRIMARY KEY (request_token)
+);
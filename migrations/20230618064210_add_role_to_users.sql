@@ -0,0 +1,7 @@
+BEGIN;
+    ALTER TABLE users ADD COLUMN role TEXT NULL;
+    -- Whoever could log in so far was in charge of everything.
+    UPDATE users SET role = 'owner';
+    ALTER TABLE users ALTER COLUMN role SET NOT NULL;
+    ALTER TABLE users ADD COLUMN deactivated_at timestamptz NULL;
+COMMIT;
@@ -0,0 +1,7 @@
+-- Invited users exist before they have picked a username and a password.
+BEGIN;
+    ALTER TABLE users ALTER COLUMN username DROP NOT NULL;
+    ALTER TABLE users ALTER COLUMN password_hash DROP NOT NULL;
+    ALTER TABLE users ADD COLUMN email TEXT NULL UNIQUE;
+    ALTER TABLE users ADD COLUMN invited_at timestamptz NULL;
+COMMIT;
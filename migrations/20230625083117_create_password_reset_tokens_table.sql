@@ -0,0 +1,8 @@
+CREATE TABLE password_reset_tokens (
+    token_hash TEXT NOT NULL,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    requested_at timestamptz NOT NULL,
+    PRIMARY KEY (token_hash)
+);
+-- Sessions started before this point in time are no longer valid.
+ALTER TABLE users ADD COLUMN sessions_revoked_at timestamptz NULL;
@@ -0,0 +1,10 @@
+ALTER TABLE users ADD COLUMN totp_secret TEXT NULL;
+-- The last time step a code was accepted for, so that codes cannot be replayed.
+ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT NULL;
+
+CREATE TABLE recovery_codes (
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    used_at timestamptz NULL,
+    PRIMARY KEY (user_id, code_hash)
+);
@@ -0,0 +1,23 @@
+CREATE TABLE audit_log (
+    audit_id BIGSERIAL PRIMARY KEY,
+    occurred_at timestamptz NOT NULL DEFAULT now(),
+    -- NULL when nobody was logged in, e.g. a failed login.
+    -- Not a foreign key: entries must outlive what they refer to.
+    actor_id uuid NULL,
+    action TEXT NOT NULL,
+    target TEXT NULL,
+    ip TEXT NULL,
+    user_agent TEXT NULL
+);
+CREATE INDEX audit_log_occurred_at_idx ON audit_log (occurred_at);
+
+-- The log is append-only.
+CREATE FUNCTION reject_audit_log_changes() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_is_append_only
+    BEFORE UPDATE OR DELETE ON audit_log
+    FOR EACH ROW EXECUTE FUNCTION reject_audit_log_changes();
@@ -0,0 +1,15 @@
+-- One row per login, so that users can see where they are logged in
+-- and end sessions they do not recognise.
+CREATE TABLE user_sessions (
+    session_id uuid PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL,
+    last_seen_at timestamptz NOT NULL,
+    ip TEXT NOT NULL,
+    user_agent TEXT NULL,
+    revoked_at timestamptz NULL
+);
+CREATE INDEX user_sessions_user_id_idx ON user_sessions (user_id);
+
+-- Superseded by `user_sessions.revoked_at`.
+ALTER TABLE users DROP COLUMN sessions_revoked_at;
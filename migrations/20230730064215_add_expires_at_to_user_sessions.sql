@@ -0,0 +1,3 @@
+-- Sessions started before lifetimes were configurable expire right away.
+ALTER TABLE user_sessions ADD COLUMN expires_at timestamptz NOT NULL DEFAULT now();
+ALTER TABLE user_sessions ALTER COLUMN expires_at DROP DEFAULT;
@@ -0,0 +1,8 @@
+-- Domains admins have let in or turned away, whatever the bundled
+-- disposable-domain list and the MX check say.
+CREATE TABLE email_domain_rules (
+    domain TEXT PRIMARY KEY,
+    kind TEXT NOT NULL CHECK (kind IN ('allow', 'block')),
+    created_at timestamptz NOT NULL DEFAULT now(),
+    created_by uuid NULL REFERENCES users (user_id) ON DELETE SET NULL
+);
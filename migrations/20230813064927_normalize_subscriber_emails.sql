@@ -0,0 +1,66 @@
+-- Addresses are now stored normalized, as `SubscriberEmail::parse` does:
+-- trimmed, with a lowercase domain. (Internationalized domains cannot be
+-- IDNA-encoded here; they are left as they are.) Provider aliases, such as
+-- Gmail's dots, are only merged for new addresses, when the settings ask for
+-- it. Subscribers whose addresses only differed in case are merged into the
+-- one who confirmed, or else the oldest.
+CREATE TEMPORARY TABLE normalized_subscriptions AS
+SELECT id, local_part || '@' || lower(domain) AS email
+FROM (
+    SELECT
+        id,
+        substring(btrim(email) from '^(.*)@[^@]*$') AS local_part,
+        substring(btrim(email) from '@([^@]*)$') AS domain
+    FROM subscriptions
+) parts
+WHERE local_part IS NOT NULL AND domain IS NOT NULL;
+
+CREATE TEMPORARY TABLE merged_subscriptions AS
+SELECT id, keeper_id
+FROM (
+    SELECT
+        s.id,
+        first_value(s.id) OVER (
+            PARTITION BY lower(n.email)
+            ORDER BY s.status = 'confirmed' DESC, s.subscribed_at, s.id
+        ) AS keeper_id
+    FROM subscriptions s
+    JOIN normalized_subscriptions n ON n.id = s.id
+) ranked
+WHERE id <> keeper_id;
+
+-- The lists of a merged subscriber carry over, confirmations winning.
+INSERT INTO list_subscriptions (subscriber_id, list_id, status, subscribed_at)
+SELECT m.keeper_id, l.list_id, l.status, l.subscribed_at
+FROM list_subscriptions l
+JOIN merged_subscriptions m ON m.id = l.subscriber_id
+ON CONFLICT (subscriber_id, list_id) DO UPDATE
+SET status = EXCLUDED.status
+WHERE list_subscriptions.status = 'pending_confirmation' AND EXCLUDED.status = 'confirmed';
+
+INSERT INTO subscriber_attributes (subscriber_id, key, value)
+SELECT m.keeper_id, a.key, a.value
+FROM subscriber_attributes a
+JOIN merged_subscriptions m ON m.id = a.subscriber_id
+ON CONFLICT (subscriber_id, key) DO NOTHING;
+
+-- Links already sent keep working.
+UPDATE subscription_tokens t SET subscription_id = m.keeper_id
+FROM merged_subscriptions m WHERE t.subscription_id = m.id;
+UPDATE email_change_requests r SET subscriber_id = m.keeper_id
+FROM merged_subscriptions m WHERE r.subscriber_id = m.id;
+UPDATE data_requests r SET subscriber_id = m.keeper_id
+FROM merged_subscriptions m WHERE r.subscriber_id = m.id;
+
+-- Preference tokens and whatever was not carried over cascade.
+DELETE FROM subscriptions s USING merged_subscriptions m WHERE s.id = m.id;
+
+UPDATE subscriptions s SET email = n.email
+FROM normalized_subscriptions n
+WHERE n.id = s.id AND s.email <> n.email;
+
+ALTER TABLE subscriptions DROP CONSTRAINT subscription_email_key;
+CREATE UNIQUE INDEX subscriptions_email_lower_idx ON subscriptions (lower(email));
+
+DROP TABLE normalized_subscriptions;
+DROP TABLE merged_subscriptions;
@@ -0,0 +1,13 @@
+-- Issues already sent stay out of the public archive until made public.
+ALTER TABLE newsletter_issues
+    ADD COLUMN slug TEXT,
+    ADD COLUMN visibility TEXT NOT NULL DEFAULT 'subscribers'
+        CHECK (visibility IN ('public', 'subscribers'));
+UPDATE newsletter_issues
+SET slug = COALESCE(
+    NULLIF(trim(BOTH '-' FROM lower(regexp_replace(title, '[^[:alnum:]]+', '-', 'g'))), ''),
+    'issue'
+) || '-' || left(newsletter_issue_id::text, 8);
+ALTER TABLE newsletter_issues
+    ALTER COLUMN slug SET NOT NULL,
+    ADD CONSTRAINT newsletter_issues_slug_key UNIQUE (slug);
@@ -0,0 +1,8 @@
+-- Read-only access to subscribers-only issues in the web archive. Kept
+-- apart from preference tokens, which can also change the subscription,
+-- because archive links get forwarded.
+CREATE TABLE archive_tokens (
+    archive_token TEXT NOT NULL,
+    subscriber_id uuid NOT NULL UNIQUE REFERENCES subscriptions (id) ON DELETE CASCADE,
+    PRIMARY KEY (archive_token)
+);
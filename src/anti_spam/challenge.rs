@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use secrecy::{ExposeSecret, Secret};
+
+use crate::configuration::ChallengeSettings;
+
+/// Checks the answer a subscription form gave to a challenge, such as
+/// a CAPTCHA.
+#[async_trait]
+pub trait ChallengeVerifier: Send + Sync {
+    /// Whether `response`, sent from `ip`, answers the challenge.
+    async fn verify(&self, response: &str, ip: &str) -> Result<bool, anyhow::Error>;
+}
+
+/// The verifier `settings` ask for, if any.
+pub fn challenge_verifier(settings: &ChallengeSettings) -> Option<Box<dyn ChallengeVerifier>> {
+    match settings {
+        ChallengeSettings::Disabled => None,
+        ChallengeSettings::Stub { answer } => Some(Box::new(StubChallengeVerifier {
+            answer: answer.clone(),
+        })),
+        ChallengeSettings::Remote { verify_url, secret } => {
+            Some(Box::new(RemoteChallengeVerifier {
+                http_client: reqwest::Client::builder()
+                    .timeout(Duration::from_secs(10))
+                    .build()
+                    .unwrap(),
+                verify_url: verify_url.clone(),
+                secret: secret.clone(),
+            }))
+        }
+    }
+}
+
+/// Accepts one fixed answer: stands in for a real challenge locally.
+pub struct StubChallengeVerifier {
+    answer: String,
+}
+
+#[async_trait]
+impl ChallengeVerifier for StubChallengeVerifier {
+    async fn verify(&self, response: &str, _ip: &str) -> Result<bool, anyhow::Error> {
+        Ok(response == self.answer)
+    }
+}
+
+/// Asks a hosted challenge service, which all answer a form with the
+/// secret, the response and the client IP with `{"success": <bool>, ...}`.
+pub struct RemoteChallengeVerifier {
+    http_client: reqwest::Client,
+    verify_url: String,
+    secret: Secret<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct VerifyResponse {
+    success: bool,
+}
+
+#[async_trait]
+impl ChallengeVerifier for RemoteChallengeVerifier {
+    #[tracing::instrument(name = "Verify a challenge response", skip(self, response))]
+    async fn verify(&self, response: &str, ip: &str) -> Result<bool, anyhow::Error> {
+        let outcome: VerifyResponse = self
+            .http_client
+            .post(&self.verify_url)
+            .form(&[
+                ("secret", self.secret.expose_secret().as_str()),
+                ("response", response),
+                ("remoteip", ip),
+            ])
+            .send()
+            .await
+            .context("Failed to reach the challenge service")?
+            .error_for_status()
+            .context("The challenge service returned an error")?
+            .json()
+            .await
+            .context("Failed to read the challenge service's answer")?;
+        Ok(outcome.success)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{challenge_verifier, ChallengeVerifier, RemoteChallengeVerifier};
+    use crate::configuration::ChallengeSettings;
+    use claims::{assert_err, assert_ok_eq};
+    use secrecy::Secret;
+    use wiremock::matchers::{body_string_contains, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn remote(server: &MockServer) -> RemoteChallengeVerifier {
+        RemoteChallengeVerifier {
+            http_client: reqwest::Client::new(),
+            verify_url: server.uri(),
+            secret: Secret::new("challenge-secret".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn the_stub_accepts_only_its_answer() {
+        let verifier = challenge_verifier(&ChallengeSettings::Stub {
+            answer: "42".to_string(),
+        })
+        .unwrap();
+        assert_ok_eq!(verifier.verify("42", "127.0.0.1").await, true);
+        assert_ok_eq!(verifier.verify("41", "127.0.0.1").await, false);
+    }
+
+    #[test]
+    fn no_verifier_when_disabled() {
+        assert!(challenge_verifier(&ChallengeSettings::Disabled).is_none());
+    }
+
+    #[tokio::test]
+    async fn the_remote_service_decides() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("secret=challenge-secret"))
+            .and(body_string_contains("response=right"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": true
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("response=wrong"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": false,
+                "error-codes": ["invalid-input-response"]
+            })))
+            .mount(&server)
+            .await;
+
+        let verifier = remote(&server);
+        assert_ok_eq!(verifier.verify("right", "127.0.0.1").await, true);
+        assert_ok_eq!(verifier.verify("wrong", "127.0.0.1").await, false);
+    }
+
+    #[tokio::test]
+    async fn an_unavailable_service_is_an_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+        assert_err!(remote(&server).verify("right", "127.0.0.1").await);
+    }
+}
@@ -0,0 +1,100 @@
+use anyhow::{anyhow, Context};
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+
+/// When a subscription form was rendered, signed so that it cannot be
+/// backdated. Forms send it back in the hidden `form_stamp` field, as
+/// `<issued_at>.<signature>`.
+#[derive(Debug)]
+pub struct FormStamp {
+    issued_at: i64,
+    signature: String,
+}
+
+impl FormStamp {
+    pub fn new(secret: &Secret<String>) -> Self {
+        Self::issued_at(Utc::now().timestamp(), secret)
+    }
+
+    fn issued_at(issued_at: i64, secret: &Secret<String>) -> Self {
+        let signature = hex::encode(Self::mac(issued_at, secret).finalize().into_bytes());
+        Self {
+            issued_at,
+            signature,
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, anyhow::Error> {
+        let (issued_at, signature) = s
+            .split_once('.')
+            .ok_or_else(|| anyhow!("The form stamp has no signature."))?;
+        Ok(Self {
+            issued_at: issued_at
+                .parse()
+                .context("The form stamp has no valid timestamp.")?,
+            signature: signature.to_string(),
+        })
+    }
+
+    /// How long ago the form was rendered, if the stamp is genuine.
+    pub fn age(&self, secret: &Secret<String>) -> Result<Duration, anyhow::Error> {
+        let signature = hex::decode(&self.signature)?;
+        Self::mac(self.issued_at, secret).verify_slice(&signature)?;
+        Ok(Duration::seconds(Utc::now().timestamp() - self.issued_at))
+    }
+
+    fn mac(issued_at: i64, secret: &Secret<String>) -> Hmac<sha2::Sha256> {
+        let mut mac =
+            Hmac::<sha2::Sha256>::new_from_slice(secret.expose_secret().as_bytes()).unwrap();
+        // Keeps the stamp from passing for any other signed value.
+        mac.update(format!("form_stamp:{issued_at}").as_bytes());
+        mac
+    }
+}
+
+impl std::fmt::Display for FormStamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.issued_at, self.signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FormStamp;
+    use chrono::{Duration, Utc};
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-very-long-and-secret-key".to_string())
+    }
+
+    #[test]
+    fn a_stamp_round_trips_and_tells_its_age() {
+        let issued_at = (Utc::now() - Duration::seconds(30)).timestamp();
+        let stamp = FormStamp::issued_at(issued_at, &secret());
+        let parsed = assert_ok!(FormStamp::parse(&stamp.to_string()));
+        let age = assert_ok!(parsed.age(&secret()));
+        assert!(age >= Duration::seconds(30) && age < Duration::seconds(35));
+    }
+
+    #[test]
+    fn a_backdated_stamp_is_rejected() {
+        let stamp = FormStamp::new(&secret());
+        let backdated = format!("{}.{}", stamp.issued_at - 60, stamp.signature);
+        assert_err!(FormStamp::parse(&backdated).unwrap().age(&secret()));
+    }
+
+    #[test]
+    fn a_stamp_signed_with_another_key_is_rejected() {
+        let stamp = FormStamp::new(&secret());
+        assert_err!(stamp.age(&Secret::new("another-key".to_string())));
+    }
+
+    #[test]
+    fn malformed_stamps_are_rejected() {
+        assert_err!(FormStamp::parse("1690000000"));
+        assert_err!(FormStamp::parse("yesterday.abcdef"));
+    }
+}
@@ -0,0 +1,182 @@
+//! Keeps bots from filling `subscriptions` with pending rows and making us
+//! mail strangers. Suspicious attempts are dropped quietly: the form is
+//! answered as if it had worked, and the drop is counted in the metrics.
+
+mod challenge;
+mod form_stamp;
+
+pub use challenge::{
+    challenge_verifier, ChallengeVerifier, RemoteChallengeVerifier, StubChallengeVerifier,
+};
+pub use form_stamp::FormStamp;
+
+use anyhow::Context;
+use chrono::Duration;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use secrecy::{ExposeSecret, Secret};
+
+use crate::configuration::SubscriptionProtectionSettings;
+
+/// Why a subscription attempt was dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpamReason {
+    /// The field hidden from people was filled in.
+    Honeypot,
+    /// The form stamp was missing, forged or too old.
+    InvalidFormStamp,
+    /// The form was sent back faster than a person could fill it in.
+    TooFast,
+    ChallengeFailed,
+    IpRateLimit,
+    DomainRateLimit,
+}
+
+impl SpamReason {
+    pub const ALL: [SpamReason; 6] = [
+        SpamReason::Honeypot,
+        SpamReason::InvalidFormStamp,
+        SpamReason::TooFast,
+        SpamReason::ChallengeFailed,
+        SpamReason::IpRateLimit,
+        SpamReason::DomainRateLimit,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpamReason::Honeypot => "honeypot",
+            SpamReason::InvalidFormStamp => "invalid_form_stamp",
+            SpamReason::TooFast => "too_fast",
+            SpamReason::ChallengeFailed => "challenge_failed",
+            SpamReason::IpRateLimit => "ip_rate_limit",
+            SpamReason::DomainRateLimit => "domain_rate_limit",
+        }
+    }
+}
+
+/// The fields a subscription form carries for our benefit rather than
+/// the subscriber's.
+#[derive(Debug, Default)]
+pub struct SpamSignals {
+    pub honeypot: Option<String>,
+    pub form_stamp: Option<String>,
+    pub challenge_response: Option<String>,
+}
+
+pub struct SpamFilter {
+    connection: ConnectionManager,
+    settings: SubscriptionProtectionSettings,
+    hmac_secret: Secret<String>,
+    challenge: Option<Box<dyn ChallengeVerifier>>,
+}
+
+impl SpamFilter {
+    pub async fn new(
+        redis_uri: &Secret<String>,
+        settings: SubscriptionProtectionSettings,
+        hmac_secret: Secret<String>,
+    ) -> Result<Self, anyhow::Error> {
+        let client = redis::Client::open(redis_uri.expose_secret().as_str())
+            .context("Failed to parse the Redis URI")?;
+        let connection = client
+            .get_tokio_connection_manager()
+            .await
+            .context("Failed to connect to Redis")?;
+        Ok(Self {
+            connection,
+            challenge: challenge_verifier(&settings.challenge),
+            settings,
+            hmac_secret,
+        })
+    }
+
+    /// The stamp to put in a form being rendered now.
+    pub fn form_stamp(&self) -> FormStamp {
+        FormStamp::new(&self.hmac_secret)
+    }
+
+    /// Checks what the form itself tells about who filled it in.
+    #[tracing::instrument(name = "Screen a subscription form", skip(self, signals))]
+    pub async fn screen(&self, signals: &SpamSignals, ip: &str) -> Option<SpamReason> {
+        if signals.honeypot.as_deref().is_some_and(|v| !v.is_empty()) {
+            return Some(SpamReason::Honeypot);
+        }
+        if self.settings.min_seconds_to_submit > 0 {
+            let age = signals
+                .form_stamp
+                .as_deref()
+                .and_then(|s| FormStamp::parse(s).ok())
+                .and_then(|stamp| stamp.age(&self.hmac_secret).ok());
+            match age {
+                None => return Some(SpamReason::InvalidFormStamp),
+                Some(age) if age > Duration::seconds(self.settings.form_validity_seconds) => {
+                    return Some(SpamReason::InvalidFormStamp)
+                }
+                Some(age) if age < Duration::seconds(self.settings.min_seconds_to_submit) => {
+                    return Some(SpamReason::TooFast)
+                }
+                Some(_) => {}
+            }
+        }
+        if let Some(challenge) = &self.challenge {
+            let response = match signals.challenge_response.as_deref() {
+                Some(response) if !response.is_empty() => response,
+                _ => return Some(SpamReason::ChallengeFailed),
+            };
+            match challenge.verify(response, ip).await {
+                Ok(true) => {}
+                Ok(false) => return Some(SpamReason::ChallengeFailed),
+                // Rather than turning everyone away while the service is
+                // down, rely on the other checks for a while.
+                Err(e) => tracing::warn!(
+                    error.cause_chain = ?e,
+                    "Could not verify the challenge, letting the subscription through"
+                ),
+            }
+        }
+        None
+    }
+
+    /// Counts the attempt against `ip` and the domain of the email address,
+    /// and tells whether either has been used too often lately.
+    #[tracing::instrument(name = "Rate limit subscriptions", skip(self))]
+    pub async fn rate_limit(
+        &self,
+        ip: &str,
+        domain: &str,
+    ) -> Result<Option<SpamReason>, anyhow::Error> {
+        let mut connection = self.connection.clone();
+        let mut reason = None;
+        for (key, max, exceeded) in [
+            (
+                self.key("ip", ip),
+                self.settings.max_per_ip,
+                SpamReason::IpRateLimit,
+            ),
+            (
+                self.key("domain", &domain.to_lowercase()),
+                self.settings.max_per_domain,
+                SpamReason::DomainRateLimit,
+            ),
+        ] {
+            let attempts: u64 = connection
+                .incr(&key, 1)
+                .await
+                .context("Failed to count the subscription attempt")?;
+            if attempts == 1 {
+                connection
+                    .expire::<_, ()>(&key, self.settings.window_seconds)
+                    .await
+                    .context("Failed to set the rate limit window")?;
+            }
+            if attempts > max && reason.is_none() {
+                reason = Some(exceeded);
+            }
+        }
+        Ok(reason)
+    }
+
+    fn key(&self, kind: &str, subject: &str) -> String {
+        format!("{}:{}:{}", self.settings.key_prefix, kind, subject)
+    }
+}
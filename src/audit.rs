@@ -0,0 +1,178 @@
+use std::future::{ready, Ready};
+
+use actix_web::dev::Payload;
+use actix_web::http::header::USER_AGENT;
+use actix_web::{FromRequest, HttpRequest};
+use anyhow::Context;
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+use crate::utils::client_ip;
+
+/// Something worth knowing who did, and when.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    Login,
+    LoginFailed,
+    Logout,
+    PasswordChanged,
+    PasswordReset,
+    TwoFactorEnabled,
+    TwoFactorDisabled,
+    ApiTokenCreated,
+    ApiTokenRevoked,
+    SessionRevoked,
+    NewsletterPublished,
+    SubscriberCreated,
+    SubscriberExported,
+    SubscriberErased,
+    UserInvited,
+    UserDeactivated,
+    LockoutLifted,
+    EmailDomainAllowed,
+    EmailDomainBlocked,
+    EmailDomainRuleRemoved,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 20] = [
+        AuditAction::Login,
+        AuditAction::LoginFailed,
+        AuditAction::Logout,
+        AuditAction::PasswordChanged,
+        AuditAction::PasswordReset,
+        AuditAction::TwoFactorEnabled,
+        AuditAction::TwoFactorDisabled,
+        AuditAction::ApiTokenCreated,
+        AuditAction::ApiTokenRevoked,
+        AuditAction::SessionRevoked,
+        AuditAction::NewsletterPublished,
+        AuditAction::SubscriberCreated,
+        AuditAction::SubscriberExported,
+        AuditAction::SubscriberErased,
+        AuditAction::UserInvited,
+        AuditAction::UserDeactivated,
+        AuditAction::LockoutLifted,
+        AuditAction::EmailDomainAllowed,
+        AuditAction::EmailDomainBlocked,
+        AuditAction::EmailDomainRuleRemoved,
+    ];
+
+    pub fn parse(s: &str) -> Result<AuditAction, String> {
+        Self::ALL
+            .into_iter()
+            .find(|action| action.as_str() == s)
+            .ok_or_else(|| format!("{} is not a known action.", s))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::LoginFailed => "login.failed",
+            AuditAction::Logout => "logout",
+            AuditAction::PasswordChanged => "password.changed",
+            AuditAction::PasswordReset => "password.reset",
+            AuditAction::TwoFactorEnabled => "two_factor.enabled",
+            AuditAction::TwoFactorDisabled => "two_factor.disabled",
+            AuditAction::ApiTokenCreated => "api_token.created",
+            AuditAction::ApiTokenRevoked => "api_token.revoked",
+            AuditAction::SessionRevoked => "session.revoked",
+            AuditAction::NewsletterPublished => "newsletter.published",
+            AuditAction::SubscriberCreated => "subscriber.created",
+            AuditAction::SubscriberExported => "subscriber.exported",
+            AuditAction::SubscriberErased => "subscriber.erased",
+            AuditAction::UserInvited => "user.invited",
+            AuditAction::UserDeactivated => "user.deactivated",
+            AuditAction::LockoutLifted => "lockout.lifted",
+            AuditAction::EmailDomainAllowed => "email_domain.allowed",
+            AuditAction::EmailDomainBlocked => "email_domain.blocked",
+            AuditAction::EmailDomainRuleRemoved => "email_domain.rule_removed",
+        }
+    }
+}
+
+impl std::fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Where a request came from, as recorded in the audit log.
+#[derive(Debug, Clone)]
+pub struct AuditContext {
+    ip: String,
+    user_agent: Option<String>,
+}
+
+impl AuditContext {
+    pub fn ip(&self) -> &str {
+        &self.ip
+    }
+
+    pub fn user_agent(&self) -> Option<&str> {
+        self.user_agent.as_deref()
+    }
+}
+
+impl FromRequest for AuditContext {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<AuditContext, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let user_agent = req
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+        ready(Ok(AuditContext {
+            ip: client_ip(req),
+            user_agent,
+        }))
+    }
+}
+
+/// Appends an entry to the audit log.
+/// Pass the transaction making the change, when there is one, so that
+/// the change is not committed without its entry.
+#[tracing::instrument(name = "Record an audit log entry", skip(executor, context))]
+pub async fn record_audit_event<'c>(
+    executor: impl PgExecutor<'c>,
+    context: &AuditContext,
+    actor_id: Option<Uuid>,
+    action: AuditAction,
+    target: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_log (actor_id, action, target, ip, user_agent)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        actor_id,
+        action.as_str(),
+        target,
+        context.ip,
+        context.user_agent
+    )
+    .execute(executor)
+    .await
+    .context("Failed to record an audit log entry.")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AuditAction;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn known_actions_are_parsed_successfully() {
+        for action in AuditAction::ALL {
+            assert_ok_eq!(AuditAction::parse(action.as_str()), action);
+        }
+    }
+
+    #[test]
+    fn unknown_actions_are_rejected() {
+        assert_err!(AuditAction::parse("newsletter"));
+    }
+}
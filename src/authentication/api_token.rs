@@ -0,0 +1,270 @@
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Utc};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::Role;
+
+/// Every token starts with this, so that leaked ones are easy to spot.
+const TOKEN_PREFIX: &str = "z2p_";
+
+/// What an API token may be used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiScope {
+    NewslettersRead,
+    NewslettersWrite,
+    SubscribersRead,
+    SubscribersWrite,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 4] = [
+        ApiScope::NewslettersRead,
+        ApiScope::NewslettersWrite,
+        ApiScope::SubscribersRead,
+        ApiScope::SubscribersWrite,
+    ];
+
+    pub fn parse(s: &str) -> Result<ApiScope, String> {
+        Self::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid scope.", s))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::NewslettersRead => "newsletters:read",
+            ApiScope::NewslettersWrite => "newsletters:write",
+            ApiScope::SubscribersRead => "subscribers:read",
+            ApiScope::SubscribersWrite => "subscribers:write",
+        }
+    }
+
+    /// The least a user's role must be for their tokens to use the scope,
+    /// mirroring what the role allows in the admin area.
+    pub fn minimum_role(&self) -> Role {
+        match self {
+            ApiScope::NewslettersRead => Role::Viewer,
+            ApiScope::NewslettersWrite | ApiScope::SubscribersRead => Role::Editor,
+            ApiScope::SubscribersWrite => Role::Owner,
+        }
+    }
+}
+
+impl std::fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The scopes the token used for the current request grants.
+#[derive(Debug, Clone)]
+pub struct ApiScopes(pub Vec<ApiScope>);
+
+impl ApiScopes {
+    pub fn contains(&self, scope: ApiScope) -> bool {
+        self.0.contains(&scope)
+    }
+}
+
+pub struct ApiToken {
+    pub token_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<ApiScope>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// The user an API token was created by, with what it may do.
+pub struct ApiTokenOwner {
+    pub user_id: Uuid,
+    pub role: Role,
+    pub scopes: ApiScopes,
+}
+
+fn generate_api_token() -> Secret<String> {
+    let secret: String = thread_rng()
+        .sample_iter(Alphanumeric)
+        .map(char::from)
+        .take(40)
+        .collect();
+    Secret::new(format!("{}{}", TOKEN_PREFIX, secret))
+}
+
+fn hash_api_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+fn parse_scopes(scopes: Vec<String>) -> Result<Vec<ApiScope>, anyhow::Error> {
+    scopes
+        .iter()
+        .map(|s| ApiScope::parse(s).map_err(|e| anyhow!(e)))
+        .collect()
+}
+
+/// Stores a new token for `user_id` and returns its id and the token.
+/// This is the only time the token is available in clear.
+#[tracing::instrument(name = "Create an API token", skip(pool))]
+pub async fn create_api_token(
+    user_id: Uuid,
+    name: &str,
+    scopes: &[ApiScope],
+    pool: &PgPool,
+) -> Result<(Uuid, Secret<String>), anyhow::Error> {
+    let token_id = Uuid::new_v4();
+    let token = generate_api_token();
+    let scopes: Vec<String> = scopes.iter().map(|s| s.as_str().to_string()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_tokens (token_id, user_id, name, token_hash, scopes, created_at)
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        token_id,
+        user_id,
+        name,
+        hash_api_token(token.expose_secret()),
+        &scopes
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the API token.")?;
+    Ok((token_id, token))
+}
+
+/// The tokens of `user_id` that have not been revoked.
+#[tracing::instrument(name = "List API tokens", skip(pool))]
+pub async fn list_api_tokens(user_id: Uuid, pool: &PgPool) -> Result<Vec<ApiToken>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT token_id, name, scopes, created_at, last_used_at
+        FROM api_tokens
+        WHERE user_id = $1 AND revoked_at IS NULL
+        ORDER BY created_at
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the API tokens.")?;
+    rows.into_iter()
+        .map(|r| {
+            Ok(ApiToken {
+                token_id: r.token_id,
+                name: r.name,
+                scopes: parse_scopes(r.scopes)?,
+                created_at: r.created_at,
+                last_used_at: r.last_used_at,
+            })
+        })
+        .collect()
+}
+
+/// Returns the name of the revoked token, or `None` if `user_id` has no
+/// such token.
+#[tracing::instrument(name = "Revoke an API token", skip(pool))]
+pub async fn revoke_api_token(
+    user_id: Uuid,
+    token_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET revoked_at = now()
+        WHERE token_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        RETURNING name
+        "#,
+        token_id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to revoke the API token.")?;
+    Ok(row.map(|r| r.name))
+}
+
+/// Looks up who `token` belongs to, recording that it has been used.
+/// Tokens of deactivated users are refused, and scopes the user's role
+/// no longer allows are dropped.
+#[tracing::instrument(name = "Authenticate an API token", skip_all)]
+pub async fn authenticate_api_token(
+    token: &Secret<String>,
+    pool: &PgPool,
+) -> Result<Option<ApiTokenOwner>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_tokens t
+        SET last_used_at = now()
+        FROM users u
+        WHERE
+            t.token_hash = $1 AND
+            t.revoked_at IS NULL AND
+            u.user_id = t.user_id AND
+            u.deactivated_at IS NULL
+        RETURNING t.user_id, t.scopes, u.role
+        "#,
+        hash_api_token(token.expose_secret())
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up the API token.")?;
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let role = Role::parse(row.role).map_err(|e| anyhow!(e))?;
+    let mut scopes = parse_scopes(row.scopes)?;
+    scopes.retain(|scope| role >= scope.minimum_role());
+    Ok(Some(ApiTokenOwner {
+        user_id: row.user_id,
+        role,
+        scopes: ApiScopes(scopes),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_api_token, hash_api_token, ApiScope, TOKEN_PREFIX};
+    use crate::authentication::Role;
+    use claims::{assert_err, assert_ok_eq};
+    use secrecy::ExposeSecret;
+
+    #[test]
+    fn known_scopes_are_parsed_successfully() {
+        for scope in ApiScope::ALL {
+            assert_ok_eq!(ApiScope::parse(scope.as_str()), scope);
+        }
+    }
+
+    #[test]
+    fn unknown_scopes_are_rejected() {
+        assert_err!(ApiScope::parse("newsletters"));
+        assert_err!(ApiScope::parse("admin:write"));
+    }
+
+    #[test]
+    fn viewers_can_only_read_newsletters() {
+        let allowed: Vec<_> = ApiScope::ALL
+            .into_iter()
+            .filter(|s| Role::Viewer >= s.minimum_role())
+            .collect();
+        assert_eq!(allowed, vec![ApiScope::NewslettersRead]);
+    }
+
+    #[test]
+    fn generated_tokens_are_prefixed_and_distinct() {
+        let a = generate_api_token();
+        let b = generate_api_token();
+        assert!(a.expose_secret().starts_with(TOKEN_PREFIX));
+        assert_ne!(a.expose_secret(), b.expose_secret());
+        assert_ne!(
+            hash_api_token(a.expose_secret()),
+            hash_api_token(b.expose_secret())
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::ContentType;
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse};
+use actix_web_lab::middleware::Next;
+use anyhow::anyhow;
+use futures_util::stream;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+
+const CSRF_FIELD: &str = "csrf_token";
+
+/// A secret tied to the session, which forms send back to show that they
+/// were rendered by us rather than by another site.
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    pub fn generate() -> Self {
+        let token = thread_rng()
+            .sample_iter(Alphanumeric)
+            .map(char::from)
+            .take(32)
+            .collect();
+        Self(token)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The hidden input every state-changing form must include.
+    pub fn form_field(&self) -> String {
+        format!(
+            r#"<input type="hidden" name="{}" value="{}">"#,
+            CSRF_FIELD, self.0
+        )
+    }
+}
+
+/// The token of the current session, created on first use.
+impl FromRequest for CsrfToken {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<CsrfToken, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let session = TypedSession::from_request(req, payload);
+        Box::pin(async move {
+            let session = session.await?;
+            if let Some(token) = session.get_csrf_token().map_err(e500)? {
+                return Ok(CsrfToken(token));
+            }
+            let token = CsrfToken::generate();
+            session.insert_csrf_token(token.as_str()).map_err(e500)?;
+            Ok(token)
+        })
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct CsrfForm {
+    csrf_token: Option<String>,
+}
+
+/// Rejects form submissions that do not carry the session's CSRF token.
+/// Requests that cannot change anything, such as `GET`s, are let through.
+pub async fn reject_forged_forms(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if req.method().is_safe() {
+        return next.call(req).await;
+    }
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+    let expected = session.get_csrf_token().map_err(e500)?;
+
+    // The handler still needs the body: put it back once we have read it.
+    let body = req.extract::<web::Bytes>().await?;
+    let submitted = serde_html_form::from_bytes::<CsrfForm>(&body)
+        .ok()
+        .and_then(|f| f.csrf_token);
+    let bytes = body.clone();
+    let payload: Pin<Box<dyn futures_util::Stream<Item = _>>> =
+        Box::pin(stream::once(async move { Ok(bytes) }));
+    req.set_payload(Payload::from(payload));
+
+    match (expected, submitted) {
+        (Some(expected), Some(submitted)) if tokens_match(&expected, &submitted) => {
+            next.call(req).await
+        }
+        _ => {
+            let response = HttpResponse::Forbidden()
+                .content_type(ContentType::plaintext())
+                .body(
+                    "This form has expired or was not sent from this site. \
+                    Go back, reload the page and try again.",
+                );
+            let e = anyhow!("The request did not carry the session's CSRF token");
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
+
+/// Comparing digests keeps the time taken from telling how much of the
+/// token was right.
+fn tokens_match(expected: &str, submitted: &str) -> bool {
+    Sha256::digest(expected.as_bytes()) == Sha256::digest(submitted.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tokens_match, CsrfToken};
+
+    #[test]
+    fn generated_tokens_are_distinct() {
+        assert_ne!(
+            CsrfToken::generate().as_str(),
+            CsrfToken::generate().as_str()
+        );
+    }
+
+    #[test]
+    fn only_identical_tokens_match() {
+        let token = CsrfToken::generate();
+        assert!(tokens_match(token.as_str(), token.as_str()));
+        assert!(!tokens_match(token.as_str(), &token.as_str()[1..]));
+        assert!(!tokens_match(token.as_str(), ""));
+    }
+
+    #[test]
+    fn the_form_field_carries_the_token() {
+        let token = CsrfToken::generate();
+        assert!(token.form_field().contains(token.as_str()));
+    }
+}
@@ -0,0 +1,102 @@
+use anyhow::anyhow;
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use serde_aux::field_attributes::deserialize_number_from_string;
+use uuid::Uuid;
+
+/// How long a setup link stays valid after it has been sent.
+pub const INVITATION_VALIDITY_HOURS: i64 = 48;
+
+/// The parameters of an invitation link.
+/// The signature covers the user and the expiry, so neither can be
+/// tampered with; the link stops working once the user has been set up.
+#[derive(serde::Deserialize, Debug)]
+pub struct SignedInvitation {
+    pub user_id: Uuid,
+    // Form fields arrive as strings, even when flattened.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub expires_at: i64,
+    signature: String,
+}
+
+impl SignedInvitation {
+    pub fn new(user_id: Uuid, secret: &Secret<String>) -> Self {
+        let expires_at = (Utc::now() + Duration::hours(INVITATION_VALIDITY_HOURS)).timestamp();
+        let signature = hex::encode(
+            Self::mac(user_id, expires_at, secret)
+                .finalize()
+                .into_bytes(),
+        );
+        Self {
+            user_id,
+            expires_at,
+            signature,
+        }
+    }
+
+    pub fn verify(&self, secret: &Secret<String>) -> Result<(), anyhow::Error> {
+        let signature = hex::decode(&self.signature)?;
+        Self::mac(self.user_id, self.expires_at, secret).verify_slice(&signature)?;
+        if self.expires_at < Utc::now().timestamp() {
+            return Err(anyhow!("The invitation has expired."));
+        }
+        Ok(())
+    }
+
+    pub fn signature(&self) -> &str {
+        &self.signature
+    }
+
+    pub fn query_string(&self) -> String {
+        format!(
+            "user_id={}&expires_at={}&signature={}",
+            self.user_id, self.expires_at, self.signature
+        )
+    }
+
+    fn mac(user_id: Uuid, expires_at: i64, secret: &Secret<String>) -> Hmac<sha2::Sha256> {
+        let mut mac =
+            Hmac::<sha2::Sha256>::new_from_slice(secret.expose_secret().as_bytes()).unwrap();
+        mac.update(format!("{user_id}:{expires_at}").as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SignedInvitation;
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-very-long-and-secret-key".to_string())
+    }
+
+    #[test]
+    fn a_freshly_signed_invitation_is_valid() {
+        let invitation = SignedInvitation::new(Uuid::new_v4(), &secret());
+        assert_ok!(invitation.verify(&secret()));
+    }
+
+    #[test]
+    fn an_invitation_for_another_user_is_rejected() {
+        let mut invitation = SignedInvitation::new(Uuid::new_v4(), &secret());
+        invitation.user_id = Uuid::new_v4();
+        assert_err!(invitation.verify(&secret()));
+    }
+
+    #[test]
+    fn an_invitation_with_a_pushed_back_expiry_is_rejected() {
+        let mut invitation = SignedInvitation::new(Uuid::new_v4(), &secret());
+        invitation.expires_at += 1;
+        assert_err!(invitation.verify(&secret()));
+    }
+
+    #[test]
+    fn an_invitation_signed_with_another_key_is_rejected() {
+        let invitation = SignedInvitation::new(Uuid::new_v4(), &secret());
+        assert_err!(invitation.verify(&Secret::new("another-key".to_string())));
+    }
+}
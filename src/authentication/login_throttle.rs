@@ -0,0 +1,279 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use secrecy::{ExposeSecret, Secret};
+
+use crate::configuration::LoginThrottleSettings;
+
+/// Failures below this count are answered right away; every failure
+/// after that doubles the time we wait before checking the password.
+const FREE_FAILURES: u64 = 2;
+const BASE_DELAY: Duration = Duration::from_millis(250);
+const MAX_DELAY: Duration = Duration::from_secs(4);
+
+/// Tracks failed logins in Redis, per username and per client IP,
+/// and locks either out for a while once they fail too often.
+///
+/// Usernames are tracked whether or not they exist, so that lockouts
+/// do not reveal which accounts are real.
+#[derive(Clone)]
+pub struct LoginThrottle {
+    connection: ConnectionManager,
+    settings: LoginThrottleSettings,
+}
+
+/// What a lockout applies to, as listed on the admin page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockoutSubject {
+    Username(String),
+    Ip(String),
+}
+
+impl LockoutSubject {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.split_once(':') {
+            Some(("username", username)) => Ok(Self::Username(username.to_string())),
+            Some(("ip", ip)) => Ok(Self::Ip(ip.to_string())),
+            _ => Err(format!("{} is not a valid lockout.", s)),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Username(_) => "username",
+            Self::Ip(_) => "ip",
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        match self {
+            Self::Username(v) | Self::Ip(v) => v,
+        }
+    }
+}
+
+impl std::fmt::Display for LockoutSubject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.kind(), self.value())
+    }
+}
+
+pub struct Lockout {
+    pub subject: LockoutSubject,
+    pub remaining: Duration,
+}
+
+impl LoginThrottle {
+    pub async fn new(
+        redis_uri: &Secret<String>,
+        settings: LoginThrottleSettings,
+    ) -> Result<Self, anyhow::Error> {
+        let client = redis::Client::open(redis_uri.expose_secret().as_str())
+            .context("Failed to parse the Redis URI")?;
+        let connection = client
+            .get_tokio_connection_manager()
+            .await
+            .context("Failed to connect to Redis")?;
+        Ok(Self {
+            connection,
+            settings,
+        })
+    }
+
+    fn key(&self, kind: &str, subject: &LockoutSubject) -> String {
+        format!("{}:{}:{}", self.settings.key_prefix, kind, subject)
+    }
+
+    fn locked_set_key(&self) -> String {
+        format!("{}:locked", self.settings.key_prefix)
+    }
+
+    fn subjects(username: &str, ip: &str) -> [LockoutSubject; 2] {
+        [
+            LockoutSubject::Username(username.to_string()),
+            LockoutSubject::Ip(ip.to_string()),
+        ]
+    }
+
+    /// How long until a login for `username` from `ip` is allowed again,
+    /// if either of them is locked out.
+    #[tracing::instrument(name = "Check login lockout", skip(self))]
+    pub async fn lockout_remaining(
+        &self,
+        username: &str,
+        ip: &str,
+    ) -> Result<Option<Duration>, anyhow::Error> {
+        let mut connection = self.connection.clone();
+        let mut remaining = None;
+        for subject in Self::subjects(username, ip) {
+            let ttl: i64 = connection
+                .ttl(self.key("lockout", &subject))
+                .await
+                .context("Failed to check the lockout")?;
+            if ttl > 0 {
+                remaining = remaining.max(Some(Duration::from_secs(ttl as u64)));
+            }
+        }
+        Ok(remaining)
+    }
+
+    /// How long to wait before checking the password, given how many
+    /// attempts have recently failed for `username` or from `ip`.
+    #[tracing::instrument(name = "Compute login delay", skip(self))]
+    pub async fn delay(&self, username: &str, ip: &str) -> Result<Duration, anyhow::Error> {
+        let mut connection = self.connection.clone();
+        let mut failures = 0;
+        for subject in Self::subjects(username, ip) {
+            let count: Option<u64> = connection
+                .get(self.key("failures", &subject))
+                .await
+                .context("Failed to read the failure count")?;
+            failures = failures.max(count.unwrap_or(0));
+        }
+        Ok(progressive_delay(failures))
+    }
+
+    #[tracing::instrument(name = "Record failed login", skip(self))]
+    pub async fn record_failure(&self, username: &str, ip: &str) -> Result<(), anyhow::Error> {
+        let mut connection = self.connection.clone();
+        let [username, ip] = Self::subjects(username, ip);
+        for (subject, max_failures) in [
+            (username, self.settings.max_failures_per_username),
+            (ip, self.settings.max_failures_per_ip),
+        ] {
+            let failures_key = self.key("failures", &subject);
+            let failures: u64 = connection
+                .incr(&failures_key, 1)
+                .await
+                .context("Failed to count the failed login")?;
+            if failures == 1 {
+                connection
+                    .expire::<_, ()>(&failures_key, self.settings.lockout_seconds)
+                    .await
+                    .context("Failed to set the failure window")?;
+            }
+            if failures >= max_failures {
+                tracing::warn!(%subject, "Locking out after too many failed logins");
+                connection
+                    .set_ex::<_, _, ()>(
+                        self.key("lockout", &subject),
+                        1,
+                        self.settings.lockout_seconds,
+                    )
+                    .await
+                    .context("Failed to store the lockout")?;
+                connection
+                    .del::<_, ()>(&failures_key)
+                    .await
+                    .context("Failed to reset the failure count")?;
+                connection
+                    .sadd::<_, _, ()>(self.locked_set_key(), subject.to_string())
+                    .await
+                    .context("Failed to list the lockout")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Forgets earlier failures for `username`. The client IP keeps its
+    /// count, otherwise one valid account would let it guess the others.
+    #[tracing::instrument(name = "Record successful login", skip(self))]
+    pub async fn record_success(&self, username: &str) -> Result<(), anyhow::Error> {
+        let mut connection = self.connection.clone();
+        let subject = LockoutSubject::Username(username.to_string());
+        connection
+            .del::<_, ()>(self.key("failures", &subject))
+            .await
+            .context("Failed to reset the failure count")?;
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "List lockouts", skip(self))]
+    pub async fn lockouts(&self) -> Result<Vec<Lockout>, anyhow::Error> {
+        let mut connection = self.connection.clone();
+        let members: Vec<String> = connection
+            .smembers(self.locked_set_key())
+            .await
+            .context("Failed to list the lockouts")?;
+        let mut lockouts = vec![];
+        for member in members {
+            let subject = match LockoutSubject::parse(&member) {
+                Ok(subject) => subject,
+                Err(_) => continue,
+            };
+            let ttl: i64 = connection
+                .ttl(self.key("lockout", &subject))
+                .await
+                .context("Failed to check the lockout")?;
+            if ttl > 0 {
+                lockouts.push(Lockout {
+                    subject,
+                    remaining: Duration::from_secs(ttl as u64),
+                });
+            } else {
+                // The lockout has expired on its own.
+                connection
+                    .srem::<_, _, ()>(self.locked_set_key(), &member)
+                    .await
+                    .context("Failed to forget an expired lockout")?;
+            }
+        }
+        Ok(lockouts)
+    }
+
+    #[tracing::instrument(name = "Lift lockout", skip(self))]
+    pub async fn unlock(&self, subject: &LockoutSubject) -> Result<(), anyhow::Error> {
+        let mut connection = self.connection.clone();
+        connection
+            .del::<_, ()>(&[self.key("lockout", subject), self.key("failures", subject)])
+            .await
+            .context("Failed to lift the lockout")?;
+        connection
+            .srem::<_, _, ()>(self.locked_set_key(), subject.to_string())
+            .await
+            .context("Failed to forget the lockout")?;
+        Ok(())
+    }
+}
+
+fn progressive_delay(failures: u64) -> Duration {
+    if failures <= FREE_FAILURES {
+        return Duration::ZERO;
+    }
+    let doublings = (failures - FREE_FAILURES - 1).min(16) as u32;
+    (BASE_DELAY * 2u32.pow(doublings)).min(MAX_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{progressive_delay, LockoutSubject};
+    use claims::{assert_err, assert_ok_eq};
+    use std::time::Duration;
+
+    #[test]
+    fn the_first_failures_are_not_delayed() {
+        assert_eq!(progressive_delay(0), Duration::ZERO);
+        assert_eq!(progressive_delay(2), Duration::ZERO);
+    }
+
+    #[test]
+    fn the_delay_doubles_up_to_a_cap() {
+        assert_eq!(progressive_delay(3), Duration::from_millis(250));
+        assert_eq!(progressive_delay(4), Duration::from_millis(500));
+        assert_eq!(progressive_delay(5), Duration::from_secs(1));
+        assert_eq!(progressive_delay(100), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn lockout_subjects_round_trip() {
+        for subject in [
+            LockoutSubject::Username("ursula:le-guin".to_string()),
+            LockoutSubject::Ip("::1".to_string()),
+        ] {
+            assert_ok_eq!(LockoutSubject::parse(&subject.to_string()), subject);
+        }
+        assert_err!(LockoutSubject::parse("email:ursula@example.com"));
+    }
+}
@@ -1,16 +1,23 @@
+use actix_web::http::header::AUTHORIZATION;
 use actix_web::{
     body::MessageBody,
     dev::{ServiceRequest, ServiceResponse},
     error::InternalError,
     FromRequest, HttpMessage,
 };
+use actix_web::{web, HttpResponse};
 use actix_web_lab::middleware::Next;
 use anyhow::anyhow;
+use secrecy::Secret;
+use sqlx::PgPool;
 use std::ops::Deref;
 use uuid::Uuid;
 
+use super::{authenticate_api_token, touch_session, Role};
 use crate::{
-    session_state::TypedSession,
+    configuration::SessionSettings,
+    routes::{ApiError, LoginError},
+    session_state::{RememberedUntil, TypedSession},
     utils::{e500, see_other},
 };
 
@@ -31,6 +38,24 @@ impl Deref for UserId {
     }
 }
 
+/// The `user_sessions` row of the current login.
+#[derive(Copy, Clone, Debug)]
+pub struct SessionId(Uuid);
+
+impl std::fmt::Display for SessionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Deref for SessionId {
+    type Target = Uuid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 pub async fn reject_anonymous_users(
     mut req: ServiceRequest,
     next: Next<impl MessageBody>,
@@ -40,15 +65,125 @@ pub async fn reject_anonymous_users(
         TypedSession::from_request(http_request, payload).await
     }?;
 
-    match session.get_user_id().map_err(e500)? {
-        Some(user_id) => {
+    let user_id = match session.get_user_id().map_err(e500)? {
+        Some(user_id) => user_id,
+        None => {
+            let response = see_other("/login");
+            let e = anyhow!("The user has not logged in");
+            return Err(InternalError::from_response(e, response).into());
+        }
+    };
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .expect("The connection pool is not registered")
+        .clone();
+    // Sessions started before they were tracked have no id, and are
+    // treated like revoked ones.
+    let settings = req
+        .app_data::<web::Data<SessionSettings>>()
+        .expect("The session settings are not registered")
+        .clone();
+    let active = match session.get_session_id().map_err(e500)? {
+        Some(session_id) => touch_session(user_id, session_id, &settings, &pool)
+            .await
+            .map_err(e500)?
+            .map(|role| (session_id, role)),
+        None => None,
+    };
+    match active {
+        Some((session_id, role)) => {
             req.extensions_mut().insert(UserId(user_id));
-            next.call(req).await
+            req.extensions_mut().insert(SessionId(session_id));
+            req.extensions_mut().insert(role);
+            let remembered_until = session.get_remembered_until().map_err(e500)?;
+            let mut res = next.call(req).await?;
+            if let Some(until) = remembered_until {
+                res.response_mut()
+                    .extensions_mut()
+                    .insert(RememberedUntil(until));
+            }
+            Ok(res)
         }
         None => {
+            session.logout();
             let response = see_other("/login");
-            let e = anyhow!("The user has not logged in");
+            let e = anyhow!("The user has been deactivated or the session revoked");
             Err(InternalError::from_response(e, response).into())
         }
     }
 }
+
+/// The bearer token counterpart of `reject_anonymous_users`, for routes
+/// called by scripts rather than browsers.
+/// Also makes the token's `ApiScopes` available to handlers.
+pub async fn reject_invalid_api_tokens(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let token = match bearer_token(&req) {
+        Some(token) => token,
+        None => return Err(unauthorized(anyhow!("No bearer token was provided"))),
+    };
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .expect("The connection pool is not registered")
+        .clone();
+    match authenticate_api_token(&token, &pool).await.map_err(e500)? {
+        Some(owner) => {
+            req.extensions_mut().insert(UserId(owner.user_id));
+            req.extensions_mut().insert(owner.role);
+            req.extensions_mut().insert(owner.scopes);
+            next.call(req).await
+        }
+        None => Err(unauthorized(anyhow!("Unknown or revoked API token"))),
+    }
+}
+
+fn bearer_token(req: &ServiceRequest) -> Option<Secret<String>> {
+    let header = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let token = header.strip_prefix("Bearer ")?.trim();
+    if token.is_empty() {
+        return None;
+    }
+    Some(Secret::new(token.to_string()))
+}
+
+fn unauthorized(e: anyhow::Error) -> actix_web::Error {
+    tracing::warn!(error.cause_chain = ?e, "Rejected an API request");
+    ApiError::from(LoginError::AuthError(e)).into()
+}
+
+/// Only lets through users whose role is at least `minimum`.
+/// Must run after `reject_anonymous_users`.
+pub async fn require_role(
+    minimum: Role,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let role = req
+        .extensions()
+        .get::<Role>()
+        .copied()
+        .ok_or_else(|| e500("The user's role has not been resolved"))?;
+    if role >= minimum {
+        next.call(req).await
+    } else {
+        let response = HttpResponse::Forbidden().body("You are not allowed to do this.");
+        let e = anyhow!("A {} tried to do something reserved to {}s", role, minimum);
+        Err(InternalError::from_response(e, response).into())
+    }
+}
+
+pub async fn require_editor(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    require_role(Role::Editor, req, next).await
+}
+
+pub async fn require_owner(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    require_role(Role::Owner, req, next).await
+}
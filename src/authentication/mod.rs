@@ -1,6 +1,36 @@
+mod api_token;
+mod csrf;
+mod invitation;
+mod login_throttle;
 mod middleware;
 mod password;
+mod role;
+mod seed_user;
+mod two_factor;
+mod user_session;
 
-pub use password::{change_password, validate_credentials, AuthError, Credentials};
+pub use api_token::{
+    authenticate_api_token, create_api_token, list_api_tokens, revoke_api_token, ApiScope,
+    ApiScopes, ApiToken, ApiTokenOwner,
+};
+pub use csrf::{reject_forged_forms, CsrfToken};
+pub use invitation::{SignedInvitation, INVITATION_VALIDITY_HOURS};
+pub use login_throttle::{Lockout, LockoutSubject, LoginThrottle};
+pub use password::{
+    change_password, compute_password_hash, validate_credentials, AuthError, Credentials,
+};
+pub use role::Role;
+pub use seed_user::set_seed_user_email;
+pub use two_factor::{
+    disable_two_factor, enable_two_factor, generate_totp_secret, provisioning_qr_code, totp,
+    two_factor_enabled, verify_second_factor, verify_totp_code,
+};
+pub use user_session::{
+    list_sessions, revoke_other_sessions, revoke_session, revoke_sessions, start_session,
+    touch_session, UserSession,
+};
 
-pub use middleware::{reject_anonymous_users, UserId};
+pub use middleware::{
+    reject_anonymous_users, reject_invalid_api_tokens, require_editor, require_owner, SessionId,
+    UserId,
+};
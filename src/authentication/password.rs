@@ -81,9 +81,12 @@ async fn get_stored_credentials(
 ) -> Result<Option<(uuid::Uuid, Secret<String>)>, anyhow::Error> {
     let row: Option<_> = sqlx::query!(
         r#"
-        SELECT user_id, password_hash
+        SELECT user_id, password_hash as "password_hash!"
         FROM users
-        WHERE username = $1
+        WHERE
+            username = $1 AND
+            password_hash IS NOT NULL AND
+            deactivated_at IS NULL
     "#,
         username,
     )
@@ -119,7 +122,7 @@ pub async fn change_password(
     Ok(())
 }
 
-fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+pub fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
     let salt = SaltString::generate(&mut rand::thread_rng());
     let password_hash = Argon2::new(
         Algorithm::Argon2id,
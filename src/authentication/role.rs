@@ -0,0 +1,64 @@
+/// What an admin user is allowed to do.
+/// Roles are ordered: every role can do whatever the ones before it can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    /// Can look around the admin area but not change anything.
+    Viewer,
+    /// Can publish newsletters, manage lists and export subscribers.
+    Editor,
+    /// Can also manage admin users and erase subscriber data.
+    Owner,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Viewer, Role::Editor, Role::Owner];
+
+    pub fn parse(s: String) -> Result<Role, String> {
+        match s.as_str() {
+            "viewer" => Ok(Role::Viewer),
+            "editor" => Ok(Role::Editor),
+            "owner" => Ok(Role::Owner),
+            _ => Err(format!("{} is not a valid role.", s)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Role;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn known_roles_are_parsed_successfully() {
+        for role in Role::ALL {
+            assert_ok_eq!(Role::parse(role.as_str().to_string()), role);
+        }
+    }
+
+    #[test]
+    fn unknown_roles_are_rejected() {
+        assert_err!(Role::parse("admin".to_string()));
+        assert_err!(Role::parse("Owner".to_string()));
+    }
+
+    #[test]
+    fn owners_can_do_whatever_editors_can() {
+        assert!(Role::Owner >= Role::Editor);
+        assert!(Role::Editor >= Role::Viewer);
+        assert!(Role::Viewer < Role::Editor);
+    }
+}
@@ -0,0 +1,27 @@
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+
+/// The `admin` user created by the first migrations, who has no email
+/// address unless one is configured.
+const SEED_USER_ID: Uuid = uuid::uuid!("81d7c75a-5d96-4576-b3e5-1e108d924159");
+
+/// Gives the seeded `admin` user `email`, so that it can reset its
+/// password like any other user.
+#[tracing::instrument(name = "Set the seeded user's email", skip(pool))]
+pub async fn set_seed_user_email(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"UPDATE users SET email = $1 WHERE user_id = $2"#,
+        email.as_ref(),
+        SEED_USER_ID
+    )
+    .execute(pool)
+    .await
+    .context("Failed to set the seeded user's email address.")?;
+    Ok(())
+}
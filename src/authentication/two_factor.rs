@@ -0,0 +1,287 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use totp_rs::{Algorithm, TOTP};
+use uuid::Uuid;
+
+const ISSUER: &str = "zero2prod";
+const RECOVERY_CODES: usize = 10;
+
+/// A fresh, base32-encoded TOTP secret.
+pub fn generate_totp_secret() -> Secret<String> {
+    match totp_rs::Secret::generate_secret().to_encoded() {
+        totp_rs::Secret::Encoded(secret) => Secret::new(secret),
+        totp_rs::Secret::Raw(_) => unreachable!(),
+    }
+}
+
+/// The RFC 6238 parameters every authenticator app understands:
+/// SHA-1, six digits, 30 second steps.
+pub fn totp(secret: &Secret<String>, username: &str) -> Result<TOTP, anyhow::Error> {
+    let secret = totp_rs::Secret::Encoded(secret.expose_secret().clone())
+        .to_bytes()
+        .map_err(|e| anyhow::anyhow!("Invalid TOTP secret: {:?}", e))?;
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        0,
+        30,
+        secret,
+        Some(ISSUER.to_string()),
+        username.replace(':', "_"),
+    )
+    .context("Failed to build a TOTP generator")
+}
+
+/// Returns the time step `code` belongs to if it is valid at `now`,
+/// allowing for one step of clock drift either way.
+pub fn verify_totp_code(totp: &TOTP, code: &str, now: u64) -> Option<i64> {
+    let current_step = now / totp.step;
+    [
+        current_step.saturating_sub(1),
+        current_step,
+        current_step + 1,
+    ]
+    .into_iter()
+    .find(|step| totp.check(code.trim(), step * totp.step))
+    .map(|step| step as i64)
+}
+
+/// The provisioning URI as a QR code, ready to be inlined in a page.
+pub fn provisioning_qr_code(totp: &TOTP) -> Result<String, anyhow::Error> {
+    let code = qrcode::QrCode::new(totp.get_url()).context("Failed to render the QR code")?;
+    Ok(code
+        .render::<qrcode::render::svg::Color>()
+        .min_dimensions(200, 200)
+        .build())
+}
+
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = thread_rng();
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            let code: String = (&mut rng)
+                .sample_iter(Alphanumeric)
+                .map(|c| char::from(c).to_ascii_lowercase())
+                .take(10)
+                .collect();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+fn hash_recovery_code(code: &str) -> String {
+    hex::encode(Sha256::digest(code.trim().to_lowercase().as_bytes()))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("The clock is set before 1970")
+        .as_secs()
+}
+
+#[tracing::instrument(
+    name = "Check whether two-factor authentication is enabled",
+    skip(pool)
+)]
+pub async fn two_factor_enabled(user_id: Uuid, pool: &PgPool) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT totp_secret IS NOT NULL as "enabled!" FROM users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to check whether two-factor authentication is enabled.")?;
+    Ok(row.enabled)
+}
+
+/// Checks a TOTP code or, failing that, an unused recovery code.
+/// Both are single-use: accepted codes cannot be presented again.
+#[tracing::instrument(name = "Verify second factor", skip(code, pool))]
+pub async fn verify_second_factor(
+    user_id: Uuid,
+    code: &str,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT username, totp_secret, totp_last_used_step FROM users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve the TOTP secret.")?;
+    let secret = match row.totp_secret {
+        Some(secret) => Secret::new(secret),
+        None => return Ok(false),
+    };
+
+    let totp = totp(&secret, &row.username.unwrap_or_default())?;
+    if let Some(step) = verify_totp_code(&totp, code, unix_now()) {
+        let n_updated_rows = sqlx::query!(
+            r#"
+            UPDATE users
+            SET totp_last_used_step = $2
+            WHERE
+                user_id = $1 AND
+                (totp_last_used_step IS NULL OR totp_last_used_step < $2)
+            "#,
+            user_id,
+            step
+        )
+        .execute(pool)
+        .await
+        .context("Failed to record the TOTP step.")?
+        .rows_affected();
+        return Ok(n_updated_rows == 1);
+    }
+
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE recovery_codes
+        SET used_at = now()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+        user_id,
+        hash_recovery_code(code)
+    )
+    .execute(pool)
+    .await
+    .context("Failed to use a recovery code.")?
+    .rows_affected();
+    Ok(n_updated_rows == 1)
+}
+
+/// Stores the secret and returns a fresh set of recovery codes,
+/// which are only ever shown once.
+#[tracing::instrument(name = "Enable two-factor authentication", skip(secret, pool))]
+pub async fn enable_two_factor(
+    user_id: Uuid,
+    secret: &Secret<String>,
+    step: i64,
+    pool: &PgPool,
+) -> Result<Vec<String>, anyhow::Error> {
+    let recovery_codes = generate_recovery_codes();
+    let code_hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|c| hash_recovery_code(c))
+        .collect();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a postgres connection from the pool")?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = $2, totp_last_used_step = $3
+        WHERE user_id = $1
+        "#,
+        user_id,
+        secret.expose_secret(),
+        step
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the TOTP secret.")?;
+    sqlx::query!(r#"DELETE FROM recovery_codes WHERE user_id = $1"#, user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to discard the old recovery codes.")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO recovery_codes (user_id, code_hash)
+        SELECT $1, * FROM UNNEST($2::text[])
+        "#,
+        user_id,
+        &code_hashes
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the recovery codes.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to enable two-factor authentication.")?;
+    Ok(recovery_codes)
+}
+
+#[tracing::instrument(name = "Disable two-factor authentication", skip(pool))]
+pub async fn disable_two_factor(user_id: Uuid, pool: &PgPool) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a postgres connection from the pool")?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = NULL, totp_last_used_step = NULL
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to remove the TOTP secret.")?;
+    sqlx::query!(r#"DELETE FROM recovery_codes WHERE user_id = $1"#, user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to discard the recovery codes.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to disable two-factor authentication.")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_recovery_codes, hash_recovery_code, totp, verify_totp_code};
+    use claims::{assert_none, assert_some_eq};
+    use secrecy::Secret;
+    use std::collections::HashSet;
+
+    fn rfc_6238_totp() -> totp_rs::TOTP {
+        // The SHA-1 seed from the RFC 6238 test vectors, "12345678901234567890".
+        let secret = Secret::new("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ".to_string());
+        totp(&secret, "admin").unwrap()
+    }
+
+    #[test]
+    fn rfc_6238_test_vectors_are_accepted() {
+        let totp = rfc_6238_totp();
+        // Six digit truncations of the vectors in appendix B.
+        assert_some_eq!(verify_totp_code(&totp, "287082", 59), 1);
+        assert_some_eq!(verify_totp_code(&totp, "081804", 1111111109), 37037036);
+    }
+
+    #[test]
+    fn codes_from_the_neighbouring_steps_are_accepted() {
+        let totp = rfc_6238_totp();
+        assert_some_eq!(verify_totp_code(&totp, "287082", 89), 1);
+        assert_some_eq!(verify_totp_code(&totp, "287082", 30), 1);
+    }
+
+    #[test]
+    fn stale_and_malformed_codes_are_rejected() {
+        let totp = rfc_6238_totp();
+        assert_none!(verify_totp_code(&totp, "287082", 120));
+        assert_none!(verify_totp_code(&totp, "not-a-code", 59));
+        assert_none!(verify_totp_code(&totp, "", 59));
+    }
+
+    #[test]
+    fn recovery_codes_are_unique_and_normalised_before_hashing() {
+        let codes = generate_recovery_codes();
+        let unique: HashSet<_> = codes.iter().collect();
+        assert_eq!(codes.len(), unique.len());
+        assert_eq!(
+            hash_recovery_code(&codes[0]),
+            hash_recovery_code(&format!(" {} ", codes[0].to_uppercase()))
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::Role;
+use crate::audit::AuditContext;
+use crate::configuration::SessionSettings;
+
+/// A login, as shown on the sessions page.
+pub struct UserSession {
+    pub session_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub ip: String,
+    pub user_agent: Option<String>,
+}
+
+/// Records a new login for `user_id`, made from where `context` says.
+/// Returns the id of the session and when it expires.
+#[tracing::instrument(name = "Start a user session", skip(context, settings, pool))]
+pub async fn start_session(
+    user_id: Uuid,
+    remember_me: bool,
+    context: &AuditContext,
+    settings: &SessionSettings,
+    pool: &PgPool,
+) -> Result<(Uuid, DateTime<Utc>), anyhow::Error> {
+    let session_id = Uuid::new_v4();
+    let ttl = if remember_me {
+        settings.remember_me_ttl_seconds
+    } else {
+        settings.ttl_seconds
+    };
+    let expires_at = Utc::now() + Duration::seconds(ttl);
+    sqlx::query!(
+        r#"
+        INSERT INTO user_sessions
+            (session_id, user_id, created_at, last_seen_at, expires_at, ip, user_agent)
+        VALUES ($1, $2, now(), now(), $3, $4, $5)
+        "#,
+        session_id,
+        user_id,
+        expires_at,
+        context.ip(),
+        context.user_agent()
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the user session.")?;
+    Ok((session_id, expires_at))
+}
+
+/// Sessions last seen before this are idle for too long to be used.
+fn idle_cutoff(settings: &SessionSettings) -> DateTime<Utc> {
+    Utc::now() - Duration::seconds(settings.idle_timeout_seconds)
+}
+
+/// The role of the user, unless they have been deactivated or the
+/// session has been revoked, has expired or has been idle for too long.
+/// Records that the session has been used, which keeps it from idling out.
+#[tracing::instrument(name = "Get the role of an active user", skip(settings, pool))]
+pub async fn touch_session(
+    user_id: Uuid,
+    session_id: Uuid,
+    settings: &SessionSettings,
+    pool: &PgPool,
+) -> Result<Option<Role>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE user_sessions s
+        SET last_seen_at = now()
+        FROM users u
+        WHERE
+            s.session_id = $1 AND
+            s.user_id = $2 AND
+            s.revoked_at IS NULL AND
+            s.expires_at > now() AND
+            s.last_seen_at > $3 AND
+            u.user_id = s.user_id AND
+            u.deactivated_at IS NULL
+        RETURNING u.role
+        "#,
+        session_id,
+        user_id,
+        idle_cutoff(settings)
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the user's role.")?;
+    row.map(|r| Role::parse(r.role).map_err(|e| anyhow!(e)))
+        .transpose()
+}
+
+/// The sessions of `user_id` that are still usable, most recently used first.
+#[tracing::instrument(name = "List user sessions", skip(settings, pool))]
+pub async fn list_sessions(
+    user_id: Uuid,
+    settings: &SessionSettings,
+    pool: &PgPool,
+) -> Result<Vec<UserSession>, anyhow::Error> {
+    sqlx::query_as!(
+        UserSession,
+        r#"
+        SELECT session_id, created_at, last_seen_at, expires_at, ip, user_agent
+        FROM user_sessions
+        WHERE
+            user_id = $1 AND
+            revoked_at IS NULL AND
+            expires_at > now() AND
+            last_seen_at > $2
+        ORDER BY last_seen_at DESC
+        "#,
+        user_id,
+        idle_cutoff(settings)
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the user sessions.")
+}
+
+/// Returns `false` if `user_id` has no such active session.
+#[tracing::instrument(name = "Revoke a user session", skip(pool))]
+pub async fn revoke_session(
+    user_id: Uuid,
+    session_id: Uuid,
+    pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET revoked_at = now()
+        WHERE session_id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        session_id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to revoke the user session.")?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Invalidates every session of `user_id` but `current`, returning how
+/// many were still active.
+#[tracing::instrument(name = "Revoke other user sessions", skip(pool))]
+pub async fn revoke_other_sessions(
+    user_id: Uuid,
+    current: Uuid,
+    pool: &PgPool,
+) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET revoked_at = now()
+        WHERE user_id = $1 AND session_id <> $2 AND revoked_at IS NULL
+        "#,
+        user_id,
+        current
+    )
+    .execute(pool)
+    .await
+    .context("Failed to revoke the other user sessions.")?;
+    Ok(result.rows_affected())
+}
+
+/// Invalidates every session the user has started so far.
+#[tracing::instrument(name = "Revoke sessions", skip(pool))]
+pub async fn revoke_sessions(user_id: Uuid, pool: &PgPool) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"UPDATE user_sessions SET revoked_at = now() WHERE user_id = $1 AND revoked_at IS NULL"#,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to revoke the user's sessions.")?;
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use crate::domain::SubscriberEmail;
+use actix_web::cookie::SameSite;
 use secrecy::{ExposeSecret, Secret};
-use serde_aux::field_attributes::deserialize_number_from_string;
+use serde_aux::field_attributes::{deserialize_bool_from_anything, deserialize_number_from_string};
 use sqlx::postgres::PgConnectOptions;
 use std::convert::{TryFrom, TryInto};
 
@@ -10,6 +11,10 @@ pub struct Settings {
     pub application: ApplicationSetting,
     pub email_client: EmailClientSetting,
     pub redis_uri: Secret<String>,
+    pub login_throttle: LoginThrottleSettings,
+    pub security_headers: SecurityHeadersSettings,
+    pub subscription_protection: SubscriptionProtectionSettings,
+    pub email_domains: EmailDomainSettings,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -19,6 +24,47 @@ pub struct ApplicationSetting {
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub hmac_secret: Secret<String>,
+    pub session: SessionSettings,
+    /// Given to the seeded `admin` user at startup, so that it can reset
+    /// its password.
+    #[serde(default)]
+    pub admin_email: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct SessionSettings {
+    pub cookie_name: String,
+    #[serde(deserialize_with = "deserialize_bool_from_anything")]
+    pub cookie_secure: bool,
+    pub cookie_same_site: SameSitePolicy,
+    /// How long a session lasts at most, however active it is.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ttl_seconds: i64,
+    /// Sessions unused for this long are logged out, remembered or not.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub idle_timeout_seconds: i64,
+    /// Replaces `ttl_seconds` for users who tick "Remember me", whose
+    /// cookie also survives the browser being closed.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub remember_me_ttl_seconds: i64,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SameSitePolicy {
+    Strict,
+    Lax,
+    None,
+}
+
+impl From<SameSitePolicy> for SameSite {
+    fn from(policy: SameSitePolicy) -> Self {
+        match policy {
+            SameSitePolicy::Strict => SameSite::Strict,
+            SameSitePolicy::Lax => SameSite::Lax,
+            SameSitePolicy::None => SameSite::None,
+        }
+    }
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
@@ -37,6 +83,89 @@ impl EmailClientSetting {
     }
 }
 
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct LoginThrottleSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_username: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_ip: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub lockout_seconds: usize,
+    /// Prepended to every Redis key, so that several deployments
+    /// can share one Redis.
+    pub key_prefix: String,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct SecurityHeadersSettings {
+    /// For the admin area, login included.
+    pub admin_content_security_policy: String,
+    /// For everything else, such as the subscription pages.
+    pub public_content_security_policy: String,
+    /// 0 leaves `Strict-Transport-Security` out, for deployments without HTTPS.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub hsts_max_age_seconds: u64,
+    pub frame_options: String,
+    pub referrer_policy: String,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct SubscriptionProtectionSettings {
+    /// Forms sent back sooner than this after being rendered are dropped.
+    /// 0 turns the check off, which lets forms hosted elsewhere, without a
+    /// signed timestamp, subscribe.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_seconds_to_submit: i64,
+    /// How long a rendered form can be sent back.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub form_validity_seconds: i64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_per_ip: u64,
+    /// Kept well above `max_per_ip`: large providers host many subscribers.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_per_domain: u64,
+    /// The window both rate limits count over.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub window_seconds: usize,
+    /// Prepended to every Redis key, as for the login throttle.
+    pub key_prefix: String,
+    pub challenge: ChallengeSettings,
+}
+
+/// Which service checks the answer to the challenge (a CAPTCHA) that forms
+/// send in `challenge_response`.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ChallengeSettings {
+    Disabled,
+    /// Accepts a single fixed answer, for local development and tests.
+    Stub {
+        answer: String,
+    },
+    /// A hosted service speaking the usual `siteverify` protocol,
+    /// such as hCaptcha, reCAPTCHA or Turnstile.
+    Remote {
+        verify_url: String,
+        secret: Secret<String>,
+    },
+}
+
+/// Which subscriber addresses are turned away for their domain, on top of
+/// the rules admins set.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct EmailDomainSettings {
+    /// Turns away the throwaway-address providers of the bundled list.
+    #[serde(deserialize_with = "deserialize_bool_from_anything")]
+    pub block_disposable: bool,
+    /// Turns away domains that DNS says cannot receive email.
+    #[serde(deserialize_with = "deserialize_bool_from_anything")]
+    pub check_mx: bool,
+    /// Stores the addresses of providers that ignore dots in the local
+    /// part, such as Gmail, in a single form.
+    #[serde(deserialize_with = "deserialize_bool_from_anything")]
+    pub merge_provider_aliases: bool,
+}
+
 #[derive(serde::Deserialize, Clone)]
 pub struct DatabaseSettings {
     pub username: String,
@@ -0,0 +1,61 @@
+use uuid::Uuid;
+
+/// The list new subscribers join when the form does not name one.
+pub const DEFAULT_LIST_SLUG: &str = "newsletter";
+
+#[derive(Debug, Clone)]
+pub struct MailingList {
+    pub list_id: Uuid,
+    pub slug: String,
+    pub name: String,
+}
+
+#[derive(Debug)]
+pub struct ListSlug(String);
+
+impl ListSlug {
+    pub fn parse(s: String) -> Result<ListSlug, String> {
+        let is_valid = !s.is_empty()
+            && s.len() <= 64
+            && s.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if is_valid {
+            Ok(Self(s))
+        } else {
+            Err(format!(
+                "{} is not a valid list identifier. \
+                Use lowercase letters, digits and dashes.",
+                s
+            ))
+        }
+    }
+}
+
+impl AsRef<str> for ListSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::ListSlug;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn a_lowercase_dashed_slug_is_valid() {
+        assert_ok!(ListSlug::parse("engineering-digest".to_string()));
+    }
+
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_err!(ListSlug::parse("".to_string()));
+    }
+
+    #[test]
+    fn slugs_with_uppercase_or_spaces_are_rejected() {
+        for slug in ["Newsletter", "engineering digest", "digest/2023"] {
+            assert_err!(ListSlug::parse(slug.to_string()));
+        }
+    }
+}
@@ -1,7 +1,15 @@
+mod mailing_list;
 mod new_subscriber;
+mod newsletter_issue;
+mod segment;
+mod subscriber_attributes;
 mod subscriber_email;
 mod subscriber_name;
 
+pub use mailing_list::{ListSlug, MailingList, DEFAULT_LIST_SLUG};
 pub use new_subscriber::NewSubscriber;
+pub use newsletter_issue::{issue_slug, IssueVisibility};
+pub use segment::Segment;
+pub use subscriber_attributes::SubscriberAttributes;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
@@ -1,7 +1,12 @@
-use crate::domain::{SubscriberEmail, SubscriberName};
+use crate::domain::{ListSlug, SubscriberAttributes, SubscriberEmail, SubscriberName};
+use crate::i18n::Locale;
 
 #[derive(Debug)]
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    pub list: ListSlug,
+    pub attributes: SubscriberAttributes,
+    /// The language the emails they get are written in.
+    pub locale: Locale,
 }
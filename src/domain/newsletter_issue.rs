@@ -0,0 +1,92 @@
+/// Who can read an issue in the web archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IssueVisibility {
+    Public,
+    /// Only readers holding a confirmed subscriber's archive token.
+    #[default]
+    Subscribers,
+}
+
+impl IssueVisibility {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IssueVisibility::Public => "public",
+            IssueVisibility::Subscribers => "subscribers",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<IssueVisibility, String> {
+        match s {
+            "public" => Ok(IssueVisibility::Public),
+            "subscribers" => Ok(IssueVisibility::Subscribers),
+            other => Err(format!(
+                "{} is not a valid visibility. Use either `public` or `subscribers`.",
+                other
+            )),
+        }
+    }
+}
+
+const MAX_SLUG_LENGTH: usize = 80;
+
+/// The archive URL segment for an issue titled `title`: lowercase letters
+/// and digits, in any script, separated by single dashes.
+pub fn issue_slug(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            if slug.len() + c.len_utf8() > MAX_SLUG_LENGTH {
+                break;
+            }
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "issue".into()
+    } else {
+        slug.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{issue_slug, IssueVisibility};
+    use claims::assert_err;
+
+    #[test]
+    fn titles_become_lowercase_dashed_slugs() {
+        assert_eq!(
+            issue_slug("  Issue #12: What's new in Rust 1.72?"),
+            "issue-12-what-s-new-in-rust-1-72"
+        );
+    }
+
+    #[test]
+    fn letters_from_other_scripts_are_kept() {
+        assert_eq!(issue_slug("شماره ۳: تابستان"), "شماره-۳-تابستان");
+    }
+
+    #[test]
+    fn titles_without_letters_or_digits_still_get_a_slug() {
+        assert_eq!(issue_slug("!!!"), "issue");
+        assert_eq!(issue_slug(""), "issue");
+    }
+
+    #[test]
+    fn long_titles_are_cut_short() {
+        let slug = issue_slug(&"a ".repeat(100));
+        assert!(slug.len() <= 80);
+        assert!(!slug.ends_with('-'));
+    }
+
+    #[test]
+    fn visibility_round_trips() {
+        for visibility in [IssueVisibility::Public, IssueVisibility::Subscribers] {
+            assert_eq!(IssueVisibility::parse(visibility.as_str()), Ok(visibility));
+        }
+        assert_err!(IssueVisibility::parse("private"));
+    }
+}
@@ -0,0 +1,413 @@
+use chrono::NaiveDate;
+use sqlx::{Postgres, QueryBuilder};
+
+/// A filter over subscribers, e.g.
+/// `country = IR and not plan in (free, trial) and subscribed after 2023-01-01`.
+///
+/// Supported conditions:
+/// - `key = value` / `key != value`: the subscriber has (or has not) that attribute value
+/// - `key in (a, b)`: the attribute matches one of the values
+/// - `has key`: the attribute is set, whatever its value
+/// - `subscribed before DATE` / `subscribed after DATE`: signup date ranges
+///
+/// Conditions can be combined with `and`, `or`, `not` and parentheses.
+/// Values containing spaces or punctuation must be double-quoted.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    And(Box<Segment>, Box<Segment>),
+    Or(Box<Segment>, Box<Segment>),
+    Not(Box<Segment>),
+    Has(String),
+    Equals(String, String),
+    NotEquals(String, String),
+    In(String, Vec<String>),
+    SubscribedBefore(NaiveDate),
+    SubscribedAfter(NaiveDate),
+}
+
+const MAX_EXPRESSION_LENGTH: usize = 1024;
+
+impl Segment {
+    pub fn parse(s: &str) -> Result<Segment, String> {
+        if s.len() > MAX_EXPRESSION_LENGTH {
+            return Err(format!(
+                "The segment expression must be shorter than {} characters.",
+                MAX_EXPRESSION_LENGTH
+            ));
+        }
+        let tokens = tokenize(s)?;
+        if tokens.is_empty() {
+            return Err("The segment expression is empty.".into());
+        }
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let segment = parser.or_expression()?;
+        match parser.peek() {
+            None => Ok(segment),
+            Some(t) => Err(format!("Unexpected {} in segment expression.", t)),
+        }
+    }
+
+    /// Appends a boolean SQL condition matching this segment.
+    /// The subscriber row must be in scope under the alias `s`.
+    pub fn push_sql(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        match self {
+            Segment::And(left, right) | Segment::Or(left, right) => {
+                let operator = if let Segment::And(_, _) = self {
+                    " AND "
+                } else {
+                    " OR "
+                };
+                builder.push("(");
+                left.push_sql(builder);
+                builder.push(operator);
+                right.push_sql(builder);
+                builder.push(")");
+            }
+            Segment::Not(inner) => {
+                builder.push("NOT (");
+                inner.push_sql(builder);
+                builder.push(")");
+            }
+            Segment::Has(key) => {
+                push_attribute_exists(builder, key);
+                builder.push(")");
+            }
+            Segment::Equals(key, value) => {
+                push_attribute_exists(builder, key);
+                builder
+                    .push(" AND a.value = ")
+                    .push_bind(value.clone())
+                    .push(")");
+            }
+            Segment::NotEquals(key, value) => {
+                builder.push("NOT ");
+                push_attribute_exists(builder, key);
+                builder
+                    .push(" AND a.value = ")
+                    .push_bind(value.clone())
+                    .push(")");
+            }
+            Segment::In(key, values) => {
+                push_attribute_exists(builder, key);
+                builder
+                    .push(" AND a.value = ANY(")
+                    .push_bind(values.clone())
+                    .push("))");
+            }
+            Segment::SubscribedBefore(date) => {
+                builder.push("s.subscribed_at < ").push_bind(*date);
+            }
+            Segment::SubscribedAfter(date) => {
+                builder.push("s.subscribed_at >= ").push_bind(*date);
+            }
+        }
+    }
+}
+
+fn push_attribute_exists(builder: &mut QueryBuilder<'_, Postgres>, key: &str) {
+    builder
+        .push(
+            "EXISTS (SELECT 1 FROM subscriber_attributes a \
+            WHERE a.subscriber_id = s.id AND a.key = ",
+        )
+        .push_bind(key.to_owned());
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Equals,
+    NotEquals,
+    OpenParen,
+    CloseParen,
+    Comma,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(w) => write!(f, "`{}`", w),
+            Token::Quoted(q) => write!(f, "\"{}\"", q),
+            Token::Equals => write!(f, "`=`"),
+            Token::NotEquals => write!(f, "`!=`"),
+            Token::OpenParen => write!(f, "`(`"),
+            Token::CloseParen => write!(f, "`)`"),
+            Token::Comma => write!(f, "`,`"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' | ',' | '=' => {
+                chars.next();
+                tokens.push(match c {
+                    '(' => Token::OpenParen,
+                    ')' => Token::CloseParen,
+                    ',' => Token::Comma,
+                    _ => Token::Equals,
+                });
+            }
+            '!' => {
+                chars.next();
+                if chars.next() != Some('=') {
+                    return Err("Expected `=` after `!` in segment expression.".into());
+                }
+                tokens.push(Token::NotEquals);
+            }
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => value.push(c),
+                        None => return Err("Unterminated string in segment expression.".into()),
+                    }
+                }
+                tokens.push(Token::Quoted(value));
+            }
+            c if is_word_character(c) => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !is_word_character(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+            c => {
+                return Err(format!(
+                    "Unexpected character `{}` in segment expression.",
+                    c
+                ))
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn is_word_character(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn next_is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(t) if t == expected => Ok(()),
+            Some(t) => Err(format!("Expected {} but found {}.", expected, t)),
+            None => Err(format!("Expected {} but the expression ended.", expected)),
+        }
+    }
+
+    fn or_expression(&mut self) -> Result<Segment, String> {
+        let mut segment = self.and_expression()?;
+        while self.next_is_keyword("or") {
+            self.next();
+            let right = self.and_expression()?;
+            segment = Segment::Or(Box::new(segment), Box::new(right));
+        }
+        Ok(segment)
+    }
+
+    fn and_expression(&mut self) -> Result<Segment, String> {
+        let mut segment = self.unary_expression()?;
+        while self.next_is_keyword("and") {
+            self.next();
+            let right = self.unary_expression()?;
+            segment = Segment::And(Box::new(segment), Box::new(right));
+        }
+        Ok(segment)
+    }
+
+    fn unary_expression(&mut self) -> Result<Segment, String> {
+        if self.next_is_keyword("not") {
+            self.next();
+            return Ok(Segment::Not(Box::new(self.unary_expression()?)));
+        }
+        if self.peek() == Some(&Token::OpenParen) {
+            self.next();
+            let segment = self.or_expression()?;
+            self.expect(Token::CloseParen)?;
+            return Ok(segment);
+        }
+        self.condition()
+    }
+
+    fn condition(&mut self) -> Result<Segment, String> {
+        if self.next_is_keyword("has") {
+            self.next();
+            return Ok(Segment::Has(self.key()?));
+        }
+        if self.next_is_keyword("subscribed") {
+            self.next();
+            let before = match self.next() {
+                Some(Token::Word(w)) if w.eq_ignore_ascii_case("before") => true,
+                Some(Token::Word(w)) if w.eq_ignore_ascii_case("after") => false,
+                _ => return Err("Expected `before` or `after` after `subscribed`.".into()),
+            };
+            let date = self.value()?;
+            let date = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                .map_err(|_| format!("{} is not a valid date, use YYYY-MM-DD.", date))?;
+            return Ok(if before {
+                Segment::SubscribedBefore(date)
+            } else {
+                Segment::SubscribedAfter(date)
+            });
+        }
+        let key = self.key()?;
+        match self.next() {
+            Some(Token::Equals) => Ok(Segment::Equals(key, self.value()?)),
+            Some(Token::NotEquals) => Ok(Segment::NotEquals(key, self.value()?)),
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("in") => {
+                self.expect(Token::OpenParen)?;
+                let mut values = vec![self.value()?];
+                while self.peek() == Some(&Token::Comma) {
+                    self.next();
+                    values.push(self.value()?);
+                }
+                self.expect(Token::CloseParen)?;
+                Ok(Segment::In(key, values))
+            }
+            Some(t) => Err(format!("Expected `=`, `!=` or `in` but found {}.", t)),
+            None => Err(format!("Incomplete condition on `{}`.", key)),
+        }
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Word(w)) => Ok(w.to_lowercase()),
+            Some(t) => Err(format!("Expected an attribute name but found {}.", t)),
+            None => Err("Expected an attribute name but the expression ended.".into()),
+        }
+    }
+
+    fn value(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Word(w)) | Some(Token::Quoted(w)) => Ok(w),
+            Some(t) => Err(format!("Expected a value but found {}.", t)),
+            None => Err("Expected a value but the expression ended.".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Segment;
+    use chrono::NaiveDate;
+    use claims::{assert_err, assert_ok};
+    use sqlx::{Postgres, QueryBuilder};
+
+    fn attribute(key: &str, value: &str) -> Segment {
+        Segment::Equals(key.into(), value.into())
+    }
+
+    #[test]
+    fn a_single_condition_is_parsed() {
+        assert_eq!(
+            Segment::parse("country = IR"),
+            Ok(attribute("country", "IR"))
+        );
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let segment = Segment::parse("plan = pro or country = IR and plan = free").unwrap();
+        assert_eq!(
+            segment,
+            Segment::Or(
+                Box::new(attribute("plan", "pro")),
+                Box::new(Segment::And(
+                    Box::new(attribute("country", "IR")),
+                    Box::new(attribute("plan", "free"))
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn parentheses_not_and_in_lists_are_supported() {
+        let segment = Segment::parse(r#"not (source in (twitter, "landing page"))"#).unwrap();
+        assert_eq!(
+            segment,
+            Segment::Not(Box::new(Segment::In(
+                "source".into(),
+                vec!["twitter".into(), "landing page".into()]
+            )))
+        );
+    }
+
+    #[test]
+    fn signup_date_ranges_are_parsed() {
+        let segment =
+            Segment::parse("subscribed after 2023-01-01 and subscribed before 2023-02-01").unwrap();
+        assert_eq!(
+            segment,
+            Segment::And(
+                Box::new(Segment::SubscribedAfter(
+                    NaiveDate::from_ymd_opt(2023, 1, 1).unwrap()
+                )),
+                Box::new(Segment::SubscribedBefore(
+                    NaiveDate::from_ymd_opt(2023, 2, 1).unwrap()
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn malformed_expressions_are_rejected() {
+        for expression in [
+            "",
+            "country",
+            "country =",
+            "(country = IR",
+            "country = IR plan = pro",
+            "subscribed after yesterday",
+            "country = \"IR",
+            "country ; drop table subscriptions",
+        ] {
+            assert_err!(Segment::parse(expression), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn values_are_bound_rather_than_inlined() {
+        let segment = assert_ok!(Segment::parse(r#"has plan and country != "IR' OR 1=1""#));
+        let mut builder = QueryBuilder::<Postgres>::new("");
+        segment.push_sql(&mut builder);
+        let sql = builder.sql();
+        assert!(!sql.contains("1=1"));
+        assert!(sql.contains("$3"));
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::BTreeMap;
+
+/// Free-form key/value pairs captured at signup (country, plan, signup source...).
+#[derive(Debug, Default)]
+pub struct SubscriberAttributes(BTreeMap<String, String>);
+
+const MAX_ATTRIBUTES: usize = 20;
+const MAX_KEY_LENGTH: usize = 32;
+const MAX_VALUE_LENGTH: usize = 256;
+
+impl SubscriberAttributes {
+    pub fn parse<I>(pairs: I) -> Result<SubscriberAttributes, String>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut attributes = BTreeMap::new();
+        for (key, value) in pairs {
+            let key = key.trim().to_lowercase();
+            let value = value.trim().to_owned();
+            let is_valid_key = !key.is_empty()
+                && key.len() <= MAX_KEY_LENGTH
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !is_valid_key {
+                return Err(format!("{} is not a valid attribute name.", key));
+            }
+            if value.chars().count() > MAX_VALUE_LENGTH {
+                return Err(format!("The value of {} is too long.", key));
+            }
+            if value.is_empty() {
+                continue;
+            }
+            attributes.insert(key, value);
+        }
+        if attributes.len() > MAX_ATTRIBUTES {
+            return Err(format!(
+                "A subscriber cannot have more than {} attributes.",
+                MAX_ATTRIBUTES
+            ));
+        }
+        Ok(Self(attributes))
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        self.0.keys().cloned().collect()
+    }
+
+    pub fn values(&self) -> Vec<String> {
+        self.0.values().cloned().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriberAttributes;
+    use claims::{assert_err, assert_ok};
+
+    fn pair(key: &str, value: &str) -> (String, String) {
+        (key.into(), value.into())
+    }
+
+    #[test]
+    fn keys_are_lowercased_and_empty_values_dropped() {
+        let attributes = assert_ok!(SubscriberAttributes::parse([
+            pair("Country", "IR"),
+            pair("plan", "")
+        ]));
+        assert_eq!(attributes.keys(), vec!["country".to_string()]);
+        assert_eq!(attributes.values(), vec!["IR".to_string()]);
+    }
+
+    #[test]
+    fn keys_with_punctuation_are_rejected() {
+        assert_err!(SubscriberAttributes::parse([pair("utm-source", "x")]));
+    }
+
+    #[test]
+    fn too_many_attributes_are_rejected() {
+        let pairs = (0..21).map(|i| pair(&format!("key{}", i), "value"));
+        assert_err!(SubscriberAttributes::parse(pairs));
+    }
+}
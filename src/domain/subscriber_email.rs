@@ -1,14 +1,83 @@
-#[derive(Debug, Clone)]
-pub struct SubscriberEmail(pub String);
+use unicode_normalization::UnicodeNormalization;
+
+/// Providers known to ignore the dots and the case of the local part,
+/// and the domain they file every alias under.
+const DOTLESS_PROVIDERS: [(&str, &str); 2] =
+    [("gmail.com", "gmail.com"), ("googlemail.com", "gmail.com")];
+
+/// An address in a single canonical form, so that the same mailbox is not
+/// stored twice: surrounding spaces are dropped and the domain is lowercased
+/// and IDNA-encoded. The local part keeps its case, which only some
+/// providers ignore; uniqueness is case-insensitive in the database.
+/// Local parts may be UTF-8 (RFC 6531), stored in NFC; delivering to them
+/// needs a relay that supports SMTPUTF8.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubscriberEmail(String);
 
 impl SubscriberEmail {
     pub fn parse(s: String) -> Result<SubscriberEmail, String> {
-        if validator::validate_email(&s) {
-            Ok(Self(s))
-        } else {
-            Err(format!("{} is not a valid subscriber email.", s))
+        normalize(&s)
+            .filter(|email| is_valid(email))
+            .map(Self)
+            .ok_or_else(|| format!("{} is not a valid subscriber email.", s))
+    }
+
+    /// Everything after the `@`.
+    pub fn domain(&self) -> &str {
+        self.0.rsplit_once('@').map_or("", |(_, domain)| domain)
+    }
+
+    /// The same mailbox as the providers in `DOTLESS_PROVIDERS` see it:
+    /// `Ursula.Le.Guin@googlemail.com` becomes `ursulaleguin@gmail.com`.
+    /// This relies on how those providers behave, so it only applies where
+    /// the settings ask for it.
+    pub fn merge_provider_aliases(self) -> SubscriberEmail {
+        let Some((local, domain)) = self.0.rsplit_once('@') else {
+            return self;
+        };
+        match DOTLESS_PROVIDERS.iter().find(|(alias, _)| *alias == domain) {
+            Some((_, canonical)) => Self(format!(
+                "{}@{}",
+                local.replace('.', "").to_lowercase(),
+                canonical
+            )),
+            None => self,
         }
     }
+
+    /// Whether the address can only be delivered over SMTPUTF8.
+    pub fn is_internationalized(&self) -> bool {
+        !self.0.is_ascii()
+    }
+}
+
+fn normalize(s: &str) -> Option<String> {
+    let (local, domain) = s.trim().rsplit_once('@')?;
+    if local.is_empty() || domain.is_empty() {
+        return None;
+    }
+    // Also lowercases the domain.
+    let domain = idna::domain_to_ascii(domain).ok()?;
+    let local: String = local.nfc().collect();
+    Some(format!("{}@{}", local, domain))
+}
+
+/// `validator` only knows the ASCII grammar, so every non-ASCII character of
+/// the local part stands in as a letter, which RFC 6531 makes it.
+fn is_valid(email: &str) -> bool {
+    let Some((local, domain)) = email.rsplit_once('@') else {
+        return false;
+    };
+    let ascii_local: String = local
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii() => c,
+            c if c.is_control() || c.is_whitespace() => ' ',
+            _ => 'a',
+        })
+        .collect();
+    // The limit is in octets, not characters.
+    local.len() <= 64 && validator::validate_email(format!("{}@{}", ascii_local, domain))
 }
 
 impl AsRef<str> for SubscriberEmail {
@@ -17,6 +86,12 @@ impl AsRef<str> for SubscriberEmail {
     }
 }
 
+impl std::fmt::Display for SubscriberEmail {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::SubscriberEmail;
@@ -40,6 +115,107 @@ mod tests {
         SubscriberEmail::parse(valid_email.0).is_ok()
     }
 
+    #[quickcheck_macros::quickcheck]
+    fn normalizing_twice_changes_nothing(valid_email: ValidEmailFixture) -> bool {
+        let once = SubscriberEmail::parse(valid_email.0).unwrap();
+        SubscriberEmail::parse(once.as_ref().to_string()).unwrap() == once
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn the_case_of_the_domain_does_not_matter(valid_email: ValidEmailFixture) -> bool {
+        let (local, domain) = valid_email.0.rsplit_once('@').unwrap();
+        let shouted = format!("{}@{}", local, domain.to_uppercase());
+        SubscriberEmail::parse(shouted) == SubscriberEmail::parse(valid_email.0)
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn surrounding_whitespace_does_not_matter(valid_email: ValidEmailFixture) -> bool {
+        let padded = format!("  {}\t", valid_email.0);
+        SubscriberEmail::parse(padded) == SubscriberEmail::parse(valid_email.0)
+    }
+
+    #[test]
+    fn the_local_part_keeps_its_case() {
+        let email = SubscriberEmail::parse(" Ursula@Example.COM ".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "Ursula@example.com");
+    }
+
+    #[test]
+    fn internationalized_domains_are_idna_encoded() {
+        let email = SubscriberEmail::parse("ursula@Bücher.example".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "ursula@xn--bcher-kva.example");
+    }
+
+    #[test]
+    fn gmail_addresses_keep_their_dots() {
+        let email = SubscriberEmail::parse("Ursula.Le.Guin@GoogleMail.com".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "Ursula.Le.Guin@googlemail.com");
+    }
+
+    #[test]
+    fn merged_gmail_aliases_ignore_dots_and_case() {
+        for address in ["Ursula.Le.Guin@gmail.com", "ursulaleguin@GoogleMail.com"] {
+            let email = SubscriberEmail::parse(address.to_string())
+                .unwrap()
+                .merge_provider_aliases();
+            assert_eq!(email.as_ref(), "ursulaleguin@gmail.com");
+        }
+    }
+
+    #[test]
+    fn other_providers_are_not_merged() {
+        let email = SubscriberEmail::parse("Ursula.Le.Guin@example.com".to_string())
+            .unwrap()
+            .merge_provider_aliases();
+        assert_eq!(email.as_ref(), "Ursula.Le.Guin@example.com");
+    }
+
+    #[test]
+    fn plus_tags_are_kept() {
+        let email = SubscriberEmail::parse("ursula+news@gmail.com".to_string())
+            .unwrap()
+            .merge_provider_aliases();
+        assert_eq!(email.as_ref(), "ursula+news@gmail.com");
+    }
+
+    #[test]
+    fn utf8_local_parts_are_accepted() {
+        let email = SubscriberEmail::parse("用户@例子.广告".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "用户@xn--fsqu00a.xn--4rr70v");
+        assert!(email.is_internationalized());
+        assert!(!SubscriberEmail::parse("ursula@example.com".to_string())
+            .unwrap()
+            .is_internationalized());
+    }
+
+    #[test]
+    fn utf8_local_parts_are_stored_composed() {
+        let decomposed = SubscriberEmail::parse("jose\u{301}@example.com".to_string()).unwrap();
+        let composed = SubscriberEmail::parse("jos\u{e9}@example.com".to_string()).unwrap();
+        assert_eq!(decomposed, composed);
+    }
+
+    #[test]
+    fn utf8_local_parts_are_still_checked() {
+        assert_err!(SubscriberEmail::parse(
+            "ursula\u{a0}le@example.com".to_string()
+        ));
+        assert_err!(SubscriberEmail::parse(
+            "ursula\u{85}@example.com".to_string()
+        ));
+        // 22 characters, 66 octets.
+        assert_err!(SubscriberEmail::parse(format!(
+            "{}@example.com",
+            "é".repeat(33)
+        )));
+    }
+
+    #[test]
+    fn invalid_domains_are_rejected() {
+        assert_err!(SubscriberEmail::parse("ursula@exa mple.com".to_string()));
+        assert_err!(SubscriberEmail::parse("ursula@".to_string()));
+    }
+
     #[test]
     fn empty_string_is_rejected() {
         let email = "".to_string();
@@ -57,4 +233,10 @@ mod tests {
         let email = "@domain.com".to_string();
         assert_err!(SubscriberEmail::parse(email));
     }
+
+    #[test]
+    fn the_domain_is_what_follows_the_at_symbol() {
+        let email = SubscriberEmail::parse("milad@domain.com".to_string()).unwrap();
+        assert_eq!(email.domain(), "domain.com");
+    }
 }
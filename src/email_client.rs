@@ -1,11 +1,12 @@
-use anyhow::Context;
+use std::any::Any;
 use std::time::Duration;
 
-use lettre::message::{header, MultiPart, SinglePart};
+use lettre::message::{header, Mailbox, MultiPart, SinglePart};
 use lettre::transport::smtp::authentication::Credentials;
 use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::transport::smtp::response::Code;
 use lettre::transport::stub::AsyncStubTransport;
-use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use lettre::{Address, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
 
 use crate::configuration::EmailClientSetting;
 use crate::domain::{SubscriberEmail, SubscriberName};
@@ -27,10 +28,33 @@ where
 
 #[derive(thiserror::Error, Debug)]
 pub enum EmailClientError {
+    #[error("{0} cannot be used as the sender mailbox")]
+    InvalidSender(String),
+    #[error("{0} cannot be used as a recipient mailbox")]
+    InvalidRecipient(String),
+    #[error("the relay does not support SMTPUTF8, which {0} needs")]
+    Smtputf8NotSupported(String),
+    #[error("failed to build the email")]
+    MessageBuildFailure(#[from] lettre::error::Error),
+    #[error("the relay rejected the email with {code}")]
+    PermanentFailure { code: Code, source: anyhow::Error },
+    #[error("the relay deferred the email with {code}")]
+    TransientFailure { code: Code, source: anyhow::Error },
     #[error("transport error while trying to send the email")]
     TransportError(#[from] anyhow::Error),
 }
 
+impl EmailClientError {
+    /// Whether sending the same email again later may succeed: the relay
+    /// said so with a 4xx code, or it could not be reached at all.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            EmailClientError::TransientFailure { .. } | EmailClientError::TransportError(_)
+        )
+    }
+}
+
 impl<T> EmailClient<T>
 where
     T: AsyncTransport + Send + Sync,
@@ -55,13 +79,16 @@ where
         plain_message: String,
         html_message: String,
     ) -> Result<(), EmailClientError> {
+        let from = Mailbox::new(
+            Some(self.sender.0.as_ref().to_owned()),
+            address(&self.sender.1).map_err(EmailClientError::InvalidSender)?,
+        );
         let email = Message::builder()
-            .from(
-                format!("{} <{}>", self.sender.0.as_ref(), self.sender.1.as_ref())
-                    .parse()
-                    .unwrap(),
-            )
-            .to(format!(" <{}>", recipient.as_ref()).parse().unwrap())
+            .from(from)
+            .to(Mailbox::new(
+                None,
+                address(recipient).map_err(EmailClientError::InvalidRecipient)?,
+            ))
             .subject(subject)
             .multipart(
                 MultiPart::alternative()
@@ -75,16 +102,54 @@ where
                             .header(header::ContentType::TEXT_HTML)
                             .body(html_message),
                     ),
-            )
-            .unwrap();
+            )?;
 
+        // The transport asks for SMTPUTF8 on its own when the envelope needs
+        // it, and gives up before the first command when the relay lacks it.
         match self.transport.send(email).await {
             Ok(_) => Ok(()),
-            Err(e) => Err(EmailClientError::TransportError(e.into())),
+            Err(e)
+                if (recipient.is_internationalized() || self.sender.1.is_internationalized())
+                    && e.to_string().contains("SMTPUTF8") =>
+            {
+                Err(EmailClientError::Smtputf8NotSupported(
+                    recipient.as_ref().to_owned(),
+                ))
+            }
+            Err(e) => Err(transport_error(e)),
         }
     }
 }
 
+fn address(email: &SubscriberEmail) -> Result<Address, String> {
+    email
+        .as_ref()
+        .parse()
+        .map_err(|_| email.as_ref().to_owned())
+}
+
+/// Only the SMTP transport knows about reply codes; the stubs' errors, like
+/// the SMTP ones raised before the relay answered, are plain transport errors.
+fn transport_error<E>(e: E) -> EmailClientError
+where
+    E: 'static + std::error::Error + Send + Sync,
+{
+    let reply = (&e as &dyn Any)
+        .downcast_ref::<lettre::transport::smtp::Error>()
+        .and_then(|e| Some((e.status()?, e.is_permanent())));
+    match reply {
+        Some((code, true)) => EmailClientError::PermanentFailure {
+            code,
+            source: e.into(),
+        },
+        Some((code, false)) => EmailClientError::TransientFailure {
+            code,
+            source: e.into(),
+        },
+        None => EmailClientError::TransportError(e.into()),
+    }
+}
+
 pub fn create_email_client_stub_which_accepts_all_messages(
     sender: SenderInfo,
 ) -> EmailClient<StubMailTransport> {
@@ -165,12 +230,77 @@ mod tests {
     use fake::Fake;
     use lettre::Address;
 
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    use claims::assert_ok;
+    use lettre::{AsyncSmtpTransport, Tokio1Executor};
+
     use crate::domain::{SubscriberEmail, SubscriberName};
     use crate::email_client::{
         create_email_client_stub_which_accepts_all_messages,
-        create_email_client_stub_which_denies_all_messages, SenderInfo,
+        create_email_client_stub_which_denies_all_messages, EmailClient, EmailClientError,
+        MailTransport, SenderInfo,
     };
 
+    fn fake_relay(smtputf8: bool) -> (u16, mpsc::Receiver<String>) {
+        fake_relay_answering_rcpt(smtputf8, b"250 ok\r\n")
+    }
+
+    /// A relay that takes a single connection and reports every command
+    /// before answering it.
+    fn fake_relay_answering_rcpt(
+        smtputf8: bool,
+        rcpt_reply: &'static [u8],
+    ) -> (u16, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            writer.write_all(b"220 relay.test ESMTP\r\n").unwrap();
+            let mut in_data = false;
+            for line in BufReader::new(stream).lines() {
+                let Ok(line) = line else { break };
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        writer.write_all(b"250 queued\r\n").unwrap();
+                    }
+                    continue;
+                }
+                let reply: &[u8] = match line.get(..4).unwrap_or("") {
+                    "EHLO" if smtputf8 => b"250-relay.test\r\n250-8BITMIME\r\n250 SMTPUTF8\r\n",
+                    "EHLO" => b"250-relay.test\r\n250 8BITMIME\r\n",
+                    "DATA" => {
+                        in_data = true;
+                        b"354 go ahead\r\n"
+                    }
+                    "RCPT" => rcpt_reply,
+                    "QUIT" => b"221 bye\r\n",
+                    _ => b"250 ok\r\n",
+                };
+                if tx.send(line).is_err() || writer.write_all(reply).is_err() {
+                    break;
+                }
+            }
+        });
+        (port, rx)
+    }
+
+    fn client_for_relay(port: u16) -> EmailClient<MailTransport> {
+        let sender = SenderInfo(
+            SubscriberName::parse("Ursula".to_string()).unwrap(),
+            SubscriberEmail::parse("newsletter@example.com".to_string()).unwrap(),
+        );
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous("127.0.0.1")
+            .port(port)
+            .build();
+        EmailClient { transport, sender }
+    }
+
     fn subject() -> String {
         Title().fake()
     }
@@ -254,4 +384,127 @@ mod tests {
         assert_eq!(transport_messages.len(), 1);
         // assert_err!(result);
     }
+
+    #[tokio::test]
+    async fn internationalized_recipients_are_sent_with_smtputf8() {
+        let (port, commands) = fake_relay(true);
+        let email_client = client_for_relay(port);
+        let recipient = SubscriberEmail::parse("用户@例子.广告".to_string()).unwrap();
+
+        let result = email_client
+            .send_email(&recipient, subject(), content(), html_content())
+            .await;
+
+        assert_ok!(result);
+        let commands: Vec<_> = commands.try_iter().collect();
+        assert!(commands
+            .iter()
+            .any(|c| c.starts_with("MAIL FROM:") && c.contains(" SMTPUTF8")));
+        assert!(commands.contains(&format!("RCPT TO:<{}>", recipient.as_ref())));
+    }
+
+    #[tokio::test]
+    async fn a_relay_without_smtputf8_is_a_recipient_error_not_a_panic() {
+        let (port, commands) = fake_relay(false);
+        let email_client = client_for_relay(port);
+        let recipient = SubscriberEmail::parse("用户@example.com".to_string()).unwrap();
+
+        let result = email_client
+            .send_email(&recipient, subject(), content(), html_content())
+            .await;
+
+        match result {
+            Err(EmailClientError::Smtputf8NotSupported(address)) => {
+                assert_eq!(address, recipient.as_ref())
+            }
+            other => panic!("expected Smtputf8NotSupported, got {:?}", other),
+        }
+        // Nothing was attempted for that recipient.
+        let commands: Vec<_> = commands.try_iter().collect();
+        assert!(!commands.iter().any(|c| c.starts_with("RCPT")));
+    }
+
+    #[tokio::test]
+    async fn ascii_recipients_do_not_need_smtputf8() {
+        let (port, commands) = fake_relay(false);
+        let email_client = client_for_relay(port);
+
+        let result = email_client
+            .send_email(&email(), subject(), content(), html_content())
+            .await;
+
+        assert_ok!(result);
+        let commands: Vec<_> = commands.try_iter().collect();
+        assert!(!commands.iter().any(|c| c.contains("SMTPUTF8")));
+    }
+
+    #[tokio::test]
+    async fn sender_names_are_not_parsed_as_addresses() {
+        let sender_email = email();
+        let sender_name = SubscriberName::parse("Le Guin, Ursula @ home".to_string()).unwrap();
+        let sender = SenderInfo(sender_name, sender_email.clone());
+        let email_client = create_email_client_stub_which_accepts_all_messages(sender);
+
+        let result = email_client
+            .send_email(&email(), subject(), content(), html_content())
+            .await;
+
+        assert_ok!(result);
+        let messages = email_client.transport.messages().await;
+        assert_eq!(
+            messages[0].0.from().unwrap(),
+            &sender_email.as_ref().parse::<Address>().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn a_rejected_recipient_is_a_permanent_failure() {
+        let (port, _commands) = fake_relay_answering_rcpt(false, b"550 no such user\r\n");
+        let email_client = client_for_relay(port);
+
+        let result = email_client
+            .send_email(&email(), subject(), content(), html_content())
+            .await;
+
+        match result {
+            Err(e @ EmailClientError::PermanentFailure { code, .. }) => {
+                assert_eq!(code.to_string(), "550");
+                assert!(!e.is_transient());
+            }
+            other => panic!("expected PermanentFailure, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn a_deferred_recipient_is_a_transient_failure() {
+        let (port, _commands) = fake_relay_answering_rcpt(false, b"451 try again later\r\n");
+        let email_client = client_for_relay(port);
+
+        let result = email_client
+            .send_email(&email(), subject(), content(), html_content())
+            .await;
+
+        match result {
+            Err(e @ EmailClientError::TransientFailure { code, .. }) => {
+                assert_eq!(code.to_string(), "451");
+                assert!(e.is_transient());
+            }
+            other => panic!("expected TransientFailure, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn an_unreachable_relay_is_a_transient_failure() {
+        let sender = SenderInfo(SubscriberName::parse(FirstName().fake()).unwrap(), email());
+        let email_client = create_email_client_stub_which_denies_all_messages(sender);
+
+        let result = email_client
+            .send_email(&email(), subject(), content(), html_content())
+            .await;
+
+        match result {
+            Err(e @ EmailClientError::TransportError(_)) => assert!(e.is_transient()),
+            other => panic!("expected TransportError, got {:?}", other),
+        }
+    }
 }
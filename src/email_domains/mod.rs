@@ -0,0 +1,339 @@
+//! Which domains we accept subscriber addresses at: admins allow or block
+//! domains, a bundled list covers throwaway-address providers, and DNS can
+//! tell domains that receive no email at all.
+
+mod resolver;
+
+pub use resolver::{DnsMxResolver, MxResolver};
+
+use std::collections::HashSet;
+use std::sync::{Arc, OnceLock};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::configuration::EmailDomainSettings;
+use crate::domain::SubscriberEmail;
+use crate::routes::error_chain_fmt;
+
+/// A domain as admins type it in a rule, e.g. `example.com`.
+/// Internationalized domains are IDNA-encoded, as in subscriber addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailDomain(String);
+
+impl EmailDomain {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let invalid = || format!("{} is not a valid domain.", s.trim());
+        // Also lowercases the domain.
+        let domain =
+            idna::domain_to_ascii(s.trim().trim_start_matches('@')).map_err(|_| invalid())?;
+        let labels_are_valid = domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+        if domain.contains('.') && domain.len() <= 253 && labels_are_valid {
+            Ok(Self(domain))
+        } else {
+            Err(invalid())
+        }
+    }
+}
+
+impl AsRef<str> for EmailDomain {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DomainRuleKind {
+    Allow,
+    Block,
+}
+
+impl DomainRuleKind {
+    pub const ALL: [DomainRuleKind; 2] = [DomainRuleKind::Allow, DomainRuleKind::Block];
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "allow" => Ok(Self::Allow),
+            "block" => Ok(Self::Block),
+            other => Err(format!("{} is not a valid rule.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DomainRuleKind::Allow => "allow",
+            DomainRuleKind::Block => "block",
+        }
+    }
+}
+
+impl std::fmt::Display for DomainRuleKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+pub struct DomainRule {
+    pub domain: String,
+    pub kind: DomainRuleKind,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Why an address was turned away. The messages are shown to whoever
+/// typed the address in.
+#[derive(thiserror::Error)]
+pub enum EmailDomainError {
+    #[error("We do not accept addresses at {0}.")]
+    Blocked(String),
+    #[error("{0} hands out temporary addresses: please use a permanent one.")]
+    Disposable(String),
+    #[error("{0} does not receive email: please check the address.")]
+    NoMailServer(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for EmailDomainError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+pub struct EmailDomainPolicy {
+    settings: EmailDomainSettings,
+    resolver: Option<Arc<dyn MxResolver>>,
+}
+
+impl EmailDomainPolicy {
+    /// `resolver` replaces the DNS servers of the host for the MX check,
+    /// which only happens if the settings ask for it.
+    pub fn new(
+        settings: EmailDomainSettings,
+        resolver: Option<Arc<dyn MxResolver>>,
+    ) -> Result<Self, anyhow::Error> {
+        let resolver = match (settings.check_mx, resolver) {
+            (false, _) => None,
+            (true, Some(resolver)) => Some(resolver),
+            (true, None) => {
+                Some(Arc::new(DnsMxResolver::from_system_conf()?) as Arc<dyn MxResolver>)
+            }
+        };
+        Ok(Self { settings, resolver })
+    }
+
+    /// `email` as subscribers are stored and looked up.
+    pub fn canonical(&self, email: SubscriberEmail) -> SubscriberEmail {
+        if self.settings.merge_provider_aliases {
+            email.merge_provider_aliases()
+        } else {
+            email
+        }
+    }
+
+    /// Whether subscribers may use `email`, going by its domain.
+    /// Admin rules come first, the most specific one winning: allowing a
+    /// domain also skips the bundled list and the MX check.
+    #[tracing::instrument(name = "Check the email domain", skip(self, email, pool))]
+    pub async fn check(
+        &self,
+        email: &SubscriberEmail,
+        pool: &PgPool,
+    ) -> Result<(), EmailDomainError> {
+        let domain = email.domain().to_lowercase();
+        let candidates = parent_domains(&domain);
+        match matching_rule(pool, &candidates)
+            .await
+            .context("Failed to look up the email domain rules.")?
+        {
+            Some(DomainRuleKind::Allow) => return Ok(()),
+            Some(DomainRuleKind::Block) => return Err(EmailDomainError::Blocked(domain)),
+            None => {}
+        }
+        if self.settings.block_disposable
+            && candidates
+                .iter()
+                .any(|d| disposable_domains().contains(d.as_str()))
+        {
+            return Err(EmailDomainError::Disposable(domain));
+        }
+        if let Some(resolver) = &self.resolver {
+            match resolver.accepts_mail(&domain).await {
+                Ok(true) => {}
+                Ok(false) => return Err(EmailDomainError::NoMailServer(domain)),
+                // DNS trouble on our side is no reason to turn people away.
+                Err(e) => tracing::warn!(
+                    error.cause_chain = ?e,
+                    "Could not check the mail servers of {}",
+                    domain
+                ),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `domain` followed by each domain it is part of, most specific first:
+/// `mail.example.com`, `example.com`, `com`.
+fn parent_domains(domain: &str) -> Vec<String> {
+    let mut domains = vec![domain.to_string()];
+    let mut rest = domain;
+    while let Some((_, parent)) = rest.split_once('.') {
+        domains.push(parent.to_string());
+        rest = parent;
+    }
+    domains
+}
+
+fn disposable_domains() -> &'static HashSet<&'static str> {
+    static DOMAINS: OnceLock<HashSet<&'static str>> = OnceLock::new();
+    DOMAINS.get_or_init(|| {
+        include_str!("disposable_domains.txt")
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect()
+    })
+}
+
+/// The kind of the most specific rule covering one of `candidates`,
+/// which run from the most specific domain to the least.
+async fn matching_rule(
+    pool: &PgPool,
+    candidates: &[String],
+) -> Result<Option<DomainRuleKind>, anyhow::Error> {
+    let rules = sqlx::query!(
+        r#"SELECT domain, kind FROM email_domain_rules WHERE domain = ANY($1)"#,
+        candidates
+    )
+    .fetch_all(pool)
+    .await?;
+    candidates
+        .iter()
+        .find_map(|candidate| rules.iter().find(|r| &r.domain == candidate))
+        .map(|r| DomainRuleKind::parse(&r.kind).map_err(anyhow::Error::msg))
+        .transpose()
+}
+
+#[tracing::instrument(name = "List email domain rules", skip(pool))]
+pub async fn list_domain_rules(pool: &PgPool) -> Result<Vec<DomainRule>, anyhow::Error> {
+    let rows =
+        sqlx::query!(r#"SELECT domain, kind, created_at FROM email_domain_rules ORDER BY domain"#)
+            .fetch_all(pool)
+            .await
+            .context("Failed to retrieve the email domain rules.")?;
+    rows.into_iter()
+        .map(|r| {
+            Ok(DomainRule {
+                kind: DomainRuleKind::parse(&r.kind).map_err(anyhow::Error::msg)?,
+                domain: r.domain,
+                created_at: r.created_at,
+            })
+        })
+        .collect()
+}
+
+/// Adds a rule for `domain`, replacing the one it had.
+#[tracing::instrument(name = "Save an email domain rule", skip(executor))]
+pub async fn save_domain_rule<'c>(
+    executor: impl PgExecutor<'c>,
+    domain: &EmailDomain,
+    kind: DomainRuleKind,
+    created_by: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_domain_rules (domain, kind, created_by)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (domain) DO UPDATE
+        SET kind = EXCLUDED.kind, created_at = now(), created_by = EXCLUDED.created_by
+        "#,
+        domain.as_ref(),
+        kind.as_str(),
+        created_by
+    )
+    .execute(executor)
+    .await
+    .context("Failed to save the email domain rule.")?;
+    Ok(())
+}
+
+/// Returns `false` if `domain` had no rule.
+#[tracing::instrument(name = "Remove an email domain rule", skip(executor))]
+pub async fn remove_domain_rule<'c>(
+    executor: impl PgExecutor<'c>,
+    domain: &EmailDomain,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"DELETE FROM email_domain_rules WHERE domain = $1"#,
+        domain.as_ref()
+    )
+    .execute(executor)
+    .await
+    .context("Failed to remove the email domain rule.")?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{disposable_domains, parent_domains, DomainRuleKind, EmailDomain};
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn domains_are_normalised() {
+        assert_ok_eq!(
+            EmailDomain::parse(" @Example.COM "),
+            EmailDomain("example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn internationalized_domains_are_idna_encoded() {
+        assert_ok_eq!(
+            EmailDomain::parse("Bücher.example"),
+            EmailDomain("xn--bcher-kva.example".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_domains_are_rejected() {
+        for domain in [
+            "",
+            "localhost",
+            "exa mple.com",
+            "-example.com",
+            "example..com",
+        ] {
+            assert_err!(EmailDomain::parse(domain));
+        }
+    }
+
+    #[test]
+    fn parent_domains_run_from_the_most_specific() {
+        assert_eq!(
+            parent_domains("mail.example.com"),
+            vec!["mail.example.com", "example.com", "com"]
+        );
+    }
+
+    #[test]
+    fn the_bundled_list_is_loaded_without_comments() {
+        assert!(disposable_domains().contains("mailinator.com"));
+        assert!(!disposable_domains().iter().any(|d| d.starts_with('#')));
+    }
+
+    #[test]
+    fn rule_kinds_round_trip() {
+        for kind in DomainRuleKind::ALL {
+            assert_ok_eq!(DomainRuleKind::parse(kind.as_str()), kind);
+        }
+        assert_err!(DomainRuleKind::parse("maybe"));
+    }
+}
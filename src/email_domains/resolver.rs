@@ -0,0 +1,45 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use trust_dns_resolver::error::ResolveErrorKind;
+use trust_dns_resolver::TokioAsyncResolver;
+
+/// Tells whether a domain can receive email, so that addresses at
+/// made-up domains can be turned away before we mail them.
+#[async_trait]
+pub trait MxResolver: Send + Sync {
+    async fn accepts_mail(&self, domain: &str) -> Result<bool, anyhow::Error>;
+}
+
+/// Asks the DNS servers of the host.
+pub struct DnsMxResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl DnsMxResolver {
+    pub fn from_system_conf() -> Result<Self, anyhow::Error> {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf()
+            .context("Failed to read the system's DNS configuration")?;
+        Ok(Self { resolver })
+    }
+}
+
+#[async_trait]
+impl MxResolver for DnsMxResolver {
+    #[tracing::instrument(name = "Look up mail servers", skip(self))]
+    async fn accepts_mail(&self, domain: &str) -> Result<bool, anyhow::Error> {
+        // Fully qualified, so that the search domains of the host are not tried.
+        let name = format!("{}.", domain.trim_end_matches('.'));
+        match self.resolver.mx_lookup(name.as_str()).await {
+            // A lone "." (a null MX) says the domain takes no mail.
+            Ok(mx) => return Ok(mx.iter().any(|mx| !mx.exchange().is_root())),
+            Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {}
+            Err(e) => return Err(e).context("Failed to look up MX records"),
+        }
+        // Without MX records, mail goes to the domain's own address.
+        match self.resolver.lookup_ip(name.as_str()).await {
+            Ok(ips) => Ok(ips.iter().next().is_some()),
+            Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => Ok(false),
+            Err(e) => Err(e).context("Failed to look up the domain's address"),
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//! Message catalogs for what subscribers and users read, and the choice of
+//! language they are read in.
+
+use std::collections::HashMap;
+use std::future::{ready, Ready};
+use std::sync::OnceLock;
+
+use actix_web::dev::Payload;
+use actix_web::http::header::ACCEPT_LANGUAGE;
+use actix_web::{FromRequest, HttpRequest};
+
+type Catalog = HashMap<&'static str, &'static str>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    En,
+    Fa,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::En, Locale::Fa];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Fa => "fa",
+        }
+    }
+
+    /// Accepts a language tag such as `fa` or `fa-IR`; only the language
+    /// is looked at.
+    pub fn parse(tag: &str) -> Option<Locale> {
+        let language = tag.trim().split(['-', '_']).next()?;
+        Self::ALL
+            .into_iter()
+            .find(|l| l.as_str().eq_ignore_ascii_case(language))
+    }
+
+    /// The supported locale an `Accept-Language` header likes best, or the
+    /// default one.
+    pub fn negotiate(accept_language: &str) -> Locale {
+        let mut ranges: Vec<(&str, f32)> = accept_language
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let tag = parts.next()?.trim();
+                let quality = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.trim().parse().ok())?;
+                Some((tag, quality))
+            })
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect();
+        // Stable, so that equal qualities keep the client's order.
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranges
+            .into_iter()
+            .find_map(|(tag, _)| Locale::parse(tag))
+            .unwrap_or_default()
+    }
+
+    pub fn is_rtl(&self) -> bool {
+        matches!(self, Locale::Fa)
+    }
+
+    /// The value of the HTML `dir` attribute for text in this locale.
+    pub fn dir(&self) -> &'static str {
+        if self.is_rtl() {
+            "rtl"
+        } else {
+            "ltr"
+        }
+    }
+
+    /// The message for `key`, in English if this catalog lacks it.
+    /// Unknown keys come back as they are, so that they show up in the page.
+    pub fn text<'a>(&self, key: &'a str) -> &'a str {
+        self.catalog()
+            .get(key)
+            .or_else(|| Locale::En.catalog().get(key))
+            .copied()
+            .unwrap_or(key)
+    }
+
+    /// The message for `key` with each `{name}` placeholder filled in.
+    pub fn format(&self, key: &str, args: &[(&str, &str)]) -> String {
+        args.iter()
+            .fold(self.text(key).to_string(), |message, (name, value)| {
+                message.replace(&format!("{{{}}}", name), value)
+            })
+    }
+
+    fn catalog(&self) -> &'static Catalog {
+        static ENGLISH: OnceLock<Catalog> = OnceLock::new();
+        static PERSIAN: OnceLock<Catalog> = OnceLock::new();
+        match self {
+            Locale::En => ENGLISH.get_or_init(|| parse_catalog(include_str!("en.txt"))),
+            Locale::Fa => PERSIAN.get_or_init(|| parse_catalog(include_str!("fa.txt"))),
+        }
+    }
+
+    /// Fills in the `{t:key}` placeholders of a page with their messages,
+    /// and `{lang}` and `{dir}` for its `<html>` element.
+    pub fn localize(&self, template: &str) -> String {
+        let mut page = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find("{t:") {
+            let Some(end) = rest[start..].find('}') else {
+                break;
+            };
+            page.push_str(&rest[..start]);
+            page.push_str(self.text(&rest[start + 3..start + end]));
+            rest = &rest[start + end + 1..];
+        }
+        page.push_str(rest);
+        page.replace("{lang}", self.as_str())
+            .replace("{dir}", self.dir())
+    }
+}
+
+impl FromRequest for Locale {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Locale, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let locale = req
+            .headers()
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|v| v.to_str().ok())
+            .map(Locale::negotiate)
+            .unwrap_or_default();
+        ready(Ok(locale))
+    }
+}
+
+/// Parses the optional language a subscriber asked for, defaulting to
+/// English.
+pub fn parse_locale(tag: Option<String>) -> Result<Locale, String> {
+    match tag {
+        Some(tag) => {
+            Locale::parse(&tag).ok_or_else(|| format!("{} is not a supported language.", tag))
+        }
+        None => Ok(Locale::default()),
+    }
+}
+
+fn parse_catalog(catalog: &'static str) -> Catalog {
+    catalog
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, message)| (key.trim(), message.trim()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_catalog, Locale};
+
+    #[test]
+    fn every_catalog_has_every_english_message() {
+        let english = parse_catalog(include_str!("en.txt"));
+        let persian = parse_catalog(include_str!("fa.txt"));
+        let mut missing: Vec<_> = english
+            .keys()
+            .filter(|k| !persian.contains_key(*k))
+            .collect();
+        missing.sort();
+        assert!(missing.is_empty(), "fa.txt lacks {:?}", missing);
+        let mut unknown: Vec<_> = persian
+            .keys()
+            .filter(|k| !english.contains_key(*k))
+            .collect();
+        unknown.sort();
+        assert!(unknown.is_empty(), "fa.txt has unknown keys {:?}", unknown);
+    }
+
+    #[test]
+    fn translations_keep_the_placeholders() {
+        let english = parse_catalog(include_str!("en.txt"));
+        for (key, message) in english {
+            for placeholder in message.split('{').skip(1) {
+                let name = placeholder.split('}').next().unwrap();
+                assert!(
+                    Locale::Fa.text(key).contains(&format!("{{{}}}", name)),
+                    "{} lacks {{{}}} in Persian",
+                    key,
+                    name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn language_tags_are_matched_on_their_language() {
+        assert_eq!(Locale::parse("fa"), Some(Locale::Fa));
+        assert_eq!(Locale::parse("FA-ir"), Some(Locale::Fa));
+        assert_eq!(Locale::parse("en_GB"), Some(Locale::En));
+        assert_eq!(Locale::parse("de"), None);
+    }
+
+    #[test]
+    fn negotiation_follows_the_quality_values() {
+        assert_eq!(Locale::negotiate("fa-IR,fa;q=0.9,en;q=0.8"), Locale::Fa);
+        assert_eq!(Locale::negotiate("en;q=0.5, fa;q=0.7"), Locale::Fa);
+        assert_eq!(Locale::negotiate("de, fa;q=0.1, en;q=0.2"), Locale::En);
+        assert_eq!(Locale::negotiate("fa;q=0, en"), Locale::En);
+    }
+
+    #[test]
+    fn unsupported_or_garbled_headers_get_the_default() {
+        assert_eq!(Locale::negotiate("de-DE"), Locale::En);
+        assert_eq!(Locale::negotiate("fa;q=abc"), Locale::En);
+        assert_eq!(Locale::negotiate(""), Locale::En);
+    }
+
+    #[test]
+    fn missing_messages_fall_back_to_english_then_the_key() {
+        assert_eq!(Locale::En.text("home.subscribe"), "Subscribe");
+        assert_eq!(Locale::Fa.text("no.such.key"), "no.such.key");
+    }
+
+    #[test]
+    fn placeholders_are_filled_in() {
+        assert_eq!(
+            Locale::En.format("login.locked_out", &[("minutes", "5")]),
+            "Too many failed login attempts. Please try again in 5 minutes."
+        );
+    }
+
+    #[test]
+    fn pages_are_localized() {
+        let page =
+            Locale::Fa.localize(r#"<html lang="{lang}" dir="{dir}"><p>{t:home.welcome}</p>{msg}"#);
+        assert_eq!(
+            page,
+            r#"<html lang="fa" dir="rtl"><p>به خبرنامهٔ ما خوش آمدید</p>{msg}"#
+        );
+    }
+}
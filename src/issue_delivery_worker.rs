@@ -7,16 +7,40 @@ use uuid::Uuid;
 
 use crate::{
     configuration::Settings,
-    domain::{SubscriberEmail, SubscriberName},
-    email_client::{self, EmailClient, SenderInfo},
+    domain::{IssueVisibility, SubscriberEmail, SubscriberName},
+    email_client::{self, EmailClient, EmailClientError, SenderInfo},
+    i18n::Locale,
+    routes::{append_preferences_link, archive_path, get_or_create_archive_token},
     startup::get_connection_pool,
 };
 
+/// How many times a delivery the relay deferred is tried again, waiting
+/// twice as long each time, before it is recorded as failed.
+const MAX_RETRIES: i16 = 5;
+const FIRST_RETRY_DELAY_SECONDS: f64 = 60.0;
+
 pub enum ExecutionOutcome {
     TaskCompleted,
     EmptyQueue,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum DeliveryOutcome {
+    Delivered,
+    Failed,
+    InvalidAddress,
+}
+
+impl DeliveryOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryOutcome::Delivered => "delivered",
+            DeliveryOutcome::Failed => "failed",
+            DeliveryOutcome::InvalidAddress => "invalid_address",
+        }
+    }
+}
+
 #[tracing::instrument(
     skip_all,
     fields(
@@ -28,6 +52,7 @@ pub enum ExecutionOutcome {
 pub async fn try_execute_task<E>(
     pool: &PgPool,
     email_client: &EmailClient<E>,
+    base_url: &str,
 ) -> Result<ExecutionOutcome, anyhow::Error>
 where
     E: 'static + AsyncTransport + Send + Sync,
@@ -38,28 +63,55 @@ where
     if task.is_none() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
-    let (transaction, issue_id, email) = task.unwrap();
+    let (transaction, issue_id, email, n_retries) = task.unwrap();
     Span::current()
-        .record("newsletter_issue_id", &display(issue_id))
-        .record("subscrbier_email", &display(&email));
-    match SubscriberEmail::parse(email.clone()) {
-        Ok(email) => {
+        .record("newsletter_issue_id", display(issue_id))
+        .record("subscrbier_email", display(&email));
+    let outcome = match SubscriberEmail::parse(email.clone()) {
+        Ok(recipient) => {
             let issue = get_issue(pool, issue_id).await?;
-            if let Err(e) = email_client
-                .send_email(
-                    &email,
-                    issue.title.clone(),
-                    issue.html_content.clone(),
-                    issue.text_content.clone(),
-                )
+            let preferences = get_subscriber_links(pool, recipient.as_ref(), &issue).await?;
+            let (text_content, html_content) = with_links(&issue, base_url, preferences);
+            match email_client
+                .send_email(&recipient, issue.title, text_content, html_content)
                 .await
             {
-                tracing::error!(
-                    error.cause_chain = ?e,
-                    error.message = %e,
-                    "Failed to deliver issue to a confirmed subscrbier. \
-                    Skipping"
-                );
+                Ok(()) => DeliveryOutcome::Delivered,
+                Err(e) if e.is_transient() && n_retries < MAX_RETRIES => {
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        n_retries,
+                        "Failed to deliver issue to a confirmed subscriber. \
+                        Retrying later"
+                    );
+                    retry_task_later(transaction, issue_id, &email, n_retries).await?;
+                    return Ok(ExecutionOutcome::TaskCompleted);
+                }
+                Err(e @ EmailClientError::InvalidRecipient(_)) => {
+                    tracing::error!(
+                        error.message = %e,
+                        "Skipping a confirmed subscriber. \
+                        Their address cannot be sent to"
+                    );
+                    DeliveryOutcome::InvalidAddress
+                }
+                Err(e @ EmailClientError::Smtputf8NotSupported(_)) => {
+                    tracing::warn!(
+                        error.message = %e,
+                        "Cannot deliver issue to an internationalized address. Skipping"
+                    );
+                    DeliveryOutcome::Failed
+                }
+                Err(e) => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to deliver issue to a confirmed subscrbier. \
+                        Skipping"
+                    );
+                    DeliveryOutcome::Failed
+                }
             }
         }
         Err(e) => {
@@ -69,20 +121,24 @@ where
                     "Skipping a confirmed subscriber \
                     Their stored contact details are invalid"
             );
+            DeliveryOutcome::InvalidAddress
         }
-    }
-    delete_task(transaction, issue_id, &email).await?;
+    };
+    delete_task(transaction, issue_id, &email, outcome).await?;
     Ok(ExecutionOutcome::TaskCompleted)
 }
 type PgTransaction = Transaction<'static, Postgres>;
 
 #[tracing::instrument(skip_all)]
-async fn deque_task(pool: &PgPool) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+async fn deque_task(
+    pool: &PgPool,
+) -> Result<Option<(PgTransaction, Uuid, String, i16)>, anyhow::Error> {
     let mut transaction = pool.begin().await?;
     let r = sqlx::query!(
         r#"
-        SELECT newsletter_issue_id, subscriber_email
+        SELECT newsletter_issue_id, subscriber_email, n_retries
         FROM issue_delivery_queue
+        WHERE execute_after <= now()
         FOR UPDATE
         SKIP LOCKED
         LIMIT 1
@@ -95,6 +151,7 @@ async fn deque_task(pool: &PgPool) -> Result<Option<(PgTransaction, Uuid, String
             transaction,
             r.newsletter_issue_id,
             r.subscriber_email,
+            r.n_retries,
         )))
     } else {
         Ok(None)
@@ -106,6 +163,7 @@ async fn delete_task(
     mut transaction: PgTransaction,
     issue_id: Uuid,
     email: &str,
+    outcome: DeliveryOutcome,
 ) -> Result<(), anyhow::Error> {
     sqlx::query!(
         r#"
@@ -119,6 +177,50 @@ async fn delete_task(
     )
     .execute(&mut transaction)
     .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_history (
+            newsletter_issue_id,
+            subscriber_email,
+            outcome,
+            recorded_at
+        )
+        VALUES ($1, $2, $3, now())
+        "#,
+        issue_id,
+        email,
+        outcome.as_str()
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn retry_task_later(
+    mut transaction: PgTransaction,
+    issue_id: Uuid,
+    email: &str,
+    n_retries: i16,
+) -> Result<(), anyhow::Error> {
+    let delay_seconds = FIRST_RETRY_DELAY_SECONDS * 2f64.powi(n_retries.into());
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_retries = n_retries + 1,
+            execute_after = now() + make_interval(secs => $3)
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        issue_id,
+        email,
+        delay_seconds
+    )
+    .execute(&mut transaction)
+    .await?;
     transaction.commit().await?;
     Ok(())
 }
@@ -127,6 +229,53 @@ struct NewsletterIssue {
     title: String,
     text_content: String,
     html_content: String,
+    slug: String,
+    visibility: String,
+}
+
+/// The tokens a subscriber's links carry. The archive token only lets the
+/// holder read subscribers-only issues, so it is the one that goes in the
+/// "view in browser" link, which readers tend to forward.
+struct SubscriberLinks {
+    preference_token: String,
+    archive_token: Option<String>,
+    locale: Locale,
+}
+
+/// The issue's text and HTML, with a link to its archive page above, and
+/// one to the subscriber's preferences below when they have a token.
+fn with_links(
+    issue: &NewsletterIssue,
+    base_url: &str,
+    preferences: Option<SubscriberLinks>,
+) -> (String, String) {
+    let locale = preferences.as_ref().map(|p| p.locale).unwrap_or_default();
+    let mut archive_link = format!("{}{}", base_url, archive_path(&issue.slug));
+    if let Some(token) = preferences.as_ref().and_then(|p| p.archive_token.as_ref()) {
+        archive_link = format!("{}?archive_token={}", archive_link, token);
+    }
+    let view = locale.text("newsletter.view_in_browser");
+    let mut text_content = format!("{}: {}\n\n{}", view, archive_link, issue.text_content);
+    let mut html_content = format!(
+        r#"<p dir="{}"><a href="{}">{}</a></p>{}"#,
+        locale.dir(),
+        archive_link,
+        view,
+        issue.html_content
+    );
+    if let Some(SubscriberLinks {
+        preference_token, ..
+    }) = preferences
+    {
+        append_preferences_link(
+            &mut text_content,
+            &mut html_content,
+            locale,
+            base_url,
+            &preference_token,
+        );
+    }
+    (text_content, html_content)
 }
 
 #[tracing::instrument(skip_all)]
@@ -134,7 +283,7 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
     let issue = sqlx::query_as!(
         NewsletterIssue,
         r#"
-        SELECT title, text_content, html_content
+        SELECT title, text_content, html_content, slug, visibility
         FROM newsletter_issues
         WHERE
             newsletter_issue_id = $1
@@ -146,14 +295,50 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
     Ok(issue)
 }
 
-async fn worker_loop<E>(pool: PgPool, email_client: EmailClient<E>) -> Result<(), anyhow::Error>
+#[tracing::instrument(skip_all)]
+async fn get_subscriber_links(
+    pool: &PgPool,
+    email: &str,
+    issue: &NewsletterIssue,
+) -> Result<Option<SubscriberLinks>, anyhow::Error> {
+    let Some(row) = sqlx::query!(
+        r#"
+        SELECT s.id, p.preference_token, s.locale
+        FROM preference_tokens p
+        JOIN subscriptions s ON s.id = p.subscriber_id
+        WHERE s.email = $1
+        "#,
+        email
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let archive_token = if issue.visibility == IssueVisibility::Subscribers.as_str() {
+        Some(get_or_create_archive_token(pool, row.id).await?)
+    } else {
+        None
+    };
+    Ok(Some(SubscriberLinks {
+        preference_token: row.preference_token,
+        archive_token,
+        locale: Locale::parse(&row.locale).unwrap_or_default(),
+    }))
+}
+
+async fn worker_loop<E>(
+    pool: PgPool,
+    email_client: EmailClient<E>,
+    base_url: String,
+) -> Result<(), anyhow::Error>
 where
     E: 'static + AsyncTransport + Send + Sync,
     <E as AsyncTransport>::Error: 'static + Send + Sync,
     <E as AsyncTransport>::Error: std::error::Error,
 {
     loop {
-        match try_execute_task(&pool, &email_client).await {
+        match try_execute_task(&pool, &email_client, &base_url).await {
             Ok(ExecutionOutcome::EmptyQueue) => {
                 tokio::time::sleep(Duration::from_secs(10)).await;
             }
@@ -172,5 +357,10 @@ pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), any
     let sender = SenderInfo(sender_name, sender_email);
     let email_client =
         email_client::create_email_client(configuration.email_client.clone(), sender).await;
-    worker_loop(connection_pool, email_client).await
+    worker_loop(
+        connection_pool,
+        email_client,
+        configuration.application.base_url,
+    )
+    .await
 }
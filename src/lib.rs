@@ -1,13 +1,20 @@
 extern crate core;
 
+pub mod anti_spam;
+pub mod audit;
 pub mod authentication;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
+pub mod email_domains;
+pub mod i18n;
 pub mod idempotency;
 pub mod issue_delivery_worker;
+pub mod metrics;
 pub mod routes;
+pub mod security_headers;
 pub mod session_state;
 pub mod startup;
+pub mod subscriber_data;
 pub mod telemetry;
 pub mod utils;
@@ -0,0 +1,66 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::anti_spam::SpamReason;
+
+/// Counters served at `/metrics`, in the Prometheus text format.
+/// They live in memory, so each instance reports its own and they
+/// start over on restart, as Prometheus expects.
+#[derive(Default)]
+pub struct Metrics {
+    dropped_subscriptions: [AtomicU64; SpamReason::ALL.len()],
+}
+
+impl Metrics {
+    pub fn record_dropped_subscription(&self, reason: SpamReason) {
+        self.dropped_subscriptions[reason as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn render(&self) -> String {
+        let mut body = String::new();
+        body.push_str(
+            "# HELP subscriptions_dropped_total Subscription attempts dropped as spam.\n\
+            # TYPE subscriptions_dropped_total counter\n",
+        );
+        for reason in SpamReason::ALL {
+            writeln!(
+                body,
+                r#"subscriptions_dropped_total{{reason="{}"}} {}"#,
+                reason.as_str(),
+                self.dropped_subscriptions[reason as usize].load(Ordering::Relaxed)
+            )
+            .unwrap();
+        }
+        body
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metrics;
+    use crate::anti_spam::SpamReason;
+
+    #[test]
+    fn every_reason_is_reported_even_before_it_happens() {
+        let metrics = Metrics::default();
+        let body = metrics.render();
+        for reason in SpamReason::ALL {
+            assert!(body.contains(&format!(
+                r#"subscriptions_dropped_total{{reason="{}"}} 0"#,
+                reason.as_str()
+            )));
+        }
+    }
+
+    #[test]
+    fn drops_are_counted_per_reason() {
+        let metrics = Metrics::default();
+        metrics.record_dropped_subscription(SpamReason::Honeypot);
+        metrics.record_dropped_subscription(SpamReason::Honeypot);
+        metrics.record_dropped_subscription(SpamReason::TooFast);
+        let body = metrics.render();
+        assert!(body.contains(r#"subscriptions_dropped_total{reason="honeypot"} 2"#));
+        assert!(body.contains(r#"subscriptions_dropped_total{reason="too_fast"} 1"#));
+        assert!(body.contains(r#"subscriptions_dropped_total{reason="ip_rate_limit"} 0"#));
+    }
+}
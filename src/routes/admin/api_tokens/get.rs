@@ -0,0 +1,64 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+
+use crate::authentication::{list_api_tokens, ApiScope, CsrfToken, Role, UserId};
+use crate::utils::{e500, escape_html};
+
+pub async fn api_tokens_form(
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+    role: ReqData<Role>,
+    flash_messages: IncomingFlashMessages,
+    csrf: CsrfToken,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut tokens_html = String::new();
+    for token in list_api_tokens(**user_id, &pool).await.map_err(e500)? {
+        let scopes: Vec<_> = token.scopes.iter().map(|s| s.as_str()).collect();
+        let last_used_at = token
+            .last_used_at
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_else(|| "Never".to_string());
+        writeln!(
+            tokens_html,
+            r#"<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td><form action="/admin/api-tokens/{}/revoke" method="post">{}<button type="submit">Revoke</button></form></td></tr>"#,
+            escape_html(&token.name),
+            scopes.join(", "),
+            token.created_at.to_rfc3339(),
+            last_used_at,
+            token.token_id,
+            csrf.form_field()
+        )
+        .unwrap();
+    }
+    // Only offer the scopes the user's role allows.
+    let mut scopes_html = String::new();
+    for scope in ApiScope::ALL
+        .into_iter()
+        .filter(|s| *role >= s.minimum_role())
+    {
+        writeln!(
+            scopes_html,
+            r#"<label><input type="checkbox" name="scope" value="{0}"> {0}</label><br>"#,
+            scope
+        )
+        .unwrap();
+    }
+    let html_page = include_str!("api_tokens.html")
+        .replace("{msg_html}", &msg_html)
+        .replace("{tokens_html}", &tokens_html)
+        .replace("{scopes_html}", &scopes_html)
+        .replace("{csrf_field}", &csrf.form_field());
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_page))
+}
@@ -0,0 +1,115 @@
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::audit::{record_audit_event, AuditAction, AuditContext};
+use crate::authentication::{ApiScope, Role, UserId};
+use crate::utils::{e400, e500, escape_html, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    #[serde(default, rename = "scope")]
+    scopes: Vec<String>,
+}
+
+#[tracing::instrument(
+    name = "Create an API token",
+    skip_all,
+    fields(user_id = %*user_id)
+)]
+pub async fn create_api_token(
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+    role: ReqData<Role>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    // `web::Form` cannot collect the repeated `scope` fields sent by the checkboxes.
+    let FormData { name, scopes } = serde_html_form::from_bytes(&body).map_err(e400)?;
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > 100 {
+        FlashMessage::error("The token name must be between 1 and 100 characters long.").send();
+        return Ok(see_other("/admin/api-tokens"));
+    }
+    let scopes = match scopes
+        .iter()
+        .map(|s| ApiScope::parse(s))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(scopes) if !scopes.is_empty() => scopes,
+        Ok(_) => {
+            FlashMessage::error("Pick at least one scope for the token.").send();
+            return Ok(see_other("/admin/api-tokens"));
+        }
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/api-tokens"));
+        }
+    };
+    if let Some(scope) = scopes.iter().find(|s| *role < s.minimum_role()) {
+        FlashMessage::error(format!(
+            "A {} cannot create tokens with the {} scope.",
+            *role, scope
+        ))
+        .send();
+        return Ok(see_other("/admin/api-tokens"));
+    }
+
+    let (token_id, token) =
+        crate::authentication::create_api_token(**user_id, name, &scopes, &pool)
+            .await
+            .map_err(e500)?;
+    record_audit_event(
+        pool.get_ref(),
+        &audit,
+        Some(**user_id),
+        AuditAction::ApiTokenCreated,
+        Some(&format!("api_token:{}", token_id)),
+    )
+    .await
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(include_str!("token_created.html").replace("{token}", token.expose_secret())))
+}
+
+#[tracing::instrument(
+    name = "Revoke an API token",
+    skip_all,
+    fields(user_id = %*user_id, token_id = %*token_id)
+)]
+pub async fn revoke_api_token(
+    token_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    match crate::authentication::revoke_api_token(**user_id, *token_id, &pool)
+        .await
+        .map_err(e500)?
+    {
+        Some(name) => {
+            record_audit_event(
+                pool.get_ref(),
+                &audit,
+                Some(**user_id),
+                AuditAction::ApiTokenRevoked,
+                Some(&format!("api_token:{}", token_id)),
+            )
+            .await
+            .map_err(e500)?;
+            FlashMessage::info(format!(
+                "The token {} has been revoked.",
+                escape_html(&name)
+            ))
+            .send()
+        }
+        None => FlashMessage::error("There is no such token.").send(),
+    }
+    Ok(see_other("/admin/api-tokens"))
+}
@@ -0,0 +1,152 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use sqlx::PgPool;
+
+use crate::audit::AuditAction;
+use crate::utils::{e400, e500, escape_html};
+
+const ENTRIES_PER_PAGE: i64 = 50;
+
+/// Empty fields are what an unfilled filter form submits: they match
+/// everything.
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct Filters {
+    #[serde(default)]
+    action: String,
+    #[serde(default)]
+    actor: String,
+    #[serde(default)]
+    target: String,
+    #[serde(default)]
+    since: String,
+    #[serde(default)]
+    until: String,
+    #[serde(default = "first_page")]
+    page: i64,
+}
+
+fn first_page() -> i64 {
+    1
+}
+
+fn non_empty(s: &str) -> Option<&str> {
+    let s = s.trim();
+    (!s.is_empty()).then_some(s)
+}
+
+fn parse_date(s: &str) -> Result<Option<NaiveDate>, String> {
+    non_empty(s)
+        .map(|s| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .map_err(|_| format!("{} is not a valid date.", s))
+        })
+        .transpose()
+}
+
+fn start_of_day(date: NaiveDate) -> DateTime<Utc> {
+    DateTime::from_utc(date.and_hms_opt(0, 0, 0).unwrap(), Utc)
+}
+
+pub async fn audit_log(
+    filters: web::Query<Filters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let action = non_empty(&filters.action)
+        .map(AuditAction::parse)
+        .transpose()
+        .map_err(e400)?;
+    let since = parse_date(&filters.since).map_err(e400)?.map(start_of_day);
+    // `until` is inclusive: stop at the end of that day.
+    let until = parse_date(&filters.until)
+        .map_err(e400)?
+        .map(|d| start_of_day(d) + Duration::days(1));
+    let page = filters.page.max(1);
+
+    // One more than shown, to know whether there is another page.
+    let entries = sqlx::query!(
+        r#"
+        SELECT a.occurred_at, u.username, a.actor_id, a.action, a.target, a.ip, a.user_agent
+        FROM audit_log a
+        LEFT JOIN users u ON u.user_id = a.actor_id
+        WHERE
+            ($1::text IS NULL OR a.action = $1) AND
+            ($2::text IS NULL OR u.username = $2) AND
+            ($3::text IS NULL OR a.target = $3) AND
+            ($4::timestamptz IS NULL OR a.occurred_at >= $4) AND
+            ($5::timestamptz IS NULL OR a.occurred_at < $5)
+        ORDER BY a.audit_id DESC
+        LIMIT $6 OFFSET $7
+        "#,
+        action.map(|a| a.as_str()),
+        non_empty(&filters.actor),
+        non_empty(&filters.target),
+        since,
+        until,
+        ENTRIES_PER_PAGE + 1,
+        (page - 1) * ENTRIES_PER_PAGE
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the audit log.")
+    .map_err(e500)?;
+
+    let has_more = entries.len() as i64 > ENTRIES_PER_PAGE;
+    let mut entries_html = String::new();
+    for e in entries.into_iter().take(ENTRIES_PER_PAGE as usize) {
+        let actor = match (e.username, e.actor_id) {
+            (Some(username), _) => username,
+            (None, Some(actor_id)) => actor_id.to_string(),
+            (None, None) => "-".to_string(),
+        };
+        writeln!(
+            entries_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            e.occurred_at.to_rfc3339(),
+            escape_html(&actor),
+            e.action,
+            escape_html(e.target.as_deref().unwrap_or("")),
+            escape_html(e.ip.as_deref().unwrap_or("")),
+            escape_html(e.user_agent.as_deref().unwrap_or(""))
+        )
+        .unwrap();
+    }
+    let more_html = if has_more {
+        let next = Filters {
+            page: page + 1,
+            ..filters.0.clone()
+        };
+        format!(
+            r#"<p><a href="/admin/audit-log?{}">Older entries -&gt;</a></p>"#,
+            escape_html(&serde_html_form::to_string(&next).map_err(e500)?)
+        )
+    } else {
+        String::new()
+    };
+    let mut actions_html = String::new();
+    for a in AuditAction::ALL {
+        let selected = if Some(a) == action { " selected" } else { "" };
+        writeln!(
+            actions_html,
+            r#"<option value="{0}"{1}>{0}</option>"#,
+            a, selected
+        )
+        .unwrap();
+    }
+
+    let html_page = include_str!("audit_log.html")
+        .replace("{actions_html}", &actions_html)
+        .replace("{actor}", &escape_html(&filters.actor))
+        .replace("{target}", &escape_html(&filters.target))
+        .replace("{since}", &escape_html(&filters.since))
+        .replace("{until}", &escape_html(&filters.until))
+        .replace("{entries_html}", &entries_html)
+        .replace("{more_html}", &more_html);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_page))
+}
@@ -1,4 +1,4 @@
-use crate::authentication::UserId;
+use crate::authentication::{CsrfToken, UserId};
 use crate::utils::e500;
 use actix_web::http::header::ContentType;
 
@@ -11,17 +11,20 @@ use uuid::Uuid;
 pub async fn admin_dashboard(
     pool: web::Data<PgPool>,
     user_id: ReqData<UserId>,
+    csrf: CsrfToken,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     let username = get_username(*user_id, &pool).await.map_err(e500)?;
-    Ok(HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(include_str!("dashboard.html").replace("{username}", &username)))
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        include_str!("dashboard.html")
+            .replace("{username}", &username)
+            .replace("{csrf_field}", &csrf.form_field()),
+    ))
 }
 
 pub async fn get_username(user_id: Uuid, pool: &PgPool) -> Result<String, anyhow::Error> {
     let row = sqlx::query!(
-        r#"SELECT username
+        r#"SELECT username as "username!"
         FROM users
         WHERE user_id = $1"#,
         user_id
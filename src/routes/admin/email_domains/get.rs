@@ -0,0 +1,47 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+
+use crate::authentication::CsrfToken;
+use crate::email_domains::{list_domain_rules, DomainRuleKind};
+use crate::utils::e500;
+
+pub async fn email_domains_form(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf: CsrfToken,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut rules_html = String::new();
+    for rule in list_domain_rules(&pool).await.map_err(e500)? {
+        writeln!(
+            rules_html,
+            r#"<tr><td>{}</td><td>{}</td><td>{}</td><td><form action="/admin/email-domains/remove" method="post">{}<input hidden type="text" name="domain" value="{}"><button type="submit">Remove</button></form></td></tr>"#,
+            rule.domain,
+            rule.kind,
+            rule.created_at.format("%Y-%m-%d %H:%M"),
+            csrf.form_field(),
+            rule.domain
+        )
+        .unwrap();
+    }
+    let mut kinds_html = String::new();
+    for kind in DomainRuleKind::ALL {
+        writeln!(kinds_html, r#"<option value="{0}">{0}</option>"#, kind).unwrap();
+    }
+    let html_page = include_str!("email_domains.html")
+        .replace("{msg_html}", &msg_html)
+        .replace("{rules_html}", &rules_html)
+        .replace("{kinds_html}", &kinds_html)
+        .replace("{csrf_field}", &csrf.form_field());
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_page))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::email_domains_form;
+pub use post::{remove_email_domain_rule, save_email_domain_rule};
@@ -0,0 +1,119 @@
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::audit::{record_audit_event, AuditAction, AuditContext};
+use crate::authentication::UserId;
+use crate::email_domains::{remove_domain_rule, save_domain_rule, DomainRuleKind, EmailDomain};
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct RuleForm {
+    domain: String,
+    kind: String,
+}
+
+#[tracing::instrument(name = "Save an email domain rule", skip_all, fields(domain = %form.domain))]
+pub async fn save_email_domain_rule(
+    form: web::Form<RuleForm>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (domain, kind) = match (
+        EmailDomain::parse(&form.domain),
+        DomainRuleKind::parse(&form.kind),
+    ) {
+        (Ok(domain), Ok(kind)) => (domain, kind),
+        (Err(e), _) | (_, Err(e)) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/email-domains"));
+        }
+    };
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    save_domain_rule(&mut transaction, &domain, kind, **user_id)
+        .await
+        .map_err(e500)?;
+    record_audit_event(
+        &mut transaction,
+        &audit,
+        Some(**user_id),
+        match kind {
+            DomainRuleKind::Allow => AuditAction::EmailDomainAllowed,
+            DomainRuleKind::Block => AuditAction::EmailDomainBlocked,
+        },
+        Some(&format!("email_domain:{}", domain.as_ref())),
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the email domain rule")
+        .map_err(e500)?;
+    let verb = match kind {
+        DomainRuleKind::Allow => "allowed",
+        DomainRuleKind::Block => "blocked",
+    };
+    FlashMessage::info(format!("{} is now {}.", domain.as_ref(), verb)).send();
+    Ok(see_other("/admin/email-domains"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RemoveForm {
+    domain: String,
+}
+
+#[tracing::instrument(name = "Remove an email domain rule", skip_all, fields(domain = %form.domain))]
+pub async fn remove_email_domain_rule(
+    form: web::Form<RemoveForm>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let domain = match EmailDomain::parse(&form.domain) {
+        Ok(domain) => domain,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/email-domains"));
+        }
+    };
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    if !remove_domain_rule(&mut transaction, &domain)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error(format!("There is no rule for {}.", domain.as_ref())).send();
+        return Ok(see_other("/admin/email-domains"));
+    }
+    record_audit_event(
+        &mut transaction,
+        &audit,
+        Some(**user_id),
+        AuditAction::EmailDomainRuleRemoved,
+        Some(&format!("email_domain:{}", domain.as_ref())),
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the removal of the email domain rule")
+        .map_err(e500)?;
+    FlashMessage::info(format!(
+        "The rule for {} has been removed.",
+        domain.as_ref()
+    ))
+    .send();
+    Ok(see_other("/admin/email-domains"))
+}
@@ -0,0 +1,67 @@
+use actix_web::{web, HttpResponse};
+use async_stream::try_stream;
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::ExportFormat;
+use crate::utils::e500;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParameters {
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+#[derive(serde::Serialize)]
+struct DeliveryRecord {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    outcome: String,
+    recorded_at: DateTime<Utc>,
+}
+
+const COLUMNS: &[&str] = &[
+    "newsletter_issue_id",
+    "subscriber_email",
+    "outcome",
+    "recorded_at",
+];
+
+#[tracing::instrument(name = "Export issue deliveries", skip(parameters, pool))]
+pub async fn export_issue_deliveries(
+    issue_id: web::Path<Uuid>,
+    parameters: web::Query<QueryParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let format = parameters.format;
+    let header = format.header(COLUMNS).map_err(e500)?;
+    let pool = pool.into_inner();
+
+    let body = try_stream! {
+        if let Some(header) = header {
+            yield header;
+        }
+        let mut rows = sqlx::query_as!(
+            DeliveryRecord,
+            r#"
+            SELECT newsletter_issue_id, subscriber_email, outcome, recorded_at
+            FROM issue_delivery_history
+            WHERE newsletter_issue_id = $1
+            ORDER BY recorded_at
+            "#,
+            issue_id
+        )
+        .fetch(pool.as_ref());
+        while let Some(record) = rows.try_next().await? {
+            yield format.encode(&record)?;
+        }
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(format.content_disposition(&format!("deliveries-{issue_id}")))
+        .streaming::<_, anyhow::Error>(body))
+}
@@ -0,0 +1,72 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::web::Bytes;
+use anyhow::Context;
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn content_disposition(&self, name: &str) -> ContentDisposition {
+        let extension = match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        };
+        ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!("{name}.{extension}"))],
+        }
+    }
+
+    /// The line to emit before any record, if the format has one.
+    pub fn header(&self, columns: &[&str]) -> Result<Option<Bytes>, anyhow::Error> {
+        match self {
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(vec![]);
+                writer
+                    .write_record(columns)
+                    .context("Failed to write the CSV header")?;
+                let line = writer
+                    .into_inner()
+                    .context("Failed to flush the CSV writer")?;
+                Ok(Some(Bytes::from(line)))
+            }
+            ExportFormat::Ndjson => Ok(None),
+        }
+    }
+
+    /// Encodes a single record as one line of output.
+    pub fn encode<T: serde::Serialize>(&self, record: &T) -> Result<Bytes, anyhow::Error> {
+        match self {
+            ExportFormat::Csv => {
+                let mut writer = csv::WriterBuilder::new()
+                    .has_headers(false)
+                    .from_writer(vec![]);
+                writer
+                    .serialize(record)
+                    .context("Failed to serialize record as CSV")?;
+                let line = writer
+                    .into_inner()
+                    .context("Failed to flush the CSV writer")?;
+                Ok(Bytes::from(line))
+            }
+            ExportFormat::Ndjson => {
+                let mut line =
+                    serde_json::to_vec(record).context("Failed to serialize record as JSON")?;
+                line.push(b'\n');
+                Ok(Bytes::from(line))
+            }
+        }
+    }
+}
@@ -0,0 +1,7 @@
+mod deliveries;
+mod format;
+mod subscriptions;
+
+pub use deliveries::export_issue_deliveries;
+pub use format::ExportFormat;
+pub use subscriptions::export_subscriptions;
@@ -0,0 +1,74 @@
+use actix_web::{web, HttpResponse};
+use async_stream::try_stream;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures_util::TryStreamExt;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::ExportFormat;
+use crate::utils::e500;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParameters {
+    #[serde(default)]
+    format: ExportFormat,
+    status: Option<String>,
+    subscribed_from: Option<NaiveDate>,
+    subscribed_until: Option<NaiveDate>,
+}
+
+#[derive(serde::Serialize)]
+struct SubscriberRecord {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+const COLUMNS: &[&str] = &["id", "email", "name", "status", "subscribed_at"];
+
+#[tracing::instrument(name = "Export subscriptions", skip_all)]
+pub async fn export_subscriptions(
+    parameters: web::Query<QueryParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let QueryParameters {
+        format,
+        status,
+        subscribed_from,
+        subscribed_until,
+    } = parameters.into_inner();
+    let header = format.header(COLUMNS).map_err(e500)?;
+    let pool = pool.into_inner();
+
+    let body = try_stream! {
+        if let Some(header) = header {
+            yield header;
+        }
+        let mut rows = sqlx::query_as!(
+            SubscriberRecord,
+            r#"
+            SELECT id, email, name, status, subscribed_at
+            FROM subscriptions
+            WHERE
+                ($1::text IS NULL OR status = $1) AND
+                ($2::date IS NULL OR subscribed_at >= $2::date) AND
+                ($3::date IS NULL OR subscribed_at < $3::date + 1)
+            ORDER BY subscribed_at
+            "#,
+            status,
+            subscribed_from,
+            subscribed_until
+        )
+        .fetch(pool.as_ref());
+        while let Some(record) = rows.try_next().await? {
+            yield format.encode(&record)?;
+        }
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(format.content_disposition("subscriptions"))
+        .streaming::<_, anyhow::Error>(body))
+}
@@ -0,0 +1,65 @@
+use std::fmt::Write;
+
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::authentication::CsrfToken;
+use crate::domain::MailingList;
+use crate::utils::{e500, escape_html};
+
+pub async fn manage_lists_form(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf: CsrfToken,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let lists = sqlx::query!(
+        r#"
+        SELECT l.slug, l.name, COUNT(s.subscriber_id) as "confirmed!"
+        FROM lists l
+        LEFT JOIN list_subscriptions s
+            ON s.list_id = l.list_id AND s.status = 'confirmed'
+        GROUP BY l.list_id
+        ORDER BY l.created_at
+        "#
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the mailing lists")
+    .map_err(e500)?;
+    let mut lists_html = String::new();
+    for l in lists {
+        writeln!(
+            lists_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            l.slug,
+            escape_html(&l.name),
+            l.confirmed
+        )
+        .unwrap();
+    }
+    let html_page = include_str!("lists.html")
+        .replace("{msg_html}", &msg_html)
+        .replace("{lists_html}", &lists_html)
+        .replace("{csrf_field}", &csrf.form_field());
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_page))
+}
+
+pub async fn get_lists(pool: &PgPool) -> Result<Vec<MailingList>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        MailingList,
+        r#"SELECT list_id, slug, name FROM lists ORDER BY created_at"#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the mailing lists")?;
+    Ok(lists)
+}
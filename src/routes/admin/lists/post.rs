@@ -0,0 +1,56 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::ListSlug;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    slug: String,
+    name: String,
+}
+
+#[tracing::instrument(name = "Create a mailing list", skip(form, pool))]
+pub async fn create_list(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData { slug, name } = form.0;
+    let slug = match ListSlug::parse(slug) {
+        Ok(slug) => slug,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/lists"));
+        }
+    };
+    if name.trim().is_empty() {
+        FlashMessage::error("The list name cannot be empty.").send();
+        return Ok(see_other("/admin/lists"));
+    }
+
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, slug, name, created_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        slug.as_ref(),
+        name.trim()
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the new mailing list")
+    .map_err(e500)?
+    .rows_affected();
+
+    if n_inserted_rows == 0 {
+        FlashMessage::error(format!("A list named {} already exists.", slug.as_ref())).send();
+    } else {
+        FlashMessage::info(format!("The list {} has been created.", slug.as_ref())).send();
+    }
+    Ok(see_other("/admin/lists"))
+}
@@ -0,0 +1,39 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+
+use crate::authentication::{CsrfToken, LoginThrottle};
+use crate::utils::{e500, escape_html};
+
+pub async fn lockouts_form(
+    throttle: web::Data<LoginThrottle>,
+    flash_messages: IncomingFlashMessages,
+    csrf: CsrfToken,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut lockouts_html = String::new();
+    for lockout in throttle.lockouts().await.map_err(e500)? {
+        writeln!(
+            lockouts_html,
+            r#"<tr><td>{} {}</td><td>{}</td><td><form action="/admin/lockouts/unlock" method="post">{}<input hidden type="text" name="subject" value="{}"><button type="submit">Unlock</button></form></td></tr>"#,
+            lockout.subject.kind(),
+            escape_html(lockout.subject.value()),
+            lockout.remaining.as_secs().div_ceil(60),
+            csrf.form_field(),
+            escape_html(&lockout.subject.to_string())
+        )
+        .unwrap();
+    }
+    let html_page = include_str!("lockouts.html")
+        .replace("{msg_html}", &msg_html)
+        .replace("{lockouts_html}", &lockouts_html);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_page))
+}
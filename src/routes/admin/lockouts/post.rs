@@ -0,0 +1,47 @@
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+use crate::audit::{record_audit_event, AuditAction, AuditContext};
+use crate::authentication::{LockoutSubject, LoginThrottle, UserId};
+use crate::utils::{e500, escape_html, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    subject: String,
+}
+
+#[tracing::instrument(name = "Lift a login lockout", skip_all, fields(subject = %form.subject))]
+pub async fn unlock(
+    form: web::Form<FormData>,
+    throttle: web::Data<LoginThrottle>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subject = match LockoutSubject::parse(&form.subject) {
+        Ok(subject) => subject,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/lockouts"));
+        }
+    };
+    throttle.unlock(&subject).await.map_err(e500)?;
+    record_audit_event(
+        pool.get_ref(),
+        &audit,
+        Some(**user_id),
+        AuditAction::LockoutLifted,
+        Some(&format!("lockout:{}", subject)),
+    )
+    .await
+    .map_err(e500)?;
+    FlashMessage::info(format!(
+        "The {} {} has been unlocked.",
+        subject.kind(),
+        escape_html(subject.value())
+    ))
+    .send();
+    Ok(see_other("/admin/lockouts"))
+}
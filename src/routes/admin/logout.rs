@@ -1,10 +1,37 @@
-use actix_web::HttpResponse;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
 
-use crate::{session_state::TypedSession, utils::see_other};
+use crate::audit::{record_audit_event, AuditAction, AuditContext};
+use crate::authentication::{revoke_session, SessionId, UserId};
+use crate::i18n::Locale;
+use crate::{
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
 
-pub async fn log_out(typed_session: TypedSession) -> Result<HttpResponse, actix_web::Error> {
+pub async fn log_out(
+    typed_session: TypedSession,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+    session_id: ReqData<SessionId>,
+    audit: AuditContext,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
     typed_session.logout();
-    FlashMessage::info("You have successfully logged out.").send();
+    revoke_session(**user_id, **session_id, &pool)
+        .await
+        .map_err(e500)?;
+    record_audit_event(
+        pool.get_ref(),
+        &audit,
+        Some(**user_id),
+        AuditAction::Logout,
+        None,
+    )
+    .await
+    .map_err(e500)?;
+    FlashMessage::info(locale.text("login.logged_out")).send();
     Ok(see_other("/login"))
 }
@@ -1,9 +1,29 @@
+mod api_tokens;
+mod audit_log;
 mod dashboard;
+mod email_domains;
+mod export;
+mod lists;
+mod lockouts;
 mod logout;
 mod newsletter;
 mod password;
+mod sessions;
+mod subscriber_data;
+mod two_factor;
+mod users;
 
-pub use dashboard::admin_dashboard;
+pub use api_tokens::*;
+pub use audit_log::*;
+pub use dashboard::{admin_dashboard, get_username};
+pub use email_domains::*;
+pub use export::*;
+pub use lists::*;
+pub use lockouts::*;
 pub use logout::log_out;
 pub use newsletter::*;
 pub use password::*;
+pub use sessions::*;
+pub use subscriber_data::*;
+pub use two_factor::*;
+pub use users::*;
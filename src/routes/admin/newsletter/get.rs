@@ -1,19 +1,45 @@
 use std::fmt::Write;
 
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::{http::header::ContentType, web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+
+use crate::authentication::CsrfToken;
+use crate::domain::DEFAULT_LIST_SLUG;
+use crate::routes::admin::lists::get_lists;
+use crate::utils::{e500, escape_html};
 
 pub async fn publish_newsletter_form(
+    pool: web::Data<PgPool>,
     flash_messages: IncomingFlashMessages,
+    csrf: CsrfToken,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut msg_html = String::new();
     for m in flash_messages.iter() {
         writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
+    let mut lists_html = String::new();
+    for list in get_lists(&pool).await.map_err(e500)? {
+        let checked = if list.slug == DEFAULT_LIST_SLUG {
+            " checked"
+        } else {
+            ""
+        };
+        writeln!(
+            lists_html,
+            r#"<label><input type="checkbox" name="list_id" value="{}"{}> {}</label><br>"#,
+            list.list_id,
+            checked,
+            escape_html(&list.name)
+        )
+        .unwrap();
+    }
     let idempotency_key = uuid::Uuid::new_v4().to_string();
     let html_page = include_str!("newsletter.html")
         .replace("{msg_html}", &msg_html)
-        .replace("{idempotency_key}", &idempotency_key);
+        .replace("{lists_html}", &lists_html)
+        .replace("{idempotency_key}", &idempotency_key)
+        .replace("{csrf_field}", &csrf.form_field());
 
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
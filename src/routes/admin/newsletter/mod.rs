@@ -2,4 +2,7 @@ mod get;
 mod post;
 
 pub use get::publish_newsletter_form;
-pub use post::publish_newsletter;
+pub use post::{
+    enqueue_delivery_tasks, get_default_list_id, insert_newsletter_issue, publish_newsletter,
+    unknown_list_ids,
+};
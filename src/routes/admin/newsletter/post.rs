@@ -1,17 +1,19 @@
-use std::fmt::Formatter;
+use std::collections::HashSet;
 
 use actix_web::web::ReqData;
 use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
 
-use sqlx::{PgPool, Postgres, Transaction};
+use sqlx::{PgPool, Postgres, QueryBuilder, Transaction};
 use uuid::Uuid;
 
+use crate::audit::{record_audit_event, AuditAction, AuditContext};
 use crate::authentication::UserId;
-use crate::domain::SubscriberEmail;
+use crate::domain::{issue_slug, IssueVisibility, ListSlug, Segment, DEFAULT_LIST_SLUG};
 
 use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::routes::get_list_by_slug;
 
 use crate::utils::{e400, e500, see_other};
 
@@ -21,6 +23,12 @@ pub struct FormData {
     html_content: String,
     text_content: String,
     idempotency_key: String,
+    #[serde(default, rename = "list_id")]
+    list_ids: Vec<Uuid>,
+    #[serde(default)]
+    segment: String,
+    #[serde(default)]
+    visibility: Option<String>,
 }
 
 #[tracing::instrument(
@@ -29,18 +37,54 @@ pub struct FormData {
     fields(user_id=%*user_id)
 )]
 pub async fn publish_newsletter<T>(
-    form: web::Form<FormData>,
+    body: web::Bytes,
     pool: web::Data<PgPool>,
     user_id: ReqData<UserId>,
+    audit: AuditContext,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
+    // `web::Form` cannot collect the repeated `list_id` fields sent by the checkboxes.
     let FormData {
         title,
         text_content,
         html_content,
         idempotency_key,
-    } = form.0;
+        list_ids,
+        segment,
+        visibility,
+    } = serde_html_form::from_bytes(&body).map_err(e400)?;
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let segment = if segment.trim().is_empty() {
+        None
+    } else {
+        match Segment::parse(&segment) {
+            Ok(segment) => Some(segment),
+            Err(e) => {
+                FlashMessage::error(format!("Invalid segment: {}", e)).send();
+                return Ok(see_other("/admin/newsletters"));
+            }
+        }
+    };
+    let visibility = match visibility.as_deref().map(IssueVisibility::parse) {
+        None => IssueVisibility::default(),
+        Some(Ok(visibility)) => visibility,
+        Some(Err(e)) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+    if list_ids.is_empty() {
+        FlashMessage::error("Pick at least one list to send the issue to.").send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+    if !unknown_list_ids(&pool, &list_ids)
+        .await
+        .map_err(e500)?
+        .is_empty()
+    {
+        FlashMessage::error("Some of the selected lists do not exist.").send();
+        return Ok(see_other("/admin/newsletters"));
+    }
     let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
         .await
         .map_err(e500)?
@@ -51,14 +95,29 @@ pub async fn publish_newsletter<T>(
             return Ok(saved_response);
         }
     };
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
-        .await
-        .context("Failed to store newsletter issue details")
-        .map_err(e500)?;
-    enqueue_delivery_tasks(&mut transaction, issue_id)
+    let (issue_id, _) = insert_newsletter_issue(
+        &mut transaction,
+        &title,
+        &text_content,
+        &html_content,
+        visibility,
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
+    enqueue_delivery_tasks(&mut transaction, issue_id, &list_ids, segment.as_ref())
         .await
         .context("Faiedl to enqueue delivery tasks")
         .map_err(e500)?;
+    record_audit_event(
+        &mut transaction,
+        &audit,
+        Some(*user_id),
+        AuditAction::NewsletterPublished,
+        Some(&format!("issue:{}", issue_id)),
+    )
+    .await
+    .map_err(e500)?;
 
     let response = see_other("/admin/newsletters");
     let response = save_response(transaction, &idempotency_key, *user_id, response)
@@ -75,59 +134,138 @@ fn success_message() -> FlashMessage {
     )
 }
 
-impl std::fmt::Display for SubscriberEmail {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        self.0.fmt(f)
-    }
+/// The list issues go to when no list was picked.
+pub async fn get_default_list_id(pool: &PgPool) -> Result<Uuid, anyhow::Error> {
+    let default_list = ListSlug::parse(DEFAULT_LIST_SLUG.into()).map_err(anyhow::Error::msg)?;
+    let default_list = get_list_by_slug(pool, &default_list)
+        .await
+        .context("Failed to look up the default list")?
+        .ok_or_else(|| anyhow::anyhow!("The default list does not exist"))?;
+    Ok(default_list.list_id)
+}
+
+/// The ids in `list_ids` that do not belong to any list.
+pub async fn unknown_list_ids(
+    pool: &PgPool,
+    list_ids: &[Uuid],
+) -> Result<Vec<Uuid>, anyhow::Error> {
+    let unknown = sqlx::query!(
+        r#"
+        SELECT id as "id!"
+        FROM unnest($1::uuid[]) AS id
+        WHERE NOT EXISTS (SELECT 1 FROM lists WHERE list_id = id)
+        "#,
+        list_ids
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to look up the selected lists")?
+    .into_iter()
+    .map(|r| r.id)
+    .collect();
+    Ok(unknown)
 }
 
+/// Stores the issue under the first free slug made from its title, and
+/// returns its id and slug.
 #[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
+pub async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
     title: &str,
     text_content: &str,
     html_content: &str,
-) -> Result<Uuid, sqlx::Error> {
+    visibility: IssueVisibility,
+) -> Result<(Uuid, String), sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
-    sqlx::query!(
-        r#"
-        INSERT INTO newsletter_issues (
+    let base_slug = issue_slug(title);
+    loop {
+        let slug = next_free_slug(transaction, &base_slug).await?;
+        // Another publication may take the slug before we do: try the next one.
+        let inserted = sqlx::query!(
+            r#"
+            INSERT INTO newsletter_issues (
+                newsletter_issue_id,
+                title,
+                text_content,
+                html_content,
+                published_at,
+                slug,
+                visibility
+            )
+            VALUES ($1, $2, $3, $4, now(), $5, $6)
+            ON CONFLICT (slug) DO NOTHING
+            "#,
             newsletter_issue_id,
             title,
             text_content,
             html_content,
-            published_at
+            slug,
+            visibility.as_str()
         )
-        VALUES ($1, $2, $3, $4, now())
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+        if inserted == 1 {
+            return Ok((newsletter_issue_id, slug));
+        }
+    }
+}
+
+/// `base_slug` itself if free, otherwise `base_slug-2`, `base_slug-3`...
+async fn next_free_slug(
+    transaction: &mut Transaction<'_, Postgres>,
+    base_slug: &str,
+) -> Result<String, sqlx::Error> {
+    let taken: HashSet<String> = sqlx::query!(
+        r#"
+        SELECT slug FROM newsletter_issues
+        WHERE slug = $1 OR slug LIKE $1 || '-%'
         "#,
-        newsletter_issue_id,
-        title,
-        text_content,
-        html_content,
+        base_slug
     )
-    .execute(transaction)
-    .await?;
-    Ok(newsletter_issue_id)
+    .fetch_all(&mut *transaction)
+    .await?
+    .into_iter()
+    .map(|r| r.slug)
+    .collect();
+    let slug = std::iter::once(base_slug.to_string())
+        .chain((2..).map(|n| format!("{}-{}", base_slug, n)))
+        .find(|slug| !taken.contains(slug))
+        .unwrap();
+    Ok(slug)
 }
 
 #[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
+pub async fn enqueue_delivery_tasks(
     transaction: &mut Transaction<'_, Postgres>,
     newsletter_issue_id: Uuid,
+    list_ids: &[Uuid],
+    segment: Option<&Segment>,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query!(
+    let mut query = QueryBuilder::new(
         r#"
         INSERT INTO issue_delivery_queue (
             newsletter_issue_id,
             subscriber_email
         )
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#,
-        newsletter_issue_id
-    )
-    .execute(transaction)
-    .await?;
+        SELECT DISTINCT "#,
+    );
+    query
+        .push_bind(newsletter_issue_id)
+        .push(
+            r#"::uuid, s.email
+        FROM subscriptions s
+        JOIN list_subscriptions l ON l.subscriber_id = s.id
+        WHERE
+            l.status = 'confirmed' AND
+            l.list_id = ANY("#,
+        )
+        .push_bind(list_ids.to_vec())
+        .push(")");
+    if let Some(segment) = segment {
+        query.push(" AND ");
+        segment.push_sql(&mut query);
+    }
+    query.build().execute(transaction).await?;
     Ok(())
 }
@@ -4,14 +4,22 @@ use actix_web::http::header::ContentType;
 use actix_web::HttpResponse;
 use actix_web_flash_messages::IncomingFlashMessages;
 
+use crate::authentication::CsrfToken;
+use crate::i18n::Locale;
+
 pub async fn change_password_form(
     flash_messages: IncomingFlashMessages,
+    csrf: CsrfToken,
+    locale: Locale,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut msg_html = String::new();
     for m in flash_messages.iter() {
         writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
-    let html_page = include_str!("password_reset.html").replace("{msg}", &msg_html);
+    let html_page = locale
+        .localize(include_str!("password_reset.html"))
+        .replace("{msg}", &msg_html)
+        .replace("{csrf_field}", &csrf.form_field());
 
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
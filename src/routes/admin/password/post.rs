@@ -4,7 +4,11 @@ use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
 
 use crate::{
-    authentication::{validate_credentials, AuthError, Credentials, UserId},
+    audit::{record_audit_event, AuditAction, AuditContext},
+    authentication::{
+        revoke_other_sessions, validate_credentials, AuthError, Credentials, SessionId, UserId,
+    },
+    i18n::Locale,
     routes::admin::dashboard::get_username,
     utils::{e500, see_other},
 };
@@ -19,14 +23,14 @@ pub struct FormData {
 pub async fn change_password(
     form: web::Form<FormData>,
     user_id: web::ReqData<UserId>,
+    session_id: web::ReqData<SessionId>,
     pool: web::Data<PgPool>,
+    audit: AuditContext,
+    locale: Locale,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
-        FlashMessage::error(
-            "You entered two different new passwords - the field values must match.",
-        )
-        .send();
+        FlashMessage::error(locale.text("password.mismatch")).send();
         return Ok(see_other("/admin/password"));
     }
 
@@ -39,7 +43,7 @@ pub async fn change_password(
     if let Err(e) = validate_credentials(credentials, &pool).await {
         return match e {
             AuthError::InvalidCredentials(_) => {
-                FlashMessage::error("The current password is incorrect.").send();
+                FlashMessage::error(locale.text("password.current_incorrect")).send();
                 Ok(see_other("/admin/password"))
             }
             AuthError::UnexpectedError(_) => Err(e500(e)),
@@ -49,6 +53,20 @@ pub async fn change_password(
     crate::authentication::change_password(*user_id, form.0.new_password, &pool)
         .await
         .map_err(e500)?;
-    FlashMessage::error("Your password has been changed.").send();
+    // Whoever may have known the old password is logged out everywhere;
+    // only the session making the change survives.
+    revoke_other_sessions(*user_id, **session_id, &pool)
+        .await
+        .map_err(e500)?;
+    record_audit_event(
+        pool.get_ref(),
+        &audit,
+        Some(*user_id),
+        AuditAction::PasswordChanged,
+        None,
+    )
+    .await
+    .map_err(e500)?;
+    FlashMessage::error(locale.text("password.changed")).send();
     Ok(see_other("/admin/password"))
 }
@@ -0,0 +1,58 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+
+use crate::authentication::{list_sessions, CsrfToken, SessionId, UserId};
+use crate::configuration::SessionSettings;
+use crate::utils::{e500, escape_html};
+
+pub async fn sessions_form(
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+    session_id: ReqData<SessionId>,
+    settings: web::Data<SessionSettings>,
+    flash_messages: IncomingFlashMessages,
+    csrf: CsrfToken,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let mut sessions_html = String::new();
+    for session in list_sessions(**user_id, &settings, &pool)
+        .await
+        .map_err(e500)?
+    {
+        let action_html = if session.session_id == **session_id {
+            "This session".to_string()
+        } else {
+            format!(
+                r#"<form action="/admin/sessions/{}/revoke" method="post">{}<button type="submit">Log out</button></form>"#,
+                session.session_id,
+                csrf.form_field()
+            )
+        };
+        writeln!(
+            sessions_html,
+            r#"<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            session.created_at.to_rfc3339(),
+            session.last_seen_at.to_rfc3339(),
+            escape_html(&session.ip),
+            escape_html(session.user_agent.as_deref().unwrap_or("")),
+            action_html
+        )
+        .unwrap();
+    }
+    let html_page = include_str!("sessions.html")
+        .replace("{msg_html}", &msg_html)
+        .replace("{sessions_html}", &sessions_html)
+        .replace("{csrf_field}", &csrf.form_field());
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_page))
+}
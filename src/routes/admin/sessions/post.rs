@@ -0,0 +1,69 @@
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::audit::{record_audit_event, AuditAction, AuditContext};
+use crate::authentication::{SessionId, UserId};
+use crate::utils::{e500, see_other};
+
+#[tracing::instrument(
+    name = "Revoke a session",
+    skip_all,
+    fields(user_id = %*user_id, session_id = %*target)
+)]
+pub async fn revoke_session(
+    target: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    if crate::authentication::revoke_session(**user_id, *target, &pool)
+        .await
+        .map_err(e500)?
+    {
+        record_audit_event(
+            pool.get_ref(),
+            &audit,
+            Some(**user_id),
+            AuditAction::SessionRevoked,
+            Some(&format!("session:{}", target)),
+        )
+        .await
+        .map_err(e500)?;
+        FlashMessage::info("The session has been logged out.").send();
+    } else {
+        FlashMessage::error("There is no such session.").send();
+    }
+    Ok(see_other("/admin/sessions"))
+}
+
+#[tracing::instrument(
+    name = "Revoke the other sessions",
+    skip_all,
+    fields(user_id = %*user_id)
+)]
+pub async fn revoke_other_sessions(
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+    session_id: ReqData<SessionId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let revoked = crate::authentication::revoke_other_sessions(**user_id, **session_id, &pool)
+        .await
+        .map_err(e500)?;
+    if revoked > 0 {
+        record_audit_event(
+            pool.get_ref(),
+            &audit,
+            Some(**user_id),
+            AuditAction::SessionRevoked,
+            Some(&format!("user:{}", **user_id)),
+        )
+        .await
+        .map_err(e500)?;
+    }
+    FlashMessage::info(format!("{} other sessions have been logged out.", revoked)).send();
+    Ok(see_other("/admin/sessions"))
+}
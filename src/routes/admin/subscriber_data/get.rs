@@ -0,0 +1,22 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::HttpResponse;
+use actix_web_flash_messages::IncomingFlashMessages;
+
+use crate::authentication::CsrfToken;
+
+pub async fn subscriber_data_form(
+    flash_messages: IncomingFlashMessages,
+    csrf: CsrfToken,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        include_str!("subscriber_data.html")
+            .replace("{msg_html}", &msg_html)
+            .replace("{csrf_field}", &csrf.form_field()),
+    ))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::subscriber_data_form;
+pub use post::{erase_subscriber, export_subscriber};
@@ -0,0 +1,87 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+use crate::audit::{record_audit_event, AuditAction, AuditContext};
+use crate::authentication::UserId;
+use crate::domain::SubscriberEmail;
+use crate::email_domains::EmailDomainPolicy;
+use crate::subscriber_data::{erase_subscriber_data, export_subscriber_data};
+use crate::utils::{e500, escape_html, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+/// The address typed in, merged the way subscribers' addresses are, so that
+/// admins can type it as the subscriber did.
+fn lookup_address(email: &str, domain_policy: &EmailDomainPolicy) -> String {
+    SubscriberEmail::parse(email.to_string())
+        .map(|e| domain_policy.canonical(e).to_string())
+        .unwrap_or_else(|_| email.to_string())
+}
+
+/// A POST, and without the address in its span, so that the address does
+/// not end up in logs that outlive an erasure.
+#[tracing::instrument(name = "Export a subscriber's data", skip_all)]
+pub async fn export_subscriber(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    domain_policy: web::Data<EmailDomainPolicy>,
+    user_id: ReqData<UserId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = lookup_address(&form.email, &domain_policy);
+    let export = export_subscriber_data(&pool, &email).await.map_err(e500)?;
+    match export {
+        Some(export) => {
+            record_audit_event(
+                pool.get_ref(),
+                &audit,
+                Some(**user_id),
+                AuditAction::SubscriberExported,
+                Some(&format!("subscriber:{}", export.subscriber_id())),
+            )
+            .await
+            .map_err(e500)?;
+            Ok(HttpResponse::Ok()
+                .insert_header(ContentDisposition {
+                    disposition: DispositionType::Attachment,
+                    parameters: vec![DispositionParam::Filename("subscriber.json".into())],
+                })
+                .json(export))
+        }
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+#[tracing::instrument(name = "Erase a subscriber's data", skip_all)]
+pub async fn erase_subscriber(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    domain_policy: web::Data<EmailDomainPolicy>,
+    user_id: ReqData<UserId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = lookup_address(&form.email, &domain_policy);
+    let erased = erase_subscriber_data(&pool, &email, &audit, Some(**user_id))
+        .await
+        .map_err(e500)?;
+    if erased.is_some() {
+        FlashMessage::info(format!(
+            "All data held about {} has been erased.",
+            escape_html(&email)
+        ))
+        .send();
+    } else {
+        FlashMessage::error(format!(
+            "There is no subscriber using {}.",
+            escape_html(&email)
+        ))
+        .send();
+    }
+    Ok(see_other("/admin/subscribers"))
+}
@@ -0,0 +1,74 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+
+use crate::authentication::{
+    generate_totp_secret, provisioning_qr_code, totp, two_factor_enabled, CsrfToken, UserId,
+};
+use crate::routes::admin::dashboard::get_username;
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+
+pub async fn two_factor_settings(
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+    csrf: CsrfToken,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let html_page = if two_factor_enabled(*user_id, &pool).await.map_err(e500)? {
+        let recovery_codes = sqlx::query!(
+            r#"
+            SELECT COUNT(*) as "count!"
+            FROM recovery_codes
+            WHERE user_id = $1 AND used_at IS NULL
+            "#,
+            *user_id
+        )
+        .fetch_one(pool.get_ref())
+        .await
+        .context("Failed to count the unused recovery codes")
+        .map_err(e500)?
+        .count;
+        include_str!("enabled.html")
+            .replace("{msg_html}", &msg_html)
+            .replace("{recovery_codes}", &recovery_codes.to_string())
+            .replace("{csrf_field}", &csrf.form_field())
+    } else {
+        // The secret only reaches the database once a code proves
+        // that the authenticator app has been set up.
+        let secret = match session.get_totp_enrollment().map_err(e500)? {
+            Some(secret) => secret,
+            None => {
+                let secret = generate_totp_secret();
+                session.insert_totp_enrollment(&secret).map_err(e500)?;
+                secret
+            }
+        };
+        let username = get_username(*user_id, &pool).await.map_err(e500)?;
+        let totp = totp(&secret, &username).map_err(e500)?;
+        let qr_code = provisioning_qr_code(&totp).map_err(e500)?;
+        include_str!("setup.html")
+            .replace("{msg_html}", &msg_html)
+            .replace("{qr_code}", &qr_code)
+            .replace("{provisioning_uri}", &totp.get_url())
+            .replace("{secret}", secret.expose_secret())
+            .replace("{csrf_field}", &csrf.form_field())
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_page))
+}
@@ -0,0 +1,99 @@
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+use crate::audit::{record_audit_event, AuditAction, AuditContext};
+use crate::authentication::{totp, verify_second_factor, verify_totp_code, UserId};
+use crate::routes::admin::dashboard::get_username;
+use crate::session_state::TypedSession;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    code: String,
+}
+
+#[tracing::instrument(name = "Enable two-factor authentication", skip_all)]
+pub async fn enable_two_factor(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+    session: TypedSession,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let secret = match session.get_totp_enrollment().map_err(e500)? {
+        Some(secret) => secret,
+        None => return Ok(see_other("/admin/two-factor")),
+    };
+    let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    let totp = totp(&secret, &username).map_err(e500)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(e500)?
+        .as_secs();
+    let step = match verify_totp_code(&totp, &form.code, now) {
+        Some(step) => step,
+        None => {
+            FlashMessage::error("The code you entered is not valid.").send();
+            return Ok(see_other("/admin/two-factor"));
+        }
+    };
+
+    let recovery_codes = crate::authentication::enable_two_factor(*user_id, &secret, step, &pool)
+        .await
+        .map_err(e500)?;
+    session.remove_totp_enrollment();
+    record_audit_event(
+        pool.get_ref(),
+        &audit,
+        Some(*user_id),
+        AuditAction::TwoFactorEnabled,
+        None,
+    )
+    .await
+    .map_err(e500)?;
+    let mut recovery_codes_html = String::new();
+    for code in recovery_codes {
+        writeln!(recovery_codes_html, "<li><code>{}</code></li>", code).unwrap();
+    }
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        include_str!("recovery_codes.html").replace("{recovery_codes_html}", &recovery_codes_html),
+    ))
+}
+
+#[tracing::instrument(name = "Disable two-factor authentication", skip_all)]
+pub async fn disable_two_factor(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    if !verify_second_factor(*user_id, &form.code, &pool)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error("The code you entered is not valid.").send();
+        return Ok(see_other("/admin/two-factor"));
+    }
+    crate::authentication::disable_two_factor(*user_id, &pool)
+        .await
+        .map_err(e500)?;
+    record_audit_event(
+        pool.get_ref(),
+        &audit,
+        Some(*user_id),
+        AuditAction::TwoFactorDisabled,
+        None,
+    )
+    .await
+    .map_err(e500)?;
+    FlashMessage::info("Two-factor authentication has been disabled.").send();
+    Ok(see_other("/admin/two-factor"))
+}
@@ -0,0 +1,78 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::authentication::{CsrfToken, Role, UserId};
+use crate::utils::{e500, escape_html};
+
+pub async fn manage_users_form(
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+    flash_messages: IncomingFlashMessages,
+    csrf: CsrfToken,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let users = sqlx::query!(
+        r#"
+        SELECT
+            user_id,
+            username,
+            email,
+            role,
+            deactivated_at IS NOT NULL as "deactivated!"
+        FROM users
+        ORDER BY username NULLS LAST, email
+        "#
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the admin users")
+    .map_err(e500)?;
+    let mut users_html = String::new();
+    for u in users {
+        let action = if u.deactivated {
+            "Deactivated".to_string()
+        } else if u.user_id == **user_id {
+            "You".to_string()
+        } else {
+            format!(
+                r#"<form action="/admin/users/{}/deactivate" method="post">{}<button type="submit">Deactivate</button></form>"#,
+                u.user_id,
+                csrf.form_field()
+            )
+        };
+        let name = match u.username {
+            Some(username) => username,
+            None => format!("{} (invited)", u.email.unwrap_or_default()),
+        };
+        writeln!(
+            users_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape_html(&name),
+            u.role,
+            action
+        )
+        .unwrap();
+    }
+    let mut roles_html = String::new();
+    for role in Role::ALL {
+        writeln!(roles_html, r#"<option value="{0}">{0}</option>"#, role).unwrap();
+    }
+    let html_page = include_str!("users.html")
+        .replace("{msg_html}", &msg_html)
+        .replace("{users_html}", &users_html)
+        .replace("{roles_html}", &roles_html)
+        .replace("{csrf_field}", &csrf.form_field());
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_page))
+}
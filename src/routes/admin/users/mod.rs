@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::manage_users_form;
+pub use post::{deactivate_user, invite_user};
@@ -0,0 +1,189 @@
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use lettre::AsyncTransport;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::audit::{record_audit_event, AuditAction, AuditContext};
+use crate::authentication::{Role, SignedInvitation, UserId, INVITATION_VALIDITY_HOURS};
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailClient, EmailClientError};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+    role: String,
+}
+
+#[tracing::instrument(
+    name = "Invite an admin user",
+    skip_all,
+    fields(email = %form.email, role = %form.role)
+)]
+pub async fn invite_user<T>(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient<T>>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    secret: web::Data<HmacSecret>,
+    current_user_id: ReqData<UserId>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    T: AsyncTransport + Send + Sync,
+    <T as AsyncTransport>::Error: 'static + Send + Sync,
+    <T as AsyncTransport>::Error: std::error::Error,
+{
+    let FormData { email, role } = form.0;
+    let (email, role) = match (SubscriberEmail::parse(email), Role::parse(role)) {
+        (Ok(email), Ok(role)) => (email, role),
+        (Err(e), _) | (_, Err(e)) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+
+    let user_id = match store_pending_user(&pool, &email, role)
+        .await
+        .context("Failed to store the invited user")
+        .map_err(e500)?
+    {
+        Some(user_id) => user_id,
+        None => {
+            FlashMessage::error(format!("{} already has an account.", email)).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    let invitation = SignedInvitation::new(user_id, &secret.0);
+    send_invitation(&email_client, &email, role, &base_url.0, &invitation)
+        .await
+        .context("Failed to send the invitation")
+        .map_err(e500)?;
+
+    record_audit_event(
+        pool.get_ref(),
+        &audit,
+        Some(**current_user_id),
+        AuditAction::UserInvited,
+        Some(&format!("user:{}", user_id)),
+    )
+    .await
+    .map_err(e500)?;
+    FlashMessage::info(format!("An invitation has been sent to {}.", email)).send();
+    Ok(see_other("/admin/users"))
+}
+
+/// Creates a user that has not picked a username and a password yet.
+/// Inviting the same address again refreshes the pending user instead;
+/// `None` is returned if the address belongs to a user who is set up.
+#[tracing::instrument(name = "Store a pending user", skip(pool))]
+async fn store_pending_user(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+    role: Role,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, email, role, invited_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT (email) DO UPDATE
+        SET role = EXCLUDED.role, invited_at = EXCLUDED.invited_at, deactivated_at = NULL
+        WHERE users.username IS NULL
+        RETURNING user_id
+        "#,
+        Uuid::new_v4(),
+        email.as_ref(),
+        role.as_str()
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|r| r.user_id))
+}
+
+#[tracing::instrument(name = "Send an invitation", skip(email_client, base_url, invitation))]
+async fn send_invitation<T>(
+    email_client: &EmailClient<T>,
+    email: &SubscriberEmail,
+    role: Role,
+    base_url: &str,
+    invitation: &SignedInvitation,
+) -> Result<(), EmailClientError>
+where
+    T: AsyncTransport + Send + Sync,
+    <T as AsyncTransport>::Error: 'static + Send + Sync,
+    <T as AsyncTransport>::Error: std::error::Error,
+{
+    let setup_link = format!(
+        "{}/invitations/accept?{}",
+        base_url,
+        invitation.query_string()
+    );
+    let html_content = format!(
+        r#"<p>You have been invited to help run our newsletter as {}.<br />
+        Click <a href="{}">here</a> to choose a username and a password.
+        The link is valid for {} hours.</p>"#,
+        role, setup_link, INVITATION_VALIDITY_HOURS
+    );
+    let text_content = format!(
+        "You have been invited to help run our newsletter as {}.\n\
+        Visit {} to choose a username and a password.\n\
+        The link is valid for {} hours.",
+        role, setup_link, INVITATION_VALIDITY_HOURS
+    );
+    email_client
+        .send_email(
+            email,
+            "You have been invited".to_owned(),
+            text_content,
+            html_content,
+        )
+        .await
+}
+
+#[tracing::instrument(name = "Deactivate an admin user", skip(pool, current_user_id, audit))]
+pub async fn deactivate_user(
+    user_id: web::Path<Uuid>,
+    current_user_id: ReqData<UserId>,
+    pool: web::Data<PgPool>,
+    audit: AuditContext,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    if user_id == **current_user_id {
+        FlashMessage::error("You cannot deactivate yourself.").send();
+        return Ok(see_other("/admin/users"));
+    }
+
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE users
+        SET deactivated_at = now()
+        WHERE user_id = $1 AND deactivated_at IS NULL
+        "#,
+        user_id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to deactivate the user")
+    .map_err(e500)?
+    .rows_affected();
+
+    if n_updated_rows == 0 {
+        FlashMessage::error("There is no such active user.").send();
+    } else {
+        record_audit_event(
+            pool.get_ref(),
+            &audit,
+            Some(**current_user_id),
+            AuditAction::UserDeactivated,
+            Some(&format!("user:{}", user_id)),
+        )
+        .await
+        .map_err(e500)?;
+        FlashMessage::info("The user has been deactivated.").send();
+    }
+    Ok(see_other("/admin/users"))
+}
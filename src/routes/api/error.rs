@@ -0,0 +1,135 @@
+use actix_web::error::{JsonPayloadError, PathError, QueryPayloadError};
+use actix_web::http::header::{HeaderValue, WWW_AUTHENTICATE};
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+
+use utoipa::ToSchema;
+
+use crate::authentication::ApiScope;
+use crate::routes::{error_chain_fmt, LoginError, SubscribeError, SubscriptionConfirmError};
+
+/// The body of every `/api/v1` error response.
+#[derive(serde::Serialize, ToSchema)]
+pub struct ErrorBody {
+    error: ErrorDetail,
+}
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct ErrorDetail {
+    /// Stable, machine-readable: one of `validation_error`, `unauthorized`,
+    /// `forbidden`, `not_found`, `too_many_requests` or `unexpected_error`.
+    #[schema(example = "not_found")]
+    code: String,
+    /// Meant for humans, may change.
+    message: String,
+}
+
+/// Errors returned by `/api/v1`, rendered as an `ErrorBody`.
+#[derive(thiserror::Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("{0}")]
+    UnauthorizedError(String),
+    #[error("This token does not have the {0} scope.")]
+    ForbiddenError(ApiScope),
+    #[error("{0}")]
+    NotFoundError(String),
+    #[error("{0}")]
+    TooManyRequestsError(String),
+    #[error("Something went wrong.")]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::ValidationError(_) => "validation_error",
+            ApiError::UnauthorizedError(_) => "unauthorized",
+            ApiError::ForbiddenError(_) => "forbidden",
+            ApiError::NotFoundError(_) => "not_found",
+            ApiError::TooManyRequestsError(_) => "too_many_requests",
+            ApiError::UnexpectedError(_) => "unexpected_error",
+        }
+    }
+}
+
+impl std::fmt::Debug for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ApiError::UnauthorizedError(_) => StatusCode::UNAUTHORIZED,
+            ApiError::ForbiddenError(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFoundError(_) => StatusCode::NOT_FOUND,
+            ApiError::TooManyRequestsError(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code()).json(ErrorBody {
+            error: ErrorDetail {
+                code: self.code().to_string(),
+                message: self.to_string(),
+            },
+        });
+        if let ApiError::UnauthorizedError(_) = self {
+            response.headers_mut().insert(
+                WWW_AUTHENTICATE,
+                HeaderValue::from_static(r#"Bearer realm="api""#),
+            );
+        }
+        response
+    }
+}
+
+impl From<SubscribeError> for ApiError {
+    fn from(e: SubscribeError) -> Self {
+        match e {
+            SubscribeError::ValidationError(message) => ApiError::ValidationError(message),
+            SubscribeError::UnexpectedError(e) => ApiError::UnexpectedError(e),
+        }
+    }
+}
+
+impl From<SubscriptionConfirmError> for ApiError {
+    fn from(e: SubscriptionConfirmError) -> Self {
+        match e {
+            SubscriptionConfirmError::UnauthorizedError => {
+                ApiError::UnauthorizedError(e.to_string())
+            }
+            SubscriptionConfirmError::NotFoundError => ApiError::NotFoundError(e.to_string()),
+            SubscriptionConfirmError::UnexpectedError(e) => ApiError::UnexpectedError(e),
+        }
+    }
+}
+
+impl From<LoginError> for ApiError {
+    fn from(e: LoginError) -> Self {
+        match e {
+            LoginError::AuthError(_) => ApiError::UnauthorizedError(e.to_string()),
+            LoginError::LockedOut(_) => ApiError::TooManyRequestsError(e.to_string()),
+            LoginError::UnexpectedError(e) => ApiError::UnexpectedError(e),
+        }
+    }
+}
+
+// Extractor failures would otherwise be reported as plain text.
+
+pub fn json_error_handler(e: JsonPayloadError, _: &HttpRequest) -> actix_web::Error {
+    ApiError::ValidationError(e.to_string()).into()
+}
+
+pub fn query_error_handler(e: QueryPayloadError, _: &HttpRequest) -> actix_web::Error {
+    ApiError::ValidationError(e.to_string()).into()
+}
+
+pub fn path_error_handler(e: PathError, _: &HttpRequest) -> actix_web::Error {
+    ApiError::ValidationError(e.to_string()).into()
+}
@@ -0,0 +1,49 @@
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::ApiError;
+use crate::authentication::{ApiScopes, Role, UserId};
+use crate::routes::get_username;
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct Me {
+    user_id: Uuid,
+    username: String,
+    #[schema(example = "editor")]
+    role: String,
+    #[schema(example = json!(["newsletters:read"]))]
+    scopes: Vec<String>,
+}
+
+/// Who the token belongs to and what it may do, so that scripts can
+/// check their configuration.
+#[utoipa::path(
+    get,
+    path = "/api/v1/me",
+    tag = "api",
+    responses(
+        (status = 200, description = "The owner of the token", body = Me),
+        (status = 401, description = "Missing, unknown or revoked token", body = ErrorBody),
+    ),
+    security(("api_token" = []))
+)]
+pub async fn api_me(
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+    role: ReqData<Role>,
+    scopes: ReqData<ApiScopes>,
+) -> Result<HttpResponse, ApiError> {
+    let username = get_username(**user_id, &pool)
+        .await
+        .context("Failed to retrieve the username.")?;
+    Ok(HttpResponse::Ok().json(Me {
+        user_id: **user_id,
+        username,
+        role: role.to_string(),
+        scopes: scopes.0.iter().map(|s| s.to_string()).collect(),
+    }))
+}
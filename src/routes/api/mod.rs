@@ -0,0 +1,59 @@
+mod error;
+mod me;
+mod newsletters;
+mod openapi;
+mod pagination;
+mod subscribers;
+
+pub use error::{json_error_handler, path_error_handler, query_error_handler, ApiError, ErrorBody};
+pub use me::api_me;
+pub use newsletters::{create_issue, get_issue, list_issues};
+pub use openapi::{openapi_spec, ApiDoc};
+pub use pagination::{IssuePage, Page, Pagination, SubscriberPage};
+pub use subscribers::{create_subscriber, delete_subscriber, get_subscriber, list_subscribers};
+
+use actix_web::http::Method;
+use actix_web::{web, Route};
+use lettre::AsyncTransport;
+
+use crate::authentication::{ApiScope, ApiScopes};
+
+/// Every operation under `/api/v1`, kept in one list so that tests can
+/// check each of them is documented.
+pub fn api_v1_routes<E>() -> Vec<(Method, &'static str, Route)>
+where
+    E: 'static + AsyncTransport + Send + Sync,
+    <E as AsyncTransport>::Error: 'static + Send + Sync,
+    <E as AsyncTransport>::Error: std::error::Error,
+{
+    vec![
+        (Method::GET, "/me", web::to(api_me)),
+        (Method::GET, "/newsletters", web::to(list_issues)),
+        (Method::POST, "/newsletters", web::to(create_issue)),
+        (Method::GET, "/newsletters/{issue_id}", web::to(get_issue)),
+        (Method::GET, "/subscribers", web::to(list_subscribers)),
+        (
+            Method::POST,
+            "/subscribers",
+            web::to(create_subscriber::<E>),
+        ),
+        (
+            Method::GET,
+            "/subscribers/{subscriber_id}",
+            web::to(get_subscriber),
+        ),
+        (
+            Method::DELETE,
+            "/subscribers/{subscriber_id}",
+            web::to(delete_subscriber),
+        ),
+    ]
+}
+
+fn require_scope(scopes: &ApiScopes, scope: ApiScope) -> Result<(), ApiError> {
+    if scopes.contains(scope) {
+        Ok(())
+    } else {
+        Err(ApiError::ForbiddenError(scope))
+    }
+}
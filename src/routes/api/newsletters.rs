@@ -0,0 +1,331 @@
+use actix_web::http::header::LOCATION;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::{require_scope, ApiError, Pagination};
+use crate::audit::{record_audit_event, AuditAction, AuditContext};
+use crate::authentication::{ApiScope, ApiScopes, UserId};
+use crate::domain::{IssueVisibility, Segment};
+use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::routes::{
+    enqueue_delivery_tasks, get_default_list_id, insert_newsletter_issue, unknown_list_ids,
+};
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct IssueSummary {
+    id: Uuid,
+    title: String,
+    published_at: String,
+    /// Where the issue lives in the web archive: `/archive/{slug}`.
+    slug: String,
+    #[schema(example = "subscribers")]
+    visibility: String,
+}
+
+/// Most recent first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/newsletters",
+    tag = "newsletters",
+    params(Pagination),
+    responses(
+        (status = 200, description = "A page of issues", body = IssuePage),
+        (status = 400, description = "Invalid pagination", body = ErrorBody),
+        (status = 401, description = "Missing, unknown or revoked token", body = ErrorBody),
+        (status = 403, description = "The token lacks the scope", body = ErrorBody),
+    ),
+    security(("api_token" = ["newsletters:read"]))
+)]
+#[tracing::instrument(name = "List newsletter issues", skip_all)]
+pub async fn list_issues(
+    pagination: web::Query<Pagination>,
+    pool: web::Data<PgPool>,
+    scopes: ReqData<ApiScopes>,
+) -> Result<HttpResponse, ApiError> {
+    require_scope(&scopes, ApiScope::NewslettersRead)?;
+    pagination.validate()?;
+    let issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT newsletter_issue_id as id, title, published_at, slug, visibility
+        FROM newsletter_issues
+        ORDER BY published_at DESC
+        LIMIT $1 OFFSET $2
+        "#,
+        pagination.limit(),
+        pagination.offset()
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the newsletter issues.")?;
+    let total = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM newsletter_issues"#)
+        .fetch_one(pool.get_ref())
+        .await
+        .context("Failed to count the newsletter issues.")?
+        .count;
+    Ok(HttpResponse::Ok().json(pagination.page(issues, total)))
+}
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct Issue {
+    id: Uuid,
+    title: String,
+    text_content: String,
+    html_content: String,
+    published_at: String,
+    slug: String,
+    #[schema(example = "subscribers")]
+    visibility: String,
+    delivery: DeliveryStatus,
+}
+
+/// How many emails of the issue are waiting to go out, and what happened
+/// to the others.
+#[derive(serde::Serialize, Default, ToSchema)]
+pub struct DeliveryStatus {
+    pending: i64,
+    delivered: i64,
+    failed: i64,
+    invalid_address: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/newsletters/{issue_id}",
+    tag = "newsletters",
+    params(("issue_id" = Uuid, Path, description = "The issue's id")),
+    responses(
+        (status = 200, description = "The issue and its delivery status", body = Issue),
+        (status = 401, description = "Missing, unknown or revoked token", body = ErrorBody),
+        (status = 403, description = "The token lacks the scope", body = ErrorBody),
+        (status = 404, description = "No such issue", body = ErrorBody),
+    ),
+    security(("api_token" = ["newsletters:read"]))
+)]
+#[tracing::instrument(name = "Get a newsletter issue", skip(pool, scopes))]
+pub async fn get_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    scopes: ReqData<ApiScopes>,
+) -> Result<HttpResponse, ApiError> {
+    require_scope(&scopes, ApiScope::NewslettersRead)?;
+    let issue_id = issue_id.into_inner();
+    let issue = sqlx::query!(
+        r#"
+        SELECT title, text_content, html_content, published_at, slug, visibility
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the newsletter issue.")?
+    .ok_or_else(|| ApiError::NotFoundError("newsletter issue was not found".into()))?;
+
+    let mut delivery = DeliveryStatus {
+        pending: sqlx::query!(
+            r#"SELECT COUNT(*) as "count!" FROM issue_delivery_queue WHERE newsletter_issue_id = $1"#,
+            issue_id
+        )
+        .fetch_one(pool.get_ref())
+        .await
+        .context("Failed to count the pending deliveries.")?
+        .count,
+        ..Default::default()
+    };
+    let outcomes = sqlx::query!(
+        r#"
+        SELECT outcome, COUNT(*) as "count!"
+        FROM issue_delivery_history
+        WHERE newsletter_issue_id = $1
+        GROUP BY outcome
+        "#,
+        issue_id
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to count the delivery outcomes.")?;
+    for r in outcomes {
+        match r.outcome.as_str() {
+            "delivered" => delivery.delivered = r.count,
+            "failed" => delivery.failed = r.count,
+            "invalid_address" => delivery.invalid_address = r.count,
+            other => tracing::warn!(outcome = other, "Unknown delivery outcome"),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(Issue {
+        id: issue_id,
+        title: issue.title,
+        text_content: issue.text_content,
+        html_content: issue.html_content,
+        published_at: issue.published_at,
+        slug: issue.slug,
+        visibility: issue.visibility,
+        delivery,
+    }))
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct NewIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+    /// The lists to send the issue to; the default list if omitted.
+    list_ids: Option<Vec<Uuid>>,
+    /// Only send to subscribers matching this segment.
+    #[schema(example = "country = us")]
+    segment: Option<String>,
+    /// Who can read the issue in the web archive: `public`, or
+    /// `subscribers` (the default).
+    #[schema(example = "public")]
+    visibility: Option<String>,
+}
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct IssueCreated {
+    id: Uuid,
+    title: String,
+    slug: String,
+}
+
+/// Publishes an issue. Retries carrying the same `Idempotency-Key` header
+/// get the original response back instead of sending the issue twice.
+#[utoipa::path(
+    post,
+    path = "/api/v1/newsletters",
+    tag = "newsletters",
+    request_body = NewIssue,
+    params((
+        "Idempotency-Key" = Option<String>,
+        Header,
+        description = "Retries with the same key are only processed once"
+    )),
+    responses(
+        (status = 202, description = "The issue is being sent", body = IssueCreated),
+        (status = 400, description = "Invalid issue", body = ErrorBody),
+        (status = 401, description = "Missing, unknown or revoked token", body = ErrorBody),
+        (status = 403, description = "The token lacks the scope", body = ErrorBody),
+    ),
+    security(("api_token" = ["newsletters:write"]))
+)]
+#[tracing::instrument(
+    name = "Publish a newsletter issue through the API",
+    skip_all,
+    fields(user_id = %*user_id)
+)]
+pub async fn create_issue(
+    request: HttpRequest,
+    body: web::Json<NewIssue>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+    scopes: ReqData<ApiScopes>,
+    audit: AuditContext,
+) -> Result<HttpResponse, ApiError> {
+    require_scope(&scopes, ApiScope::NewslettersWrite)?;
+    let NewIssue {
+        title,
+        text_content,
+        html_content,
+        list_ids,
+        segment,
+        visibility,
+    } = body.into_inner();
+    if title.trim().is_empty() {
+        return Err(ApiError::ValidationError("title cannot be empty.".into()));
+    }
+    let segment = match segment.filter(|s| !s.trim().is_empty()) {
+        Some(segment) => Some(
+            Segment::parse(&segment)
+                .map_err(|e| ApiError::ValidationError(format!("Invalid segment: {}", e)))?,
+        ),
+        None => None,
+    };
+    let visibility = match visibility {
+        Some(visibility) => {
+            IssueVisibility::parse(&visibility).map_err(ApiError::ValidationError)?
+        }
+        None => IssueVisibility::default(),
+    };
+    let idempotency_key = match request.headers().get("Idempotency-Key") {
+        Some(value) => {
+            let value = value
+                .to_str()
+                .map_err(|e| ApiError::ValidationError(e.to_string()))?;
+            Some(
+                IdempotencyKey::try_from(value.to_string())
+                    .map_err(|e| ApiError::ValidationError(e.to_string()))?,
+            )
+        }
+        None => None,
+    };
+    let list_ids = match list_ids {
+        Some(list_ids) if list_ids.is_empty() => {
+            return Err(ApiError::ValidationError(
+                "list_ids cannot be empty.".into(),
+            ));
+        }
+        Some(list_ids) => list_ids,
+        None => vec![get_default_list_id(&pool).await?],
+    };
+    if let Some(unknown) = unknown_list_ids(&pool, &list_ids).await?.first() {
+        return Err(ApiError::ValidationError(format!(
+            "{} is not a known list.",
+            unknown
+        )));
+    }
+
+    let mut transaction = match &idempotency_key {
+        Some(key) => match try_processing(&pool, key, **user_id).await? {
+            NextAction::StartProcessing(t) => t,
+            NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+        },
+        None => pool
+            .begin()
+            .await
+            .context("Failed to acquire a postgres connection from the pool")?,
+    };
+    let (issue_id, slug) = insert_newsletter_issue(
+        &mut transaction,
+        &title,
+        &text_content,
+        &html_content,
+        visibility,
+    )
+    .await
+    .context("Failed to store newsletter issue details")?;
+    enqueue_delivery_tasks(&mut transaction, issue_id, &list_ids, segment.as_ref())
+        .await
+        .context("Failed to enqueue delivery tasks")?;
+    record_audit_event(
+        &mut transaction,
+        &audit,
+        Some(**user_id),
+        AuditAction::NewsletterPublished,
+        Some(&format!("issue:{}", issue_id)),
+    )
+    .await?;
+
+    let response = HttpResponse::Accepted()
+        .insert_header((LOCATION, format!("/api/v1/newsletters/{}", issue_id)))
+        .json(IssueCreated {
+            id: issue_id,
+            title,
+            slug,
+        });
+    match idempotency_key {
+        Some(key) => Ok(save_response(transaction, &key, **user_id, response).await?),
+        None => {
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to publish an issue")?;
+            Ok(response)
+        }
+    }
+}
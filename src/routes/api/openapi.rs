@@ -0,0 +1,73 @@
+use actix_web::HttpResponse;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use super::{error, me, newsletters, pagination, subscribers};
+use crate::routes;
+
+/// The contract of the endpoints meant for programs rather than browsers,
+/// generated from the handlers and the types they exchange.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "zero2prod"),
+    paths(
+        routes::health_check,
+        routes::metrics,
+        routes::subscribe,
+        routes::confirm,
+        routes::request_subscriber_data,
+        routes::data_request_form,
+        routes::confirm_data_request,
+        me::api_me,
+        newsletters::list_issues,
+        newsletters::create_issue,
+        newsletters::get_issue,
+        subscribers::list_subscribers,
+        subscribers::create_subscriber,
+        subscribers::get_subscriber,
+        subscribers::delete_subscriber,
+    ),
+    components(schemas(
+        routes::subscriptions::FormData,
+        routes::DataRequestForm,
+        routes::DataRequestKind,
+        routes::DataRequestConfirmationForm,
+        error::ErrorBody,
+        error::ErrorDetail,
+        me::Me,
+        newsletters::IssueSummary,
+        newsletters::Issue,
+        newsletters::DeliveryStatus,
+        newsletters::NewIssue,
+        newsletters::IssueCreated,
+        pagination::IssuePage,
+        pagination::SubscriberPage,
+        subscribers::SubscriberSummary,
+        subscribers::Subscriber,
+        subscribers::NewSubscriberBody,
+    )),
+    modifiers(&ApiTokenSecurity),
+    tags(
+        (name = "subscriptions", description = "Public subscription management"),
+        (name = "api", description = "Authenticated with a personal API token"),
+        (name = "newsletters", description = "Authenticated with a personal API token"),
+        (name = "subscribers", description = "Authenticated with a personal API token"),
+    )
+)]
+pub struct ApiDoc;
+
+struct ApiTokenSecurity;
+
+impl Modify for ApiTokenSecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+pub async fn openapi_spec() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
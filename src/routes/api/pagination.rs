@@ -0,0 +1,95 @@
+use utoipa::{IntoParams, ToSchema};
+
+use super::newsletters::IssueSummary;
+use super::subscribers::SubscriberSummary;
+use super::ApiError;
+
+const DEFAULT_PER_PAGE: u32 = 20;
+const MAX_PER_PAGE: u32 = 100;
+
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Pagination {
+    /// Starts at 1.
+    #[serde(default = "first_page")]
+    #[param(minimum = 1, default = 1)]
+    page: u32,
+    #[serde(default = "default_per_page")]
+    #[param(minimum = 1, maximum = 100, default = 20)]
+    per_page: u32,
+}
+
+fn first_page() -> u32 {
+    1
+}
+
+fn default_per_page() -> u32 {
+    DEFAULT_PER_PAGE
+}
+
+impl Pagination {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.page == 0 {
+            return Err(ApiError::ValidationError("page starts at 1.".to_string()));
+        }
+        if self.per_page == 0 || self.per_page > MAX_PER_PAGE {
+            return Err(ApiError::ValidationError(format!(
+                "per_page must be between 1 and {}.",
+                MAX_PER_PAGE
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn limit(&self) -> i64 {
+        self.per_page.into()
+    }
+
+    pub fn offset(&self) -> i64 {
+        i64::from(self.page - 1) * i64::from(self.per_page)
+    }
+
+    pub fn page<T: serde::Serialize>(&self, items: Vec<T>, total: i64) -> Page<T> {
+        Page {
+            items,
+            page: self.page,
+            per_page: self.per_page,
+            total,
+        }
+    }
+}
+
+/// One page of a listing, with what is needed to fetch the others.
+#[derive(serde::Serialize, ToSchema)]
+#[aliases(IssuePage = Page<IssueSummary>, SubscriberPage = Page<SubscriberSummary>)]
+pub struct Page<T> {
+    items: Vec<T>,
+    page: u32,
+    per_page: u32,
+    total: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pagination;
+    use claims::{assert_err, assert_ok};
+
+    fn pagination(page: u32, per_page: u32) -> Pagination {
+        Pagination { page, per_page }
+    }
+
+    #[test]
+    fn pages_are_numbered_from_one() {
+        assert_err!(pagination(0, 20).validate());
+        assert_ok!(pagination(1, 20).validate());
+        assert_eq!(pagination(1, 20).offset(), 0);
+        assert_eq!(pagination(3, 20).offset(), 40);
+    }
+
+    #[test]
+    fn per_page_is_bounded() {
+        assert_err!(pagination(1, 0).validate());
+        assert_ok!(pagination(1, 100).validate());
+        assert_err!(pagination(1, 101).validate());
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+
+use actix_web::http::header::LOCATION;
+use actix_web::web::ReqData;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use lettre::AsyncTransport;
+use sqlx::PgPool;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use super::{require_scope, ApiError, Pagination};
+use crate::audit::{record_audit_event, AuditAction, AuditContext};
+use crate::authentication::{ApiScope, ApiScopes, UserId};
+use crate::domain::{
+    ListSlug, NewSubscriber, SubscriberAttributes, SubscriberEmail, SubscriberName,
+    DEFAULT_LIST_SLUG,
+};
+use crate::email_client::EmailClient;
+use crate::email_domains::EmailDomainPolicy;
+use crate::i18n::parse_locale;
+use crate::routes::{register_subscriber, SubscribeError, SubscriptionConfirmError};
+use crate::startup::ApplicationBaseUrl;
+use crate::subscriber_data::erase_subscriber_data;
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct SubscriberSummary {
+    id: Uuid,
+    email: String,
+    name: String,
+    #[schema(example = "confirmed")]
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SubscriberFilter {
+    /// `pending_confirmation` or `confirmed`.
+    status: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscribers",
+    tag = "subscribers",
+    params(Pagination, SubscriberFilter),
+    responses(
+        (status = 200, description = "A page of subscribers", body = SubscriberPage),
+        (status = 400, description = "Invalid pagination", body = ErrorBody),
+        (status = 401, description = "Missing, unknown or revoked token", body = ErrorBody),
+        (status = 403, description = "The token lacks the scope", body = ErrorBody),
+    ),
+    security(("api_token" = ["subscribers:read"]))
+)]
+#[tracing::instrument(name = "List subscribers", skip_all)]
+pub async fn list_subscribers(
+    pagination: web::Query<Pagination>,
+    filter: web::Query<SubscriberFilter>,
+    pool: web::Data<PgPool>,
+    scopes: ReqData<ApiScopes>,
+) -> Result<HttpResponse, ApiError> {
+    require_scope(&scopes, ApiScope::SubscribersRead)?;
+    pagination.validate()?;
+    let subscribers = sqlx::query_as!(
+        SubscriberSummary,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE $1::text IS NULL OR status = $1
+        ORDER BY subscribed_at, email
+        LIMIT $2 OFFSET $3
+        "#,
+        filter.status,
+        pagination.limit(),
+        pagination.offset()
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscribers.")?;
+    let total = sqlx::query!(
+        r#"SELECT COUNT(*) as "count!" FROM subscriptions WHERE $1::text IS NULL OR status = $1"#,
+        filter.status
+    )
+    .fetch_one(pool.get_ref())
+    .await
+    .context("Failed to count the subscribers.")?
+    .count;
+    Ok(HttpResponse::Ok().json(pagination.page(subscribers, total)))
+}
+
+#[derive(serde::Serialize, ToSchema)]
+pub struct Subscriber {
+    #[serde(flatten)]
+    summary: SubscriberSummary,
+    /// The status of the subscription to each list, by list slug.
+    lists: HashMap<String, String>,
+    attributes: HashMap<String, String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscribers/{subscriber_id}",
+    tag = "subscribers",
+    params(("subscriber_id" = Uuid, Path, description = "The subscriber's id")),
+    responses(
+        (status = 200, description = "The subscriber", body = Subscriber),
+        (status = 401, description = "Missing, unknown or revoked token", body = ErrorBody),
+        (status = 403, description = "The token lacks the scope", body = ErrorBody),
+        (status = 404, description = "No such subscriber", body = ErrorBody),
+    ),
+    security(("api_token" = ["subscribers:read"]))
+)]
+#[tracing::instrument(name = "Get a subscriber", skip(pool, scopes))]
+pub async fn get_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    scopes: ReqData<ApiScopes>,
+) -> Result<HttpResponse, ApiError> {
+    require_scope(&scopes, ApiScope::SubscribersRead)?;
+    let subscriber = get_subscriber_by_id(&pool, *subscriber_id)
+        .await?
+        .ok_or(SubscriptionConfirmError::NotFoundError)?;
+    Ok(HttpResponse::Ok().json(subscriber))
+}
+
+async fn get_subscriber_by_id(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Subscriber>, anyhow::Error> {
+    let summary = sqlx::query_as!(
+        SubscriberSummary,
+        r#"SELECT id, email, name, status, subscribed_at FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscriber.")?;
+    let summary = match summary {
+        Some(summary) => summary,
+        None => return Ok(None),
+    };
+    let lists = sqlx::query!(
+        r#"
+        SELECT l.slug, s.status
+        FROM list_subscriptions s
+        JOIN lists l ON l.list_id = s.list_id
+        WHERE s.subscriber_id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the subscriber's lists.")?
+    .into_iter()
+    .map(|r| (r.slug, r.status))
+    .collect();
+    let attributes = sqlx::query!(
+        r#"SELECT key, value FROM subscriber_attributes WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the subscriber's attributes.")?
+    .into_iter()
+    .map(|r| (r.key, r.value))
+    .collect();
+    Ok(Some(Subscriber {
+        summary,
+        lists,
+        attributes,
+    }))
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct NewSubscriberBody {
+    email: String,
+    name: String,
+    /// The slug of the list to subscribe to; the default list if missing.
+    list: Option<String>,
+    #[serde(default)]
+    attributes: HashMap<String, String>,
+    /// The language of the emails they get, such as `fa`; English if missing.
+    locale: Option<String>,
+}
+
+impl TryFrom<NewSubscriberBody> for NewSubscriber {
+    type Error = String;
+
+    fn try_from(value: NewSubscriberBody) -> Result<Self, Self::Error> {
+        Ok(Self {
+            name: SubscriberName::parse(value.name)?,
+            email: SubscriberEmail::parse(value.email)?,
+            list: ListSlug::parse(value.list.unwrap_or_else(|| DEFAULT_LIST_SLUG.into()))?,
+            attributes: SubscriberAttributes::parse(value.attributes)?,
+            locale: parse_locale(value.locale)?,
+        })
+    }
+}
+
+/// Subscribes someone on their behalf: like with the public form, they
+/// still have to confirm through the link we email them.
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscribers",
+    tag = "subscribers",
+    request_body = NewSubscriberBody,
+    responses(
+        (status = 202, description = "A confirmation email has been sent", body = Subscriber),
+        (status = 400, description = "Invalid subscriber", body = ErrorBody),
+        (status = 401, description = "Missing, unknown or revoked token", body = ErrorBody),
+        (status = 403, description = "The token lacks the scope", body = ErrorBody),
+    ),
+    security(("api_token" = ["subscribers:write"]))
+)]
+#[tracing::instrument(name = "Add a subscriber through the API", skip_all)]
+#[allow(clippy::too_many_arguments)]
+pub async fn create_subscriber<T>(
+    body: web::Json<NewSubscriberBody>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient<T>>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    domain_policy: web::Data<EmailDomainPolicy>,
+    user_id: ReqData<UserId>,
+    scopes: ReqData<ApiScopes>,
+    audit: AuditContext,
+) -> Result<HttpResponse, ApiError>
+where
+    T: AsyncTransport + Send + Sync,
+    <T as AsyncTransport>::Error: 'static + Send + Sync,
+    <T as AsyncTransport>::Error: std::error::Error,
+{
+    require_scope(&scopes, ApiScope::SubscribersWrite)?;
+    let mut new_subscriber: NewSubscriber = body
+        .into_inner()
+        .try_into()
+        .map_err(SubscribeError::ValidationError)?;
+    new_subscriber.email = domain_policy.canonical(new_subscriber.email);
+    register_subscriber(
+        &pool,
+        &email_client,
+        &base_url.0,
+        &domain_policy,
+        &new_subscriber,
+    )
+    .await?;
+    let subscriber_id = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE lower(email) = lower($1)"#,
+        new_subscriber.email.as_ref()
+    )
+    .fetch_one(pool.get_ref())
+    .await
+    .context("Failed to retrieve the new subscriber.")?
+    .id;
+    record_audit_event(
+        pool.get_ref(),
+        &audit,
+        Some(**user_id),
+        AuditAction::SubscriberCreated,
+        Some(&format!("subscriber:{}", subscriber_id)),
+    )
+    .await?;
+    let subscriber = get_subscriber_by_id(&pool, subscriber_id)
+        .await?
+        .ok_or(SubscriptionConfirmError::NotFoundError)?;
+    Ok(HttpResponse::Accepted()
+        .insert_header((LOCATION, format!("/api/v1/subscribers/{}", subscriber_id)))
+        .json(subscriber))
+}
+
+/// Erases the subscriber and everything we hold about them.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/subscribers/{subscriber_id}",
+    tag = "subscribers",
+    params(("subscriber_id" = Uuid, Path, description = "The subscriber's id")),
+    responses(
+        (status = 204, description = "The subscriber has been erased"),
+        (status = 401, description = "Missing, unknown or revoked token", body = ErrorBody),
+        (status = 403, description = "The token lacks the scope", body = ErrorBody),
+        (status = 404, description = "No such subscriber", body = ErrorBody),
+    ),
+    security(("api_token" = ["subscribers:write"]))
+)]
+#[tracing::instrument(
+    name = "Erase a subscriber through the API",
+    skip(pool, user_id, scopes, audit)
+)]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: ReqData<UserId>,
+    scopes: ReqData<ApiScopes>,
+    audit: AuditContext,
+) -> Result<HttpResponse, ApiError> {
+    require_scope(&scopes, ApiScope::SubscribersWrite)?;
+    let email = sqlx::query!(
+        r#"SELECT email FROM subscriptions WHERE id = $1"#,
+        *subscriber_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscriber.")?
+    .ok_or(SubscriptionConfirmError::NotFoundError)?
+    .email;
+    erase_subscriber_data(&pool, &email, &audit, Some(**user_id)).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
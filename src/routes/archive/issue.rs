@@ -0,0 +1,48 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+use super::list::published_on;
+use super::reader::{identify_reader, Parameters};
+use crate::i18n::Locale;
+use crate::utils::{e500, escape_html};
+
+/// Issues the reader may not see are reported as missing, like unknown ones.
+#[tracing::instrument(name = "Show an archived issue", skip(parameters, pool, locale))]
+pub async fn archived_issue(
+    slug: web::Path<String>,
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    let reader = identify_reader(&pool, parameters.into_inner())
+        .await
+        .map_err(e500)?;
+    let issue = sqlx::query!(
+        r#"
+        SELECT title, html_content, published_at
+        FROM newsletter_issues
+        WHERE slug = $1 AND visibility = ANY($2)
+        "#,
+        slug.as_str(),
+        &reader.can_see() as &[&str]
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(e500)?;
+    let Some(issue) = issue else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+
+    // The issue's own HTML goes in last, so that it is not mistaken for
+    // placeholders.
+    let html_page = locale
+        .localize(include_str!("issue.html"))
+        .replace("{published_on}", published_on(&issue.published_at))
+        .replace("{archive_query}", &reader.query())
+        .replace("{title}", &escape_html(&issue.title))
+        .replace("{html_content}", &issue.html_content);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_page))
+}
@@ -0,0 +1,63 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+use super::reader::{archive_path, identify_reader, Parameters};
+use crate::i18n::Locale;
+use crate::utils::{e500, escape_html};
+
+#[tracing::instrument(name = "Show the archive of past issues", skip_all)]
+pub async fn archive(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    let reader = identify_reader(&pool, parameters.into_inner())
+        .await
+        .map_err(e500)?;
+    let issues = sqlx::query!(
+        r#"
+        SELECT title, slug, published_at
+        FROM newsletter_issues
+        WHERE visibility = ANY($1)
+        ORDER BY published_at DESC
+        "#,
+        &reader.can_see() as &[&str]
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(e500)?;
+
+    let mut issues_html = String::new();
+    if issues.is_empty() {
+        writeln!(issues_html, "<p>{}</p>", locale.text("archive.empty")).unwrap();
+    } else {
+        writeln!(issues_html, "<ul>").unwrap();
+        for issue in issues {
+            writeln!(
+                issues_html,
+                r#"<li><a href="{}{}" dir="auto">{}</a> <span dir="ltr">{}</span></li>"#,
+                archive_path(&issue.slug),
+                reader.query(),
+                escape_html(&issue.title),
+                published_on(&issue.published_at)
+            )
+            .unwrap();
+        }
+        writeln!(issues_html, "</ul>").unwrap();
+    }
+
+    let html_page = locale
+        .localize(include_str!("archive.html"))
+        .replace("{issues_html}", &issues_html);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_page))
+}
+
+/// The day part of a `published_at` timestamp.
+pub(super) fn published_on(published_at: &str) -> &str {
+    published_at.get(..10).unwrap_or(published_at)
+}
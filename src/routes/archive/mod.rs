@@ -0,0 +1,7 @@
+mod issue;
+mod list;
+mod reader;
+
+pub use issue::archived_issue;
+pub use list::archive;
+pub use reader::{archive_path, get_or_create_archive_token};
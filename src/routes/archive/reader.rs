@@ -0,0 +1,122 @@
+use std::fmt::Write;
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::IssueVisibility;
+use crate::routes::generate_token;
+use crate::utils::escape_html;
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    /// Lets a subscriber read the issues meant for subscribers only.
+    archive_token: Option<String>,
+}
+
+/// Who is browsing the archive.
+pub enum Reader {
+    Anyone,
+    Subscriber { archive_token: String },
+}
+
+impl Reader {
+    pub fn can_see(&self) -> Vec<&'static str> {
+        match self {
+            Reader::Anyone => vec![IssueVisibility::Public.as_str()],
+            Reader::Subscriber { .. } => vec![
+                IssueVisibility::Public.as_str(),
+                IssueVisibility::Subscribers.as_str(),
+            ],
+        }
+    }
+
+    /// The query string that keeps the reader's access on the archive's links.
+    pub fn query(&self) -> String {
+        match self {
+            Reader::Anyone => String::new(),
+            Reader::Subscriber { archive_token } => {
+                format!("?archive_token={}", escape_html(archive_token))
+            }
+        }
+    }
+}
+
+/// A confirmed subscriber if the parameters carry their archive token.
+#[tracing::instrument(name = "Identify the archive reader", skip_all)]
+pub async fn identify_reader(pool: &PgPool, parameters: Parameters) -> Result<Reader, sqlx::Error> {
+    let Some(archive_token) = parameters.archive_token else {
+        return Ok(Reader::Anyone);
+    };
+    let is_subscriber = sqlx::query!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM archive_tokens a
+            JOIN list_subscriptions l ON l.subscriber_id = a.subscriber_id
+            WHERE a.archive_token = $1 AND l.status = 'confirmed'
+        ) as "exists!"
+        "#,
+        archive_token
+    )
+    .fetch_one(pool)
+    .await?
+    .exists;
+    if is_subscriber {
+        Ok(Reader::Subscriber { archive_token })
+    } else {
+        Ok(Reader::Anyone)
+    }
+}
+
+/// The subscriber's archive token, created the first time they are sent
+/// a link to a subscribers-only issue.
+#[tracing::instrument(name = "Get the subscriber's archive token", skip(pool))]
+pub async fn get_or_create_archive_token(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<String, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO archive_tokens (archive_token, subscriber_id)
+        VALUES ($1, $2)
+        ON CONFLICT (subscriber_id) DO NOTHING
+        "#,
+        generate_token(),
+        subscriber_id
+    )
+    .execute(pool)
+    .await?;
+    let archive_token = sqlx::query!(
+        r#"SELECT archive_token FROM archive_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .fetch_one(pool)
+    .await?
+    .archive_token;
+    Ok(archive_token)
+}
+
+/// The archive page of the issue with `slug`. Slugs may have letters from
+/// any script, so they are percent-encoded to keep links plain ASCII.
+pub fn archive_path(slug: &str) -> String {
+    let mut path = String::from("/archive/");
+    for byte in slug.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' {
+            path.push(byte as char);
+        } else {
+            write!(path, "%{:02X}", byte).unwrap();
+        }
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::archive_path;
+
+    #[test]
+    fn non_ascii_slugs_are_percent_encoded() {
+        assert_eq!(archive_path("issue-12"), "/archive/issue-12");
+        assert_eq!(archive_path("ش-1"), "/archive/%D8%B4-1");
+    }
+}
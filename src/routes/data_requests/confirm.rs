@@ -0,0 +1,166 @@
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::audit::{record_audit_event, AuditAction, AuditContext};
+use crate::i18n::Locale;
+use crate::routes::error_chain_fmt;
+use crate::subscriber_data::{erase_subscriber_data, export_subscriber_data};
+use crate::utils::escape_html;
+
+#[derive(thiserror::Error)]
+pub enum DataRequestError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The link you followed is invalid or has expired.")]
+    UnauthorizedError,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for DataRequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for DataRequestError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            DataRequestError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            DataRequestError::UnauthorizedError => StatusCode::UNAUTHORIZED,
+            DataRequestError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Parameters {
+    /// From the link in the confirmation email.
+    request_token: String,
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+#[schema(as = DataRequestConfirmationForm)]
+pub struct FormData {
+    /// From the link in the confirmation email.
+    request_token: String,
+}
+
+/// Only asks the subscriber to confirm: mail scanners and link prefetchers
+/// follow the links in emails too, and must not erase anyone.
+#[utoipa::path(
+    get,
+    path = "/subscriptions/data-requests/confirm",
+    tag = "subscriptions",
+    params(Parameters),
+    responses(
+        (status = 200, description = "A form to carry out the request", body = String, content_type = "text/html"),
+        (status = 401, description = "Unknown or expired token", body = String, content_type = "text/plain"),
+    )
+)]
+#[tracing::instrument(name = "Show a data request confirmation", skip_all)]
+pub async fn data_request_form(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    locale: Locale,
+) -> Result<HttpResponse, DataRequestError> {
+    let kind = sqlx::query!(
+        r#"
+        SELECT kind
+        FROM data_requests
+        WHERE
+            request_token = $1 AND
+            requested_at > now() - interval '1 day'
+        "#,
+        parameters.request_token
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the data request.")?
+    .ok_or(DataRequestError::UnauthorizedError)?
+    .kind;
+
+    let html_page = locale
+        .localize(include_str!("confirm.html"))
+        .replace(
+            "{title}",
+            locale.text(&format!("data_request.{}_title", kind)),
+        )
+        .replace(
+            "{prompt}",
+            locale.text(&format!("data_request.{}_prompt", kind)),
+        )
+        .replace(
+            "{submit}",
+            locale.text(&format!("data_request.{}_submit", kind)),
+        )
+        .replace("{request_token}", &escape_html(&parameters.request_token));
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_page))
+}
+
+#[utoipa::path(
+    post,
+    path = "/subscriptions/data-requests/confirm",
+    tag = "subscriptions",
+    request_body(content = DataRequestConfirmationForm, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "The exported data as JSON for an export, a confirmation for an erasure"),
+        (status = 400, description = "Invalid form", body = String, content_type = "text/plain"),
+        (status = 401, description = "Unknown or expired token", body = String, content_type = "text/plain"),
+    )
+)]
+#[tracing::instrument(name = "Confirm a data request", skip_all)]
+pub async fn confirm_data_request(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    audit: AuditContext,
+) -> Result<HttpResponse, DataRequestError> {
+    let request = sqlx::query!(
+        r#"
+        DELETE FROM data_requests d
+        USING subscriptions s
+        WHERE
+            s.id = d.subscriber_id AND
+            d.request_token = $1 AND
+            d.requested_at > now() - interval '1 day'
+        RETURNING s.email, d.kind
+        "#,
+        form.request_token
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the data request.")?
+    .ok_or(DataRequestError::UnauthorizedError)?;
+
+    match request.kind.as_str() {
+        "export" => {
+            let export = export_subscriber_data(&pool, &request.email)
+                .await?
+                .ok_or(DataRequestError::UnauthorizedError)?;
+            let target = format!("subscriber:{}", export.subscriber_id());
+            record_audit_event(
+                pool.get_ref(),
+                &audit,
+                None,
+                AuditAction::SubscriberExported,
+                Some(&target),
+            )
+            .await?;
+            Ok(HttpResponse::Ok().json(export))
+        }
+        "erasure" => {
+            erase_subscriber_data(&pool, &request.email, &audit, None).await?;
+            Ok(HttpResponse::Ok()
+                .content_type(ContentType::plaintext())
+                .body("All the data we held about you has been erased."))
+        }
+        kind => Err(anyhow::anyhow!("Unknown data request kind: {kind}").into()),
+    }
+}
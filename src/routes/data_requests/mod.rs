@@ -0,0 +1,11 @@
+mod confirm;
+mod request;
+
+pub use confirm::{
+    __path_confirm_data_request, __path_data_request_form, confirm_data_request, data_request_form,
+    DataRequestError, FormData as DataRequestConfirmationForm,
+};
+pub use request::{
+    __path_request_subscriber_data, request_subscriber_data, DataRequestKind,
+    FormData as DataRequestForm,
+};
@@ -0,0 +1,208 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use lettre::AsyncTransport;
+use sqlx::PgPool;
+use tracing::Instrument;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::DataRequestError;
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailClient, EmailClientError};
+use crate::email_domains::EmailDomainPolicy;
+use crate::i18n::Locale;
+use crate::routes::{append_preferences_link, generate_token};
+use crate::startup::ApplicationBaseUrl;
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DataRequestKind {
+    Export,
+    Erasure,
+}
+
+impl DataRequestKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataRequestKind::Export => "export",
+            DataRequestKind::Erasure => "erasure",
+        }
+    }
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+#[schema(as = DataRequestForm)]
+pub struct FormData {
+    email: String,
+    kind: DataRequestKind,
+}
+
+/// Emails the subscriber a link to confirm the request, if they exist.
+#[utoipa::path(
+    post,
+    path = "/subscriptions/data-requests",
+    tag = "subscriptions",
+    request_body(content = DataRequestForm, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "The same whether or not the address is subscribed", body = String, content_type = "text/plain"),
+        (status = 400, description = "Invalid request", body = String, content_type = "text/plain"),
+    )
+)]
+#[tracing::instrument(
+    name = "Request subscriber data",
+    skip(form, pool, email_client, base_url, domain_policy),
+    fields(kind = ?form.kind)
+)]
+pub async fn request_subscriber_data<T>(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient<T>>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    domain_policy: web::Data<EmailDomainPolicy>,
+) -> Result<HttpResponse, DataRequestError>
+where
+    T: 'static + AsyncTransport + Send + Sync,
+    <T as AsyncTransport>::Error: 'static + Send + Sync,
+    <T as AsyncTransport>::Error: std::error::Error,
+{
+    let FormData { email, kind } = form.0;
+    let email = SubscriberEmail::parse(email).map_err(DataRequestError::ValidationError)?;
+    let email = domain_policy.canonical(email);
+
+    // We answer the same way whether or not the address is subscribed,
+    // so that the endpoint cannot be used to find out who is.
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT s.id, s.locale, p.preference_token as "preference_token?"
+        FROM subscriptions s
+        LEFT JOIN preference_tokens p ON p.subscriber_id = s.id
+        WHERE lower(s.email) = lower($1)
+        "#,
+        email.as_ref()
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to look up the subscriber.")?;
+    if let Some(subscriber) = subscriber {
+        // Storing the request and sending the email happen in the background,
+        // so that the response time does not tell whether the address is subscribed.
+        let pool = pool.into_inner();
+        let email_client = email_client.into_inner();
+        let base_url = base_url.0.clone();
+        let email = email.clone();
+        actix_web::rt::spawn(
+            async move {
+                let request_token = generate_token();
+                let locale = Locale::parse(&subscriber.locale).unwrap_or_default();
+                let sent = async {
+                    store_data_request(&pool, subscriber.id, kind, &request_token)
+                        .await
+                        .context("Failed to store the data request.")?;
+                    send_data_request_confirmation(
+                        &email_client,
+                        &email,
+                        kind,
+                        locale,
+                        &base_url,
+                        &request_token,
+                        subscriber.preference_token.as_deref(),
+                    )
+                    .await
+                    .context("Failed to send the data request confirmation.")
+                }
+                .await;
+                if let Err(e) = sent {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to send a data request confirmation."
+                    );
+                }
+            }
+            .in_current_span(),
+        );
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .body(format!(
+            "If {} is subscribed, we have sent it a link to confirm your request.",
+            email
+        )))
+}
+
+#[tracing::instrument(name = "Store data request", skip(pool, request_token))]
+async fn store_data_request(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    kind: DataRequestKind,
+    request_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO data_requests (request_token, subscriber_id, kind, requested_at)
+        VALUES ($1, $2, $3, now())
+        "#,
+        request_token,
+        subscriber_id,
+        kind.as_str()
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Send a data request confirmation",
+    skip(email_client, base_url, token, preference_token)
+)]
+async fn send_data_request_confirmation<T>(
+    email_client: &EmailClient<T>,
+    email: &SubscriberEmail,
+    kind: DataRequestKind,
+    locale: Locale,
+    base_url: &str,
+    token: &str,
+    preference_token: Option<&str>,
+) -> Result<(), EmailClientError>
+where
+    T: AsyncTransport + Send + Sync,
+    <T as AsyncTransport>::Error: 'static + Send + Sync,
+    <T as AsyncTransport>::Error: std::error::Error,
+{
+    let confirmation_link =
+        format!("{base_url}/subscriptions/data-requests/confirm?request_token={token}");
+    let action_key = format!("data_request.{}_action", kind.as_str());
+    let args = [
+        ("action", locale.text(&action_key)),
+        ("link", &confirmation_link),
+    ];
+    let validity = locale.text("data_request.link_validity");
+    let mut html_content = format!(
+        r#"<p dir="{}">{} {}</p>"#,
+        locale.dir(),
+        locale.format("data_request.email_html", &args),
+        validity
+    );
+    let mut text_content = format!(
+        "{}\n{}",
+        locale.format("data_request.email_text", &args),
+        validity
+    );
+    if let Some(preference_token) = preference_token {
+        append_preferences_link(
+            &mut text_content,
+            &mut html_content,
+            locale,
+            base_url,
+            preference_token,
+        );
+    }
+    let subject = locale
+        .text(&format!("data_request.{}_subject", kind.as_str()))
+        .to_owned();
+    email_client
+        .send_email(email, subject, text_content, html_content)
+        .await
+}
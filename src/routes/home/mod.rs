@@ -1,8 +1,14 @@
 use actix_web::http::header::ContentType;
+use actix_web::web::Data;
 use actix_web::HttpResponse;
 
-pub async fn home() -> HttpResponse {
-    HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(include_str!("home.html"))
+use crate::anti_spam::SpamFilter;
+use crate::i18n::Locale;
+
+pub async fn home(spam_filter: Data<SpamFilter>, locale: Locale) -> HttpResponse {
+    HttpResponse::Ok().content_type(ContentType::html()).body(
+        locale
+            .localize(include_str!("home.html"))
+            .replace("{form_stamp}", &spam_filter.form_stamp().to_string()),
+    )
 }
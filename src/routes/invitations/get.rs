@@ -0,0 +1,42 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use super::post::{get_pending_user_email, InvitationError};
+use crate::authentication::SignedInvitation;
+use crate::startup::HmacSecret;
+
+#[tracing::instrument(name = "Show the account setup form", skip_all)]
+pub async fn accept_invitation_form(
+    invitation: web::Query<SignedInvitation>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, InvitationError> {
+    invitation
+        .verify(&secret.0)
+        .map_err(InvitationError::UnauthorizedError)?;
+    let email = get_pending_user_email(&pool, invitation.user_id)
+        .await
+        .context("Failed to retrieve the invited user.")?
+        .ok_or_else(|| InvitationError::UnauthorizedError(anyhow::anyhow!("Not pending")))?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let html_page = include_str!("accept.html")
+        .replace("{msg_html}", &msg_html)
+        .replace("{email}", &email)
+        .replace("{user_id}", &invitation.user_id.to_string())
+        .replace("{expires_at}", &invitation.expires_at.to_string())
+        .replace("{signature}", invitation.signature());
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_page))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::accept_invitation_form;
+pub use post::{accept_invitation, InvitationError};
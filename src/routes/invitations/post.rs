@@ -0,0 +1,153 @@
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::{compute_password_hash, SignedInvitation};
+use crate::routes::error_chain_fmt;
+use crate::startup::HmacSecret;
+use crate::telemetry::spawn_blocking_with_tracing;
+use crate::utils::{escape_html, see_other};
+
+const USERNAME_CONSTRAINT: &str = "users_username_key";
+
+#[derive(thiserror::Error)]
+pub enum InvitationError {
+    #[error("The link you followed is invalid or has expired.")]
+    UnauthorizedError(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for InvitationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for InvitationError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            InvitationError::UnauthorizedError(_) => StatusCode::UNAUTHORIZED,
+            InvitationError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    #[serde(flatten)]
+    invitation: SignedInvitation,
+    username: String,
+    password: Secret<String>,
+    password_check: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Accept an invitation",
+    skip_all,
+    fields(user_id = %form.invitation.user_id, username = %form.username)
+)]
+pub async fn accept_invitation(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, InvitationError> {
+    let FormData {
+        invitation,
+        username,
+        password,
+        password_check,
+    } = form.0;
+    invitation
+        .verify(&secret.0)
+        .map_err(InvitationError::UnauthorizedError)?;
+    get_pending_user_email(&pool, invitation.user_id)
+        .await
+        .context("Failed to retrieve the invited user.")?
+        .ok_or_else(|| InvitationError::UnauthorizedError(anyhow::anyhow!("Not pending")))?;
+
+    let setup_form = format!("/invitations/accept?{}", invitation.query_string());
+    let username = username.trim().to_string();
+    if username.is_empty() {
+        FlashMessage::error("The username cannot be empty.").send();
+        return Ok(see_other(&setup_form));
+    }
+    if password.expose_secret().is_empty() {
+        FlashMessage::error("The password cannot be empty.").send();
+        return Ok(see_other(&setup_form));
+    }
+    if password.expose_secret() != password_check.expose_secret() {
+        FlashMessage::error("You entered two different passwords - the field values must match.")
+            .send();
+        return Ok(see_other(&setup_form));
+    }
+
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+        .await
+        .context("Failed to spawn a blocking task.")??;
+    // The unique constraint decides who gets a username, so that two
+    // invitations accepted at once cannot both claim it.
+    let result = sqlx::query!(
+        r#"
+        UPDATE users
+        SET username = $2, password_hash = $3
+        WHERE user_id = $1 AND username IS NULL
+        "#,
+        invitation.user_id,
+        username,
+        password_hash.expose_secret()
+    )
+    .execute(pool.get_ref())
+    .await;
+    match result {
+        Err(sqlx::Error::Database(e)) if e.constraint() == Some(USERNAME_CONSTRAINT) => {
+            FlashMessage::error(format!(
+                "The username {} is already taken.",
+                escape_html(&username)
+            ))
+            .send();
+            return Ok(see_other(&setup_form));
+        }
+        Err(e) => {
+            return Err(anyhow::Error::new(e)
+                .context("Failed to set up the invited user.")
+                .into())
+        }
+        Ok(result) if result.rows_affected() == 0 => {
+            return Err(InvitationError::UnauthorizedError(anyhow::anyhow!(
+                "Accepted in the meantime"
+            )));
+        }
+        Ok(_) => {}
+    }
+
+    FlashMessage::info("Your account is ready - you can now log in.").send();
+    Ok(see_other("/login"))
+}
+
+/// The email address of an invited user who has not been set up yet.
+#[tracing::instrument(name = "Get pending user email", skip(pool))]
+pub async fn get_pending_user_email(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT email as "email!"
+        FROM users
+        WHERE
+            user_id = $1 AND
+            username IS NULL AND
+            email IS NOT NULL AND
+            deactivated_at IS NULL
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|r| r.email))
+}
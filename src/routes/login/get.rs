@@ -4,12 +4,22 @@ use actix_web::http::header::ContentType;
 use actix_web::HttpResponse;
 use actix_web_flash_messages::IncomingFlashMessages;
 
-pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
+use crate::authentication::CsrfToken;
+use crate::i18n::Locale;
+
+pub async fn login_form(
+    flash_messages: IncomingFlashMessages,
+    csrf: CsrfToken,
+    locale: Locale,
+) -> HttpResponse {
     let mut msg_html = String::new();
     for m in flash_messages.iter() {
         writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
-    HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(include_str!("login.html").replace("{msg}", &msg_html))
+    HttpResponse::Ok().content_type(ContentType::html()).body(
+        locale
+            .localize(include_str!("login.html"))
+            .replace("{msg}", &msg_html)
+            .replace("{csrf_field}", &csrf.form_field()),
+    )
 }
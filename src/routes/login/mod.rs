@@ -2,4 +2,7 @@ mod get;
 pub use get::login_form;
 
 mod post;
-pub use post::login;
+pub use post::{login, LoginError};
+
+mod two_factor;
+pub use two_factor::{two_factor_form, verify_two_factor};
@@ -2,58 +2,175 @@ use std::fmt::Formatter;
 
 use actix_web::error::InternalError;
 use actix_web::http::header::LOCATION;
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use chrono::Utc;
 use secrecy::Secret;
 use sqlx::PgPool;
+use uuid::Uuid;
 
-use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::audit::{record_audit_event, AuditAction, AuditContext};
+use crate::authentication::{
+    start_session, two_factor_enabled, validate_credentials, AuthError, Credentials, CsrfToken,
+    LoginThrottle,
+};
+use crate::configuration::SessionSettings;
+use crate::i18n::Locale;
 use crate::routes::error_chain_fmt;
-use crate::session_state::TypedSession;
+use crate::session_state::{PendingLogin, RememberedUntil, TypedSession};
+use crate::utils::{client_ip, see_other};
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     username: String,
     password: Secret<String>,
+    /// Sent as "on" when the "Remember me" box is ticked.
+    remember_me: Option<String>,
 }
 
 #[tracing::instrument(
-skip(form, pool, session),
+skip(form, pool, session, throttle, request, audit, session_settings, locale),
 fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn login(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     session: TypedSession,
+    throttle: web::Data<LoginThrottle>,
+    request: HttpRequest,
+    audit: AuditContext,
+    session_settings: web::Data<SessionSettings>,
+    locale: Locale,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
+    let remember_me = form.0.remember_me.is_some();
+    let username = form.0.username;
+    let ip = client_ip(&request);
+    tracing::Span::current().record("username", tracing::field::display(&username));
+    check_throttle(&throttle, &username, &ip)
+        .await
+        .map_err(|e| login_redirect(e, locale))?;
+
     let credentials = Credentials {
-        username: form.0.username,
+        username: username.clone(),
         password: form.0.password,
     };
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
     match validate_credentials(credentials, &pool).await {
         Ok(user_id) => {
-            tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+            tracing::Span::current().record("user_id", tracing::field::display(&user_id));
             session.renew();
-            session
-                .insert_user_id(user_id)
-                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
-            Ok(HttpResponse::SeeOther()
-                .insert_header((LOCATION, "/admin/dashboard"))
-                .finish())
+            let needs_second_factor = two_factor_enabled(user_id, &pool)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e), locale))?;
+            if needs_second_factor {
+                session
+                    .insert_pending_login(PendingLogin {
+                        user_id,
+                        started_at: Utc::now(),
+                        remember_me,
+                        failed_attempts: 0,
+                    })
+                    .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into()), locale))?;
+                return Ok(see_other("/login/two-factor"));
+            }
+            let response = complete_login(
+                &session,
+                user_id,
+                remember_me,
+                &audit,
+                &session_settings,
+                &pool,
+            )
+            .await
+            .map_err(|e| login_redirect(LoginError::UnexpectedError(e), locale))?;
+            // Only once the login is complete: users with two-factor
+            // authentication are reset in `verify_two_factor`.
+            throttle
+                .record_success(&username)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e), locale))?;
+            record_audit_event(
+                pool.get_ref(),
+                &audit,
+                Some(user_id),
+                AuditAction::Login,
+                None,
+            )
+            .await
+            .map_err(|e| login_redirect(LoginError::UnexpectedError(e), locale))?;
+            Ok(response)
         }
         Err(e) => {
             let e = match e {
-                AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+                AuthError::InvalidCredentials(_) => {
+                    throttle
+                        .record_failure(&username, &ip)
+                        .await
+                        .map_err(|e| login_redirect(LoginError::UnexpectedError(e), locale))?;
+                    record_audit_event(
+                        pool.get_ref(),
+                        &audit,
+                        None,
+                        AuditAction::LoginFailed,
+                        Some(&format!("username:{}", username)),
+                    )
+                    .await
+                    .map_err(|e| login_redirect(LoginError::UnexpectedError(e), locale))?;
+                    LoginError::AuthError(e.into())
+                }
                 AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
             };
-            Err(login_redirect(e))
+            Err(login_redirect(e, locale))
         }
     }
 }
 
-fn login_redirect(e: LoginError) -> InternalError<LoginError> {
-    FlashMessage::error(e.to_string()).send();
+/// Rejects locked out attempts, and slows down the others in proportion
+/// to how many recent attempts have failed.
+pub async fn check_throttle(
+    throttle: &LoginThrottle,
+    username: &str,
+    ip: &str,
+) -> Result<(), LoginError> {
+    if let Some(remaining) = throttle.lockout_remaining(username, ip).await? {
+        let minutes = remaining.as_secs().div_ceil(60);
+        return Err(LoginError::LockedOut(minutes));
+    }
+    let delay = throttle.delay(username, ip).await?;
+    if !delay.is_zero() {
+        actix_web::rt::time::sleep(delay).await;
+    }
+    Ok(())
+}
+
+/// Turns the session into an authenticated one, tracked in `user_sessions`,
+/// and sends the user to the dashboard.
+pub async fn complete_login(
+    session: &TypedSession,
+    user_id: Uuid,
+    remember_me: bool,
+    audit: &AuditContext,
+    settings: &SessionSettings,
+    pool: &PgPool,
+) -> Result<HttpResponse, anyhow::Error> {
+    let (session_id, expires_at) =
+        start_session(user_id, remember_me, audit, settings, pool).await?;
+    session.insert_user_id(user_id)?;
+    session.insert_session_id(session_id)?;
+    // Tokens handed out before logging in may have been seen by someone else.
+    session.insert_csrf_token(CsrfToken::generate().as_str())?;
+    let mut response = see_other("/admin/dashboard");
+    if remember_me {
+        session.insert_remembered_until(expires_at)?;
+        response
+            .extensions_mut()
+            .insert(RememberedUntil(expires_at));
+    }
+    Ok(response)
+}
+
+pub fn login_redirect(e: LoginError, locale: Locale) -> InternalError<LoginError> {
+    FlashMessage::error(e.message(locale)).send();
     let response = HttpResponse::SeeOther()
         .insert_header((LOCATION, "/login"))
         .finish();
@@ -64,10 +181,25 @@ fn login_redirect(e: LoginError) -> InternalError<LoginError> {
 pub enum LoginError {
     #[error("Authentication failed.")]
     AuthError(#[source] anyhow::Error),
+    #[error("Too many failed login attempts. Please try again in {0} minutes.")]
+    LockedOut(u64),
     #[error("Something went wrong")]
     UnexpectedError(#[from] anyhow::Error),
 }
 
+impl LoginError {
+    /// What the user is told, in their language.
+    pub fn message(&self, locale: Locale) -> String {
+        match self {
+            LoginError::AuthError(_) => locale.text("login.failed").to_string(),
+            LoginError::LockedOut(minutes) => {
+                locale.format("login.locked_out", &[("minutes", &minutes.to_string())])
+            }
+            LoginError::UnexpectedError(_) => locale.text("login.unexpected_error").to_string(),
+        }
+    }
+}
+
 impl std::fmt::Debug for LoginError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         error_chain_fmt(self, f)
@@ -0,0 +1,125 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+
+use super::post::{check_throttle, complete_login, login_redirect};
+use crate::audit::{record_audit_event, AuditAction, AuditContext};
+use crate::authentication::{verify_second_factor, CsrfToken, LoginThrottle};
+use crate::configuration::SessionSettings;
+use crate::i18n::Locale;
+use crate::routes::get_username;
+use crate::session_state::TypedSession;
+use crate::utils::{client_ip, e500, see_other};
+
+/// How long a user has to provide their second factor after their password.
+const PENDING_LOGIN_VALIDITY_MINUTES: i64 = 5;
+/// How many wrong codes a user can enter before having to start over
+/// with their password.
+const MAX_SECOND_FACTOR_ATTEMPTS: u32 = 5;
+
+pub async fn two_factor_form(
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+    csrf: CsrfToken,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_pending_login().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        locale
+            .localize(include_str!("two_factor.html"))
+            .replace("{msg}", &msg_html)
+            .replace("{csrf_field}", &csrf.form_field()),
+    ))
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    code: String,
+}
+
+#[tracing::instrument(
+    name = "Verify the second login factor",
+    skip_all,
+    fields(user_id = tracing::field::Empty)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn verify_two_factor(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    throttle: web::Data<LoginThrottle>,
+    request: HttpRequest,
+    audit: AuditContext,
+    session_settings: web::Data<SessionSettings>,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut pending = match session.get_pending_login().map_err(e500)? {
+        Some(pending) => pending,
+        None => return Ok(see_other("/login")),
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&pending.user_id));
+    if pending.started_at < Utc::now() - Duration::minutes(PENDING_LOGIN_VALIDITY_MINUTES) {
+        session.remove_pending_login();
+        FlashMessage::error(locale.text("two_factor.expired")).send();
+        return Ok(see_other("/login"));
+    }
+
+    // Codes are short: guesses count against the same limits as passwords.
+    let username = get_username(pending.user_id, &pool).await.map_err(e500)?;
+    let ip = client_ip(&request);
+    if let Err(e) = check_throttle(&throttle, &username, &ip).await {
+        session.remove_pending_login();
+        return Err(login_redirect(e, locale).into());
+    }
+    if !verify_second_factor(pending.user_id, &form.code, &pool)
+        .await
+        .map_err(e500)?
+    {
+        throttle
+            .record_failure(&username, &ip)
+            .await
+            .map_err(e500)?;
+        pending.failed_attempts += 1;
+        if pending.failed_attempts >= MAX_SECOND_FACTOR_ATTEMPTS {
+            session.remove_pending_login();
+            FlashMessage::error(locale.text("two_factor.too_many_attempts")).send();
+            return Ok(see_other("/login"));
+        }
+        session.insert_pending_login(pending).map_err(e500)?;
+        FlashMessage::error(locale.text("two_factor.invalid_code")).send();
+        return Ok(see_other("/login/two-factor"));
+    }
+    throttle.record_success(&username).await.map_err(e500)?;
+    session.remove_pending_login();
+    session.renew();
+    let response = complete_login(
+        &session,
+        pending.user_id,
+        pending.remember_me,
+        &audit,
+        &session_settings,
+        &pool,
+    )
+    .await
+    .map_err(e500)?;
+    record_audit_event(
+        pool.get_ref(),
+        &audit,
+        Some(pending.user_id),
+        AuditAction::Login,
+        None,
+    )
+    .await
+    .map_err(e500)?;
+    Ok(response)
+}
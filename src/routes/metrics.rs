@@ -0,0 +1,18 @@
+use actix_web::web::Data;
+use actix_web::HttpResponse;
+
+use crate::metrics::Metrics;
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "health",
+    responses(
+        (status = 200, description = "Counters in the Prometheus text format", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn metrics(metrics: Data<Metrics>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics.render())
+}
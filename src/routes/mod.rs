@@ -1,10 +1,22 @@
+mod api;
+mod archive;
+mod data_requests;
 mod health_check;
-mod newsletter;
+mod invitations;
+mod metrics;
+mod password_reset;
+mod preferences;
 mod subscription_confirm;
 mod subscriptions;
 
+pub use api::*;
+pub use archive::*;
+pub use data_requests::*;
 pub use health_check::*;
-pub use newsletter::*;
+pub use invitations::*;
+pub use metrics::*;
+pub use password_reset::*;
+pub use preferences::*;
 pub use subscription_confirm::*;
 pub use subscriptions::*;
 
@@ -0,0 +1,5 @@
+mod request;
+mod reset;
+
+pub use request::{request_password_reset, request_password_reset_form};
+pub use reset::{reset_password, reset_password_form, PasswordResetError};
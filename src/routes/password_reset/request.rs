@@ -0,0 +1,165 @@
+use std::fmt::Write;
+use std::sync::Arc;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use lettre::AsyncTransport;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::i18n::Locale;
+use crate::routes::generate_token;
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e500, see_other};
+
+pub async fn request_password_reset_form(
+    flash_messages: IncomingFlashMessages,
+    locale: Locale,
+) -> HttpResponse {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    HttpResponse::Ok().content_type(ContentType::html()).body(
+        locale
+            .localize(include_str!("request.html"))
+            .replace("{msg}", &msg_html),
+    )
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    username: String,
+}
+
+#[tracing::instrument(
+    name = "Request a password reset",
+    skip_all,
+    fields(username = %form.username)
+)]
+pub async fn request_password_reset<T>(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient<T>>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    locale: Locale,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    T: 'static + AsyncTransport + Send + Sync,
+    <T as AsyncTransport>::Error: 'static + Send + Sync,
+    <T as AsyncTransport>::Error: std::error::Error,
+{
+    let user = get_user_email(&pool, &form.username).await.map_err(e500)?;
+    if let Some((user_id, email)) = user {
+        // Storing the token and sending the email happen in the background,
+        // so that the response time does not tell whether the user exists.
+        let pool = pool.into_inner();
+        let email_client = email_client.into_inner();
+        let base_url = base_url.0.clone();
+        actix_web::rt::spawn(
+            async move {
+                if let Err(e) =
+                    send_reset_link(&pool, &email_client, user_id, email, &base_url).await
+                {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to send a password reset link."
+                    );
+                }
+            }
+            .in_current_span(),
+        );
+    }
+
+    FlashMessage::info(locale.text("password_reset.link_sent")).send();
+    Ok(see_other("/login"))
+}
+
+/// The id and email address of an active user who can log in.
+async fn get_user_email(
+    pool: &PgPool,
+    username: &str,
+) -> Result<Option<(Uuid, String)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id, email as "email!"
+        FROM users
+        WHERE
+            username = $1 AND
+            email IS NOT NULL AND
+            password_hash IS NOT NULL AND
+            deactivated_at IS NULL
+        "#,
+        username
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the user's email address.")?;
+    Ok(row.map(|r| (r.user_id, r.email)))
+}
+
+/// Reset tokens are as good as a password, so we only store their hash.
+pub fn hash_reset_token(reset_token: &str) -> String {
+    hex::encode(Sha256::digest(reset_token.as_bytes()))
+}
+
+#[tracing::instrument(
+    name = "Send a password reset link",
+    skip(pool, email_client, base_url)
+)]
+async fn send_reset_link<T>(
+    pool: &PgPool,
+    email_client: &Arc<EmailClient<T>>,
+    user_id: Uuid,
+    email: String,
+    base_url: &str,
+) -> Result<(), anyhow::Error>
+where
+    T: AsyncTransport + Send + Sync,
+    <T as AsyncTransport>::Error: 'static + Send + Sync,
+    <T as AsyncTransport>::Error: std::error::Error,
+{
+    let email = SubscriberEmail::parse(email).map_err(anyhow::Error::msg)?;
+    let reset_token = generate_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens (token_hash, user_id, requested_at)
+        VALUES ($1, $2, now())
+        "#,
+        hash_reset_token(&reset_token),
+        user_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the password reset token.")?;
+
+    let reset_link = format!("{base_url}/password-reset/confirm?reset_token={reset_token}");
+    let html_content = format!(
+        r#"<p>Click <a href="{}">here</a> to choose a new password.
+        The link is valid for one hour.<br />
+        If you did not ask for this, you can ignore this email.</p>"#,
+        reset_link
+    );
+    let text_content = format!(
+        "Visit {} to choose a new password.\n\
+        The link is valid for one hour.\n\
+        If you did not ask for this, you can ignore this email.",
+        reset_link
+    );
+    email_client
+        .send_email(
+            &email,
+            "Reset your password".to_owned(),
+            text_content,
+            html_content,
+        )
+        .await
+        .context("Failed to send the password reset email.")
+}
@@ -0,0 +1,144 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+use super::request::hash_reset_token;
+use crate::audit::{record_audit_event, AuditAction, AuditContext};
+use crate::authentication::revoke_sessions;
+use crate::i18n::Locale;
+use crate::routes::error_chain_fmt;
+use crate::utils::see_other;
+
+#[derive(thiserror::Error)]
+pub enum PasswordResetError {
+    #[error("The link you followed is invalid or has expired.")]
+    UnauthorizedError,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PasswordResetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PasswordResetError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PasswordResetError::UnauthorizedError => StatusCode::UNAUTHORIZED,
+            PasswordResetError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    reset_token: String,
+}
+
+#[tracing::instrument(name = "Show the password reset form", skip_all)]
+pub async fn reset_password_form(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    locale: Locale,
+) -> Result<HttpResponse, PasswordResetError> {
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id
+        FROM password_reset_tokens
+        WHERE token_hash = $1 AND requested_at > now() - interval '1 hour'
+        "#,
+        hash_reset_token(&parameters.reset_token)
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the password reset token.")?;
+    if row.is_none() {
+        return Err(PasswordResetError::UnauthorizedError);
+    }
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let html_page = locale
+        .localize(include_str!("reset.html"))
+        .replace("{msg}", &msg_html)
+        .replace("{reset_token}", &parameters.reset_token);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_page))
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    reset_token: String,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+#[tracing::instrument(name = "Reset a password", skip_all)]
+pub async fn reset_password(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    audit: AuditContext,
+    locale: Locale,
+) -> Result<HttpResponse, PasswordResetError> {
+    let FormData {
+        reset_token,
+        new_password,
+        new_password_check,
+    } = form.0;
+    if new_password.expose_secret() != new_password_check.expose_secret() {
+        FlashMessage::error(locale.text("password.mismatch")).send();
+        return Ok(see_other(&format!(
+            "/password-reset/confirm?reset_token={}",
+            reset_token
+        )));
+    }
+
+    // Consuming the token up front makes it single-use even under
+    // concurrent submissions.
+    let user_id = sqlx::query!(
+        r#"
+        DELETE FROM password_reset_tokens
+        WHERE token_hash = $1 AND requested_at > now() - interval '1 hour'
+        RETURNING user_id
+        "#,
+        hash_reset_token(&reset_token)
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to consume the password reset token.")?
+    .ok_or(PasswordResetError::UnauthorizedError)?
+    .user_id;
+
+    crate::authentication::change_password(user_id, new_password, &pool).await?;
+    revoke_sessions(user_id, &pool).await?;
+    sqlx::query!(
+        r#"DELETE FROM password_reset_tokens WHERE user_id = $1"#,
+        user_id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to discard the remaining password reset tokens.")?;
+    record_audit_event(
+        pool.get_ref(),
+        &audit,
+        Some(user_id),
+        AuditAction::PasswordReset,
+        None,
+    )
+    .await?;
+
+    FlashMessage::info(locale.text("password_reset.done")).send();
+    Ok(see_other("/login"))
+}
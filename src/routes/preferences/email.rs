@@ -0,0 +1,205 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use lettre::AsyncTransport;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::subscriber::{
+    get_subscriber_from_preference_token, preferences_redirect, PreferencesError,
+};
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailClient, EmailClientError};
+use crate::email_domains::{EmailDomainError, EmailDomainPolicy};
+use crate::i18n::Locale;
+use crate::routes::generate_token;
+use crate::startup::ApplicationBaseUrl;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    preference_token: String,
+    new_email: String,
+}
+
+#[tracing::instrument(name = "Request an email address change", skip_all)]
+pub async fn change_email<T>(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient<T>>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    domain_policy: web::Data<EmailDomainPolicy>,
+) -> Result<HttpResponse, PreferencesError>
+where
+    T: AsyncTransport + Send + Sync,
+    <T as AsyncTransport>::Error: 'static + Send + Sync,
+    <T as AsyncTransport>::Error: std::error::Error,
+{
+    let FormData {
+        preference_token,
+        new_email,
+    } = form.0;
+    let subscriber = get_subscriber_from_preference_token(&pool, &preference_token)
+        .await
+        .context("Failed to retrieve the subscriber associated with the provided token.")?
+        .ok_or(PreferencesError::UnauthorizedError)?;
+    let new_email = match SubscriberEmail::parse(new_email) {
+        Ok(email) => domain_policy.canonical(email),
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(preferences_redirect(&preference_token));
+        }
+    };
+    match domain_policy.check(&new_email, &pool).await {
+        Ok(()) => {}
+        Err(EmailDomainError::UnexpectedError(e)) => return Err(e.into()),
+        Err(rejected) => {
+            FlashMessage::error(rejected.to_string()).send();
+            return Ok(preferences_redirect(&preference_token));
+        }
+    }
+
+    let confirmation_token = generate_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO email_change_requests (
+            confirmation_token,
+            subscriber_id,
+            new_email,
+            requested_at
+        )
+        VALUES ($1, $2, $3, now())
+        "#,
+        confirmation_token,
+        subscriber.subscriber_id,
+        new_email.as_ref()
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the email change request.")?;
+    send_email_change_confirmation(
+        &email_client,
+        &new_email,
+        subscriber.locale,
+        &base_url.0,
+        &confirmation_token,
+    )
+    .await
+    .context("Failed to send the email change confirmation.")?;
+
+    FlashMessage::info(subscriber.locale.format(
+        "preferences.email_change_sent",
+        &[("email", new_email.as_ref())],
+    ))
+    .send();
+    Ok(preferences_redirect(&preference_token))
+}
+
+#[tracing::instrument(
+    name = "Send an email change confirmation",
+    skip(email_client, new_email, base_url, token)
+)]
+async fn send_email_change_confirmation<T>(
+    email_client: &EmailClient<T>,
+    new_email: &SubscriberEmail,
+    locale: Locale,
+    base_url: &str,
+    token: &str,
+) -> Result<(), EmailClientError>
+where
+    T: AsyncTransport + Send + Sync,
+    <T as AsyncTransport>::Error: 'static + Send + Sync,
+    <T as AsyncTransport>::Error: std::error::Error,
+{
+    let confirmation_link =
+        format!("{base_url}/subscriptions/preferences/email/confirm?confirmation_token={token}");
+    let args = [("link", confirmation_link.as_str())];
+    let html_content = format!(
+        r#"<p dir="{}">{}</p>"#,
+        locale.dir(),
+        locale.format("email_change.html", &args)
+    );
+    let text_content = locale.format("email_change.text", &args);
+    email_client
+        .send_email(
+            new_email,
+            locale.text("email_change.subject").to_owned(),
+            text_content,
+            html_content,
+        )
+        .await
+}
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    confirmation_token: String,
+}
+
+#[tracing::instrument(name = "Confirm an email address change", skip_all)]
+pub async fn confirm_email_change(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PreferencesError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a postgres connection from the pool")?;
+    let request = sqlx::query!(
+        r#"
+        DELETE FROM email_change_requests
+        WHERE
+            confirmation_token = $1 AND
+            requested_at > now() - interval '1 day'
+        RETURNING subscriber_id, new_email
+        "#,
+        parameters.confirmation_token
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve the email change request.")?
+    .ok_or(PreferencesError::UnauthorizedError)?;
+
+    if email_is_taken(&mut transaction, request.subscriber_id, &request.new_email)
+        .await
+        .context("Failed to check whether the new email address is in use.")?
+    {
+        return Err(PreferencesError::ConflictError(format!(
+            "{} is already subscribed.",
+            request.new_email
+        )));
+    }
+    sqlx::query!(
+        r#"UPDATE subscriptions SET email = $2 WHERE id = $1"#,
+        request.subscriber_id,
+        request.new_email
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to update the subscriber's email address.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to change an email address.")?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .body(format!(
+            "Your email address has been changed to {}.",
+            request.new_email
+        )))
+}
+
+async fn email_is_taken(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    subscriber_id: Uuid,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE lower(email) = lower($1) AND id != $2"#,
+        email,
+        subscriber_id
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(row.is_some())
+}
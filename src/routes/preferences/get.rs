@@ -0,0 +1,86 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use super::subscriber::{get_subscriber_from_preference_token, PreferencesError};
+use crate::utils::escape_html;
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    preference_token: String,
+}
+
+#[tracing::instrument(name = "Show subscription preferences", skip_all)]
+pub async fn preferences_form(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, PreferencesError> {
+    let subscriber = get_subscriber_from_preference_token(&pool, &parameters.preference_token)
+        .await
+        .context("Failed to retrieve the subscriber associated with the provided token.")?
+        .ok_or(PreferencesError::UnauthorizedError)?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        // Messages about invalid names and addresses repeat what was typed.
+        writeln!(msg_html, "<p><i>{}</i></p>", escape_html(m.content())).unwrap();
+    }
+    let lists = sqlx::query!(
+        r#"
+        SELECT l.list_id, l.name, s.status as "status?"
+        FROM lists l
+        LEFT JOIN list_subscriptions s
+            ON s.list_id = l.list_id AND s.subscriber_id = $1
+        ORDER BY l.created_at
+        "#,
+        subscriber.subscriber_id
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the subscriber's lists.")?;
+    let mut lists_html = String::new();
+    for list in lists {
+        // Lists waiting for confirmation stay ticked, or saving would drop them.
+        let (checked, pending) = match list.status.as_deref() {
+            Some("confirmed") => (" checked", String::new()),
+            Some("pending_confirmation") => (
+                " checked",
+                format!(" {}", subscriber.locale.text("preferences.pending")),
+            ),
+            _ => ("", String::new()),
+        };
+        writeln!(
+            lists_html,
+            r#"<label><input type="checkbox" name="list_id" value="{}"{}> {}</label>{}<br>"#,
+            list.list_id,
+            checked,
+            escape_html(&list.name),
+            pending
+        )
+        .unwrap();
+    }
+
+    let locale = subscriber.locale;
+    let subscribed_as = locale.format(
+        "preferences.subscribed_as",
+        &[("email", &escape_html(&subscriber.email))],
+    );
+    let html_page = locale
+        .localize(include_str!("preferences.html"))
+        .replace("{msg_html}", &msg_html)
+        .replace("{subscribed_as}", &subscribed_as)
+        .replace("{name}", &escape_html(&subscriber.name))
+        .replace("{lists_html}", &lists_html)
+        .replace(
+            "{preference_token}",
+            &escape_html(&parameters.preference_token),
+        );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html_page))
+}
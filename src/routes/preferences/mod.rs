@@ -0,0 +1,11 @@
+mod email;
+mod get;
+mod post;
+mod subscriber;
+mod unsubscribe;
+
+pub use email::{change_email, confirm_email_change};
+pub use get::preferences_form;
+pub use post::update_preferences;
+pub use subscriber::{append_preferences_link, PreferencesError};
+pub use unsubscribe::unsubscribe;
@@ -0,0 +1,176 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use lettre::AsyncTransport;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::subscriber::{
+    get_subscriber_from_preference_token, preferences_redirect, PreferencesError,
+};
+use crate::domain::{MailingList, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::routes::{generate_token, sends_confirmation_email, store_token};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::e400;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    preference_token: String,
+    name: String,
+    #[serde(default, rename = "list_id")]
+    list_ids: Vec<Uuid>,
+}
+
+#[tracing::instrument(name = "Update subscription preferences", skip_all)]
+pub async fn update_preferences<T>(
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient<T>>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    T: AsyncTransport + Send + Sync,
+    <T as AsyncTransport>::Error: 'static + Send + Sync,
+    <T as AsyncTransport>::Error: std::error::Error,
+{
+    // `web::Form` cannot collect the repeated `list_id` fields sent by the checkboxes.
+    let FormData {
+        preference_token,
+        name,
+        list_ids,
+    } = serde_html_form::from_bytes(&body).map_err(e400)?;
+    let subscriber = get_subscriber_from_preference_token(&pool, &preference_token)
+        .await
+        .context("Failed to retrieve the subscriber associated with the provided token.")
+        .map_err(PreferencesError::from)?
+        .ok_or(PreferencesError::UnauthorizedError)?;
+    let name = match SubscriberName::parse(name) {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(preferences_redirect(&preference_token));
+        }
+    };
+
+    let to_confirm = store_preferences(&pool, subscriber.subscriber_id, &name, &list_ids)
+        .await
+        .map_err(PreferencesError::from)?;
+    if !to_confirm.is_empty() {
+        let email = SubscriberEmail::parse(subscriber.email)
+            .map_err(anyhow::Error::msg)
+            .context("The stored subscriber email is invalid.")
+            .map_err(PreferencesError::from)?;
+        for (list, subscription_token) in to_confirm {
+            sends_confirmation_email(
+                &email_client,
+                &email,
+                subscriber.locale,
+                &list,
+                &base_url.0,
+                &subscription_token,
+                &preference_token,
+            )
+            .await
+            .context("Failed to send a confirmation email.")
+            .map_err(PreferencesError::from)?;
+        }
+    }
+    FlashMessage::info(subscriber.locale.text("preferences.updated")).send();
+    Ok(preferences_redirect(&preference_token))
+}
+
+/// Stores the name and the lists picked, and returns the lists that still
+/// need confirming along with the token to confirm each of them.
+#[tracing::instrument(name = "Store subscription preferences", skip(pool, name))]
+async fn store_preferences(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    name: &SubscriberName,
+    list_ids: &[Uuid],
+) -> Result<Vec<(MailingList, String)>, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a postgres connection from the pool")?;
+    let confirmed = sqlx::query!(
+        r#"UPDATE subscriptions SET name = $2 WHERE id = $1 RETURNING status"#,
+        subscriber_id,
+        name.as_ref()
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("Failed to update the subscriber's name.")?
+    .status
+        == "confirmed";
+    sqlx::query!(
+        r#"
+        UPDATE list_subscriptions SET status = 'unsubscribed'
+        WHERE subscriber_id = $1 AND NOT (list_id = ANY($2))
+        "#,
+        subscriber_id,
+        list_ids
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to leave the lists that were unticked.")?;
+    let joined = join_lists(&mut transaction, subscriber_id, list_ids, confirmed)
+        .await
+        .context("Failed to join the lists that were ticked.")?;
+    let mut to_confirm = vec![];
+    if !confirmed {
+        for list in joined {
+            let subscription_token = generate_token();
+            store_token(
+                &mut transaction,
+                subscriber_id,
+                list.list_id,
+                &subscription_token,
+            )
+            .await
+            .context("Failed to store a confirmation token.")?;
+            to_confirm.push((list, subscription_token));
+        }
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the subscriber's preferences.")?;
+    Ok(to_confirm)
+}
+
+/// Adds the subscriber to the lists among `list_ids` they are not on yet,
+/// and returns those. Subscribers who have confirmed their address join
+/// straight away; the others go through double opt-in, as on the
+/// subscription form, and stay pending until they follow the link.
+async fn join_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+    confirmed: bool,
+) -> Result<Vec<MailingList>, sqlx::Error> {
+    let status = if confirmed {
+        "confirmed"
+    } else {
+        "pending_confirmation"
+    };
+    sqlx::query_as!(
+        MailingList,
+        r#"
+        WITH joined AS (
+            INSERT INTO list_subscriptions (subscriber_id, list_id, status, subscribed_at)
+            SELECT $1, list_id, $3, now() FROM lists WHERE list_id = ANY($2)
+            ON CONFLICT (subscriber_id, list_id) DO UPDATE SET status = EXCLUDED.status
+            WHERE list_subscriptions.status NOT IN ('confirmed', EXCLUDED.status)
+            RETURNING list_id
+        )
+        SELECT l.list_id, l.slug, l.name
+        FROM lists l JOIN joined j ON j.list_id = l.list_id
+        "#,
+        subscriber_id,
+        list_ids,
+        status
+    )
+    .fetch_all(transaction)
+    .await
+}
@@ -0,0 +1,96 @@
+use std::fmt::Write;
+
+use actix_web::http::StatusCode;
+use actix_web::ResponseError;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::i18n::Locale;
+use crate::routes::error_chain_fmt;
+use crate::utils::see_other;
+
+pub struct Subscriber {
+    pub subscriber_id: Uuid,
+    pub email: String,
+    pub name: String,
+    /// The language they picked when subscribing.
+    pub locale: Locale,
+}
+
+#[derive(thiserror::Error)]
+pub enum PreferencesError {
+    #[error("The link you followed is invalid or has expired.")]
+    UnauthorizedError,
+    #[error("{0}")]
+    ConflictError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PreferencesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PreferencesError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PreferencesError::UnauthorizedError => StatusCode::UNAUTHORIZED,
+            PreferencesError::ConflictError(_) => StatusCode::CONFLICT,
+            PreferencesError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[tracing::instrument(name = "Get subscriber from preference token", skip_all)]
+pub async fn get_subscriber_from_preference_token(
+    pool: &PgPool,
+    preference_token: &str,
+) -> Result<Option<Subscriber>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT s.id, s.email, s.name, s.locale
+        FROM preference_tokens p
+        JOIN subscriptions s ON s.id = p.subscriber_id
+        WHERE p.preference_token = $1
+        "#,
+        preference_token
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|r| Subscriber {
+        subscriber_id: r.id,
+        email: r.email,
+        name: r.name,
+        locale: Locale::parse(&r.locale).unwrap_or_default(),
+    }))
+}
+
+pub fn preferences_redirect(preference_token: &str) -> actix_web::HttpResponse {
+    see_other(&format!(
+        "/subscriptions/preferences?preference_token={}",
+        preference_token
+    ))
+}
+
+/// Ends an email to a subscriber with a link to their preference page.
+pub fn append_preferences_link(
+    text_content: &mut String,
+    html_content: &mut String,
+    locale: Locale,
+    base_url: &str,
+    preference_token: &str,
+) {
+    let link = format!("{base_url}/subscriptions/preferences?preference_token={preference_token}");
+    let manage = locale.text("newsletter.manage_subscription");
+    write!(text_content, "\n\n--\n{}: {}", manage, link).unwrap();
+    write!(
+        html_content,
+        r#"<p dir="{}"><a href="{}">{}</a></p>"#,
+        locale.dir(),
+        link,
+        manage
+    )
+    .unwrap();
+}
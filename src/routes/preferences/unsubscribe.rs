@@ -0,0 +1,49 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::subscriber::{
+    get_subscriber_from_preference_token, preferences_redirect, PreferencesError,
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    preference_token: String,
+}
+
+#[tracing::instrument(name = "Unsubscribe from all lists", skip_all)]
+pub async fn unsubscribe(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, PreferencesError> {
+    let subscriber = get_subscriber_from_preference_token(&pool, &form.preference_token)
+        .await
+        .context("Failed to retrieve the subscriber associated with the provided token.")?
+        .ok_or(PreferencesError::UnauthorizedError)?;
+
+    mark_as_unsubscribed(&pool, subscriber.subscriber_id)
+        .await
+        .context("Failed to unsubscribe the subscriber.")?;
+    FlashMessage::info(subscriber.locale.text("preferences.unsubscribed")).send();
+    Ok(preferences_redirect(&form.preference_token))
+}
+
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(pool))]
+async fn mark_as_unsubscribed(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'unsubscribed' WHERE id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"UPDATE list_subscriptions SET status = 'unsubscribed' WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await
+}
@@ -3,10 +3,13 @@ use actix_web::{web, HttpResponse, ResponseError};
 use anyhow::Context;
 use serde::Deserialize;
 use sqlx::PgPool;
+use utoipa::IntoParams;
 use uuid::Uuid;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct Parameters {
+    /// From the link in the confirmation email.
     subscription_token: String,
 }
 
@@ -30,44 +33,73 @@ impl ResponseError for SubscriptionConfirmError {
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/subscriptions/confirm",
+    tag = "subscriptions",
+    params(Parameters),
+    responses(
+        (status = 200, description = "The subscription is confirmed"),
+        (status = 400, description = "The token is missing"),
+        (status = 401, description = "Unknown token", body = String, content_type = "text/plain"),
+    )
+)]
 #[tracing::instrument(name = "Confirm a pending subscription", skip(pool, parameters))]
 pub async fn confirm(
     pool: web::Data<PgPool>,
     parameters: web::Query<Parameters>,
 ) -> Result<HttpResponse, SubscriptionConfirmError> {
-    let subscriber_id = get_subscriber_id_from_token(&pool, &parameters.subscription_token)
-        .await
-        .context("Failed to find the requested user")?
-        .ok_or(SubscriptionConfirmError::UnauthorizedError)?;
+    let (subscriber_id, list_id) =
+        get_subscriber_id_from_token(&pool, &parameters.subscription_token)
+            .await
+            .context("Failed to find the requested user")?
+            .ok_or(SubscriptionConfirmError::UnauthorizedError)?;
 
-    confirm_subscriber(&pool, subscriber_id)
+    confirm_subscriber(&pool, subscriber_id, list_id)
         .await
         .context("Failed to edit the subscriber record")?;
     Ok(HttpResponse::Ok().finish())
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+#[tracing::instrument(name = "Mark subscriber as confirmed", skip(pool))]
+pub async fn confirm_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
     sqlx::query!(
         r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
         subscriber_id
     )
-    .execute(pool)
+    .execute(&mut transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query {:?}", e);
+        e
+    })?;
+    sqlx::query!(
+        r#"UPDATE list_subscriptions SET status = 'confirmed'
+        WHERE subscriber_id = $1 AND list_id = $2"#,
+        subscriber_id,
+        list_id
+    )
+    .execute(&mut transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query {:?}", e);
         e
     })?;
-    Ok(())
+    transaction.commit().await
 }
 
 #[tracing::instrument(name = "Get subscriber_id from token", skip(pool, subscription_token))]
 pub async fn get_subscriber_id_from_token(
     pool: &PgPool,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
+) -> Result<Option<(Uuid, Uuid)>, sqlx::Error> {
     let result = sqlx::query!(
-        r#"SELECT subscription_id, subscription_token FROM subscription_tokens WHERE subscription_token = $1"#,
+        r#"SELECT subscription_id, list_id FROM subscription_tokens WHERE subscription_token = $1"#,
         subscription_token
     )
     .fetch_optional(pool)
@@ -76,5 +108,5 @@ pub async fn get_subscriber_id_from_token(
         tracing::error!("Failed to execute query: {:?}", e);
         e
     })?;
-    Ok(result.map(|r| r.subscription_id))
+    Ok(result.map(|r| (r.subscription_id, r.list_id)))
 }
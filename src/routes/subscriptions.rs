@@ -1,82 +1,254 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
 
 use actix_web::http::StatusCode;
 use actix_web::web::{Data, Form};
-use actix_web::{HttpResponse, ResponseError};
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
 use chrono::Utc;
 use lettre::AsyncTransport;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use sqlx::{PgPool, Postgres, Transaction};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::anti_spam::{SpamFilter, SpamReason, SpamSignals};
+use crate::domain::{
+    ListSlug, MailingList, NewSubscriber, SubscriberAttributes, SubscriberEmail, SubscriberName,
+    DEFAULT_LIST_SLUG,
+};
 use crate::email_client::{EmailClient, EmailClientError};
+use crate::email_domains::{EmailDomainError, EmailDomainPolicy};
+use crate::i18n::{parse_locale, Locale};
+use crate::metrics::Metrics;
+use crate::routes::append_preferences_link;
 use crate::startup::ApplicationBaseUrl;
+use crate::utils::{client_ip, escape_html};
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, ToSchema)]
+#[schema(as = SubscribeForm)]
 pub struct FormData {
     email: String,
     name: String,
+    /// The slug of the list to subscribe to; the default list if missing.
+    list: Option<String>,
+    /// Left empty by people: the form hides it from them.
+    website: Option<String>,
+    /// The signed time the form was rendered at, as put in the forms we
+    /// serve. Needed unless the minimum time to submit is turned off.
+    form_stamp: Option<String>,
+    /// The answer to the challenge, when one is configured.
+    challenge_response: Option<String>,
+    /// The language of the emails they get, such as `fa`. Negotiated from
+    /// `Accept-Language` if missing.
+    locale: Option<String>,
+    /// Fields named `attr_<name>` (usually hidden inputs such as
+    /// `attr_country` or `attr_source`) are stored as the subscriber's
+    /// `<name>` attribute for segmentation. Any other field is ignored.
+    #[serde(flatten)]
+    attributes: HashMap<String, String>,
 }
 
+/// Only fields carrying this prefix become attributes, so that whatever
+/// else a page or a bot posts is not stored.
+const ATTRIBUTE_FIELD_PREFIX: &str = "attr_";
+
 impl TryFrom<FormData> for NewSubscriber {
     type Error = String;
 
     fn try_from(value: FormData) -> Result<Self, Self::Error> {
         let name = SubscriberName::parse(value.name)?;
         let email = SubscriberEmail::parse(value.email)?;
-        Ok(Self { name, email })
+        let list = ListSlug::parse(value.list.unwrap_or_else(|| DEFAULT_LIST_SLUG.into()))?;
+        let attributes = SubscriberAttributes::parse(value.attributes.into_iter().filter_map(
+            |(field, value)| {
+                let key = field.strip_prefix(ATTRIBUTE_FIELD_PREFIX)?;
+                Some((key.to_owned(), value))
+            },
+        ))?;
+        let locale = parse_locale(value.locale)?;
+        Ok(Self {
+            name,
+            email,
+            list,
+            attributes,
+            locale,
+        })
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/subscriptions",
+    tag = "subscriptions",
+    request_body(content = SubscribeForm, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "A confirmation email has been sent, unless the attempt looked like spam"),
+        (status = 400, description = "Invalid subscriber", body = String, content_type = "text/plain"),
+    )
+)]
 #[tracing::instrument(
 name = "Adding a new subscriber.",
-skip(form, pool, email_client, base_url),
+skip(form, request, pool, email_client, base_url, spam_filter, metrics, domain_policy, locale),
 fields(
 subscriber_email = % form.email,
 subscriber_name = % form.name
 )
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn subscribe<T>(
     form: Form<FormData>,
+    request: HttpRequest,
     pool: Data<PgPool>,
     email_client: Data<EmailClient<T>>,
     base_url: Data<ApplicationBaseUrl>,
+    spam_filter: Data<SpamFilter>,
+    metrics: Data<Metrics>,
+    domain_policy: Data<EmailDomainPolicy>,
+    locale: Locale,
 ) -> Result<HttpResponse, SubscribeError>
 where
     T: AsyncTransport + Send + Sync,
     <T as AsyncTransport>::Error: 'static + Send + Sync,
     <T as AsyncTransport>::Error: std::error::Error,
 {
-    let new_subscriber: NewSubscriber =
-        form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let mut form = form.0;
+    let ip = client_ip(&request);
+    let signals = SpamSignals {
+        honeypot: form.website.take(),
+        form_stamp: form.form_stamp.take(),
+        challenge_response: form.challenge_response.take(),
+    };
+    if let Some(reason) = spam_filter.screen(&signals, &ip).await {
+        return Ok(drop_as_spam(reason, &metrics));
+    }
+    form.locale
+        .get_or_insert_with(|| locale.as_str().to_owned());
+    let mut new_subscriber: NewSubscriber =
+        form.try_into().map_err(SubscribeError::ValidationError)?;
+    new_subscriber.email = domain_policy.canonical(new_subscriber.email);
+    if let Some(reason) = spam_filter
+        .rate_limit(&ip, new_subscriber.email.domain())
+        .await?
+    {
+        return Ok(drop_as_spam(reason, &metrics));
+    }
+    register_subscriber(
+        &pool,
+        &email_client,
+        &base_url.0,
+        &domain_policy,
+        &new_subscriber,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Bots are answered as if they had subscribed, so that they learn nothing
+/// from trying.
+fn drop_as_spam(reason: SpamReason, metrics: &Metrics) -> HttpResponse {
+    tracing::info!(reason = reason.as_str(), "Dropped a subscription as spam");
+    metrics.record_dropped_subscription(reason);
+    HttpResponse::Ok().finish()
+}
+
+/// Adds `new_subscriber` to the list they asked for, creating them if
+/// needed, and emails them a link to confirm.
+pub async fn register_subscriber<T>(
+    pool: &PgPool,
+    email_client: &EmailClient<T>,
+    base_url: &str,
+    domain_policy: &EmailDomainPolicy,
+    new_subscriber: &NewSubscriber,
+) -> Result<(), SubscribeError>
+where
+    T: AsyncTransport + Send + Sync,
+    <T as AsyncTransport>::Error: 'static + Send + Sync,
+    <T as AsyncTransport>::Error: std::error::Error,
+{
+    domain_policy.check(&new_subscriber.email, pool).await?;
+    let list = get_list_by_slug(pool, &new_subscriber.list)
+        .await
+        .context("Failed to look up the requested list.")?
+        .ok_or_else(|| {
+            SubscribeError::ValidationError(format!(
+                "{} is not a known list.",
+                new_subscriber.list.as_ref()
+            ))
+        })?;
 
     let mut transaction = pool
         .begin()
         .await
         .context("Failed to acquire a postgres connection from the pool")?;
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
-        .await
-        .context("Failed to insert a subscriber in the database.")?;
-    let subscription_token = generate_subscription_token().await;
-    store_token(&mut transaction, subscriber_id, &subscription_token)
+    let (subscriber_id, preference_token) =
+        match get_subscriber_id_by_email(&mut transaction, &new_subscriber.email)
+            .await
+            .context("Failed to look up an existing subscriber.")?
+        {
+            Some(subscriber_id) => {
+                let preference_token = get_preference_token(&mut transaction, subscriber_id)
+                    .await
+                    .context("Failed to look up the subscriber's preference token")?;
+                (subscriber_id, preference_token)
+            }
+            None => {
+                let subscriber_id = insert_subscriber(&mut transaction, new_subscriber)
+                    .await
+                    .context("Failed to insert a subscriber in the database.")?;
+                let preference_token = generate_token();
+                store_preference_token(&mut transaction, subscriber_id, &preference_token)
+                    .await
+                    .context("Failed to store the preference token for a new subscriber")?;
+                // Only for new subscribers: anyone can post the form with an
+                // existing subscriber's address, and must not change their segments.
+                store_attributes(&mut transaction, subscriber_id, &new_subscriber.attributes)
+                    .await
+                    .context("Failed to store the subscriber attributes.")?;
+                (subscriber_id, preference_token)
+            }
+        };
+    add_subscriber_to_list(&mut transaction, subscriber_id, list.list_id)
         .await
-        .context("Failed to store the confirmation token for a new subscriber")?;
+        .context("Failed to add the subscriber to the requested list.")?;
+    let subscription_token = generate_token();
+    store_token(
+        &mut transaction,
+        subscriber_id,
+        list.list_id,
+        &subscription_token,
+    )
+    .await
+    .context("Failed to store the confirmation token for a new subscriber")?;
+    // Sent before committing, so that an address the relay cannot take
+    // leaves nothing behind and can be retried once it is fixed.
+    let sent = sends_confirmation_email(
+        email_client,
+        &new_subscriber.email,
+        new_subscriber.locale,
+        &list,
+        base_url,
+        &subscription_token,
+        &preference_token,
+    )
+    .await;
+    if let Err(EmailClientError::Smtputf8NotSupported(email)) = sent {
+        transaction
+            .rollback()
+            .await
+            .context("Failed to roll back the SQL transaction for a new subscriber")?;
+        return Err(SubscribeError::ValidationError(format!(
+            "{} is an internationalized address, which we cannot send mail to yet.",
+            email
+        )));
+    }
     transaction
         .commit()
         .await
         .context("Failed to commit SQL transaction to store a new subscriber")?;
-    sends_confirmation_email(
-        &email_client,
-        &new_subscriber,
-        &base_url.0,
-        &subscription_token,
-    )
-    .await
-    .context("Failed to send a confirmation email.")?;
-    Ok(HttpResponse::Ok().finish())
+    sent.context("Failed to send a confirmation email.")?;
+    Ok(())
 }
 
 #[derive(thiserror::Error)]
@@ -87,6 +259,15 @@ pub enum SubscribeError {
     UnexpectedError(#[from] anyhow::Error),
 }
 
+impl From<EmailDomainError> for SubscribeError {
+    fn from(e: EmailDomainError) -> Self {
+        match e {
+            EmailDomainError::UnexpectedError(e) => SubscribeError::UnexpectedError(e),
+            rejected => SubscribeError::ValidationError(rejected.to_string()),
+        }
+    }
+}
+
 impl std::fmt::Debug for SubscribeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         error_chain_fmt(self, f)
@@ -102,7 +283,7 @@ impl ResponseError for SubscribeError {
     }
 }
 
-async fn generate_subscription_token() -> String {
+pub fn generate_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -114,16 +295,18 @@ async fn generate_subscription_token() -> String {
     name = "Store subscription token in the database",
     skip(transaction, subscription_token)
 )]
-async fn store_token(
+pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
+    list_id: Uuid,
     subscription_token: &str,
 ) -> Result<(), StoreTokenError> {
     sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscription_id)
-    VALUES ($1, $2)"#,
+        r#"INSERT INTO subscription_tokens (subscription_token, subscription_id, list_id)
+    VALUES ($1, $2, $3)"#,
         subscription_token,
-        subscriber_id
+        subscriber_id,
+        list_id
     )
     .execute(transaction)
     .await
@@ -158,15 +341,19 @@ impl std::fmt::Display for StoreTokenError {
     }
 }
 
+/// Asks `email` to confirm they want to receive `list`.
 #[tracing::instrument(
     name = "sends a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber, base_url)
+    skip(email_client, email, list, base_url, token, preference_token)
 )]
-async fn sends_confirmation_email<T>(
+pub async fn sends_confirmation_email<T>(
     email_client: &EmailClient<T>,
-    new_subscriber: &NewSubscriber,
+    email: &SubscriberEmail,
+    locale: Locale,
+    list: &MailingList,
     base_url: &str,
     token: &str,
+    preference_token: &str,
 ) -> Result<(), EmailClientError>
 where
     T: AsyncTransport + Send + Sync,
@@ -174,20 +361,34 @@ where
     <T as AsyncTransport>::Error: std::error::Error,
 {
     let confirmation_link = format!("{base_url}/subscriptions/confirm?subscription_token={token}");
-    let html_content = format!(
+    let mut html_content = format!(
         r#"
-<h2>Welcome to our newsletter please click here <a href="{}">here</a></h2>
+<h2 dir="{}">{}</h2>
 "#,
-        confirmation_link
+        locale.dir(),
+        locale.format(
+            "confirmation.html",
+            &[
+                ("list", &escape_html(&list.name)),
+                ("link", &confirmation_link)
+            ]
+        )
+    );
+    let mut email_content = locale.format(
+        "confirmation.text",
+        &[("list", &list.name), ("link", &confirmation_link)],
     );
-    let email_content = format!(
-        "Welcome to our newsletter click here to confirm your subscription {}",
-        confirmation_link
+    append_preferences_link(
+        &mut email_content,
+        &mut html_content,
+        locale,
+        base_url,
+        preference_token,
     );
     email_client
         .send_email(
-            &new_subscriber.email,
-            "Welcome".to_owned(),
+            email,
+            locale.text("confirmation.subject").to_owned(),
             email_content,
             html_content,
         )
@@ -205,13 +406,14 @@ pub async fn insert_subscriber(
     let subscriber_id = Uuid::new_v4();
     sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, locale)
+        VALUES ($1, $2, $3, $4, 'pending_confirmation', $5)
     "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        new_subscriber.locale.as_str()
     )
     .execute(transaction)
     .await
@@ -223,6 +425,112 @@ pub async fn insert_subscriber(
     Ok(subscriber_id)
 }
 
+#[tracing::instrument(
+    name = "Store preference token in the database",
+    skip(transaction, preference_token)
+)]
+async fn store_preference_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    preference_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO preference_tokens (preference_token, subscriber_id)
+        VALUES ($1, $2)"#,
+        preference_token,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get the preference token of a subscriber", skip(transaction))]
+async fn get_preference_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<String, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT preference_token FROM preference_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .fetch_one(transaction)
+    .await?;
+    Ok(row.preference_token)
+}
+
+#[tracing::instrument(name = "Get a list by its slug", skip(pool))]
+pub async fn get_list_by_slug(
+    pool: &PgPool,
+    slug: &ListSlug,
+) -> Result<Option<MailingList>, sqlx::Error> {
+    sqlx::query_as!(
+        MailingList,
+        r#"SELECT list_id, slug, name FROM lists WHERE slug = $1"#,
+        slug.as_ref()
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get subscriber_id from email", skip(transaction, email))]
+async fn get_subscriber_id_by_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE lower(email) = lower($1)"#,
+        email.as_ref()
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(result.map(|r| r.id))
+}
+
+#[tracing::instrument(name = "Add subscriber to a list", skip(transaction))]
+async fn add_subscriber_to_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (subscriber_id, list_id, status, subscribed_at)
+        VALUES ($1, $2, 'pending_confirmation', $3)
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        list_id,
+        Utc::now()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Store subscriber attributes", skip(transaction, attributes))]
+async fn store_attributes(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    attributes: &SubscriberAttributes,
+) -> Result<(), sqlx::Error> {
+    if attributes.is_empty() {
+        return Ok(());
+    }
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_attributes (subscriber_id, key, value)
+        SELECT $1, * FROM UNNEST($2::text[], $3::text[])
+        "#,
+        subscriber_id,
+        &attributes.keys(),
+        &attributes.values()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
 pub fn error_chain_fmt(
     e: &impl std::error::Error,
     f: &mut std::fmt::Formatter<'_>,
@@ -0,0 +1,133 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{
+    HeaderMap, HeaderValue, CONTENT_SECURITY_POLICY, REFERRER_POLICY, STRICT_TRANSPORT_SECURITY,
+    X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+};
+use actix_web::web;
+use actix_web_lab::middleware::Next;
+use anyhow::Context;
+
+use crate::configuration::SecurityHeadersSettings;
+
+/// Paths used by admin users rather than subscribers: they get the strict
+/// content security policy.
+const ADMIN_PREFIXES: [&str; 4] = ["/admin", "/login", "/invitations", "/password-reset"];
+
+/// The headers added to every response, checked once at startup.
+pub struct SecurityHeaders {
+    admin_content_security_policy: HeaderValue,
+    public_content_security_policy: HeaderValue,
+    strict_transport_security: Option<HeaderValue>,
+    frame_options: HeaderValue,
+    referrer_policy: HeaderValue,
+}
+
+impl TryFrom<&SecurityHeadersSettings> for SecurityHeaders {
+    type Error = anyhow::Error;
+
+    fn try_from(settings: &SecurityHeadersSettings) -> Result<Self, Self::Error> {
+        let strict_transport_security = match settings.hsts_max_age_seconds {
+            0 => None,
+            max_age => Some(HeaderValue::from_str(&format!(
+                "max-age={}; includeSubDomains",
+                max_age
+            ))?),
+        };
+        Ok(Self {
+            admin_content_security_policy: HeaderValue::from_str(
+                &settings.admin_content_security_policy,
+            )
+            .context("Invalid admin content security policy")?,
+            public_content_security_policy: HeaderValue::from_str(
+                &settings.public_content_security_policy,
+            )
+            .context("Invalid public content security policy")?,
+            strict_transport_security,
+            frame_options: HeaderValue::from_str(&settings.frame_options)
+                .context("Invalid frame options")?,
+            referrer_policy: HeaderValue::from_str(&settings.referrer_policy)
+                .context("Invalid referrer policy")?,
+        })
+    }
+}
+
+impl SecurityHeaders {
+    fn apply(&self, path_is_admin: bool, headers: &mut HeaderMap) {
+        let content_security_policy = if path_is_admin {
+            &self.admin_content_security_policy
+        } else {
+            &self.public_content_security_policy
+        };
+        let mut values = vec![
+            (CONTENT_SECURITY_POLICY, content_security_policy),
+            (X_FRAME_OPTIONS, &self.frame_options),
+            (REFERRER_POLICY, &self.referrer_policy),
+        ];
+        if let Some(hsts) = &self.strict_transport_security {
+            values.push((STRICT_TRANSPORT_SECURITY, hsts));
+        }
+        for (name, value) in values {
+            if !headers.contains_key(&name) {
+                headers.insert(name, value.clone());
+            }
+        }
+        if !headers.contains_key(X_CONTENT_TYPE_OPTIONS) {
+            headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+        }
+    }
+}
+
+fn is_admin_path(path: &str) -> bool {
+    ADMIN_PREFIXES.iter().any(|prefix| {
+        path.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
+
+/// Adds the security headers to every response, leaving alone those a
+/// handler has set itself.
+pub async fn add_security_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let headers = req
+        .app_data::<web::Data<SecurityHeaders>>()
+        .expect("The security headers are not registered")
+        .clone();
+    let path_is_admin = is_admin_path(req.path());
+    match next.call(req).await {
+        Ok(mut res) => {
+            headers.apply(path_is_admin, res.headers_mut());
+            Ok(res)
+        }
+        // Errors raised by other middleware, such as the redirect of
+        // anonymous users, only become responses further out.
+        Err(e) => {
+            let mut response = e.error_response();
+            headers.apply(path_is_admin, response.headers_mut());
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_admin_path;
+
+    #[test]
+    fn admin_pages_are_recognised() {
+        assert!(is_admin_path("/admin"));
+        assert!(is_admin_path("/admin/dashboard"));
+        assert!(is_admin_path("/login/two-factor"));
+        assert!(is_admin_path("/password-reset/confirm"));
+    }
+
+    #[test]
+    fn public_pages_are_not_admin_pages() {
+        assert!(!is_admin_path("/"));
+        assert!(!is_admin_path("/subscriptions/preferences"));
+        assert!(!is_admin_path("/administrators"));
+    }
+}
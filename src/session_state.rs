@@ -1,16 +1,45 @@
 use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
-use actix_web::dev::Payload;
-use actix_web::{FromRequest, HttpRequest};
+use actix_web::body::MessageBody;
+use actix_web::cookie::{time, Cookie};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderValue, SET_COOKIE};
+use actix_web::{web, FromRequest, HttpRequest};
+use actix_web_lab::middleware::Next;
 
+use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, Secret};
 use std::future::{ready, Ready};
 
 use uuid::Uuid;
 
+use crate::configuration::SessionSettings;
+
 #[derive(Clone)]
 pub struct TypedSession(pub Session);
 
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct PendingLogin {
+    pub user_id: Uuid,
+    pub started_at: DateTime<Utc>,
+    #[serde(default)]
+    pub remember_me: bool,
+    /// Wrong codes entered since the password was checked.
+    #[serde(default)]
+    pub failed_attempts: u32,
+}
+
+/// Attached to the responses of remembered sessions, so that
+/// `persist_remembered_sessions` can give their cookie a lifetime.
+#[derive(Clone, Copy, Debug)]
+pub struct RememberedUntil(pub DateTime<Utc>);
+
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const SESSION_ID_KEY: &'static str = "session_id";
+    const REMEMBERED_UNTIL_KEY: &'static str = "remembered_until";
+    const CSRF_TOKEN_KEY: &'static str = "csrf_token";
+    const PENDING_LOGIN_KEY: &'static str = "pending_login";
+    const TOTP_ENROLLMENT_KEY: &'static str = "totp_enrollment";
 
     pub fn renew(&self) {
         self.0.renew();
@@ -24,6 +53,69 @@ impl TypedSession {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    /// The id of the `user_sessions` row tracking this login.
+    pub fn insert_session_id(&self, session_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::SESSION_ID_KEY, session_id)
+    }
+
+    pub fn get_session_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::SESSION_ID_KEY)
+    }
+
+    /// Set for users who asked to be remembered, until their session expires.
+    pub fn insert_remembered_until(
+        &self,
+        remembered_until: DateTime<Utc>,
+    ) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::REMEMBERED_UNTIL_KEY, remembered_until)
+    }
+
+    pub fn get_remembered_until(&self) -> Result<Option<DateTime<Utc>>, SessionGetError> {
+        self.0.get(Self::REMEMBERED_UNTIL_KEY)
+    }
+
+    pub fn insert_csrf_token(&self, token: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::CSRF_TOKEN_KEY, token)
+    }
+
+    pub fn get_csrf_token(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::CSRF_TOKEN_KEY)
+    }
+
+    /// A user who got their password right but still has to provide
+    /// their second factor.
+    pub fn insert_pending_login(&self, pending: PendingLogin) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PENDING_LOGIN_KEY, pending)
+    }
+
+    pub fn get_pending_login(&self) -> Result<Option<PendingLogin>, SessionGetError> {
+        self.0.get(Self::PENDING_LOGIN_KEY)
+    }
+
+    pub fn remove_pending_login(&self) {
+        self.0.remove(Self::PENDING_LOGIN_KEY);
+    }
+
+    /// The TOTP secret being enrolled, until the user confirms it with a code.
+    pub fn insert_totp_enrollment(
+        &self,
+        secret: &Secret<String>,
+    ) -> Result<(), SessionInsertError> {
+        self.0
+            .insert(Self::TOTP_ENROLLMENT_KEY, secret.expose_secret())
+    }
+
+    pub fn get_totp_enrollment(&self) -> Result<Option<Secret<String>>, SessionGetError> {
+        Ok(self
+            .0
+            .get::<String>(Self::TOTP_ENROLLMENT_KEY)?
+            .map(Secret::new))
+    }
+
+    pub fn remove_totp_enrollment(&self) {
+        self.0.remove(Self::TOTP_ENROLLMENT_KEY);
+    }
+
     pub fn logout(&self) {
         self.0.purge()
     }
@@ -37,3 +129,44 @@ impl FromRequest for TypedSession {
         ready(Ok(TypedSession(req.get_session())))
     }
 }
+
+/// The session middleware only issues cookies that die with the browser.
+/// This turns the session cookie of remembered sessions into a persistent
+/// one, whenever it is (re)issued.
+/// Must wrap the session middleware.
+pub async fn persist_remembered_sessions(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let cookie_name = req
+        .app_data::<web::Data<SessionSettings>>()
+        .expect("The session settings are not registered")
+        .cookie_name
+        .clone();
+    let mut res = next.call(req).await?;
+    let remembered_until = res
+        .response()
+        .extensions()
+        .get::<RememberedUntil>()
+        .copied();
+    if let Some(RememberedUntil(until)) = remembered_until {
+        let max_age = time::Duration::seconds((until - Utc::now()).num_seconds().max(0));
+        let headers = res.headers_mut();
+        let cookies: Vec<HeaderValue> = headers.get_all(SET_COOKIE).cloned().collect();
+        headers.remove(SET_COOKIE);
+        for value in cookies {
+            let persisted = value
+                .to_str()
+                .ok()
+                .and_then(|v| Cookie::parse(v).ok())
+                // A cookie with a `Max-Age` already is being removed.
+                .filter(|c| c.name() == cookie_name && c.max_age().is_none())
+                .and_then(|mut c| {
+                    c.set_max_age(max_age);
+                    HeaderValue::from_str(&c.to_string()).ok()
+                });
+            headers.append(SET_COOKIE, persisted.unwrap_or(value));
+        }
+    }
+    Ok(res)
+}
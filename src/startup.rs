@@ -1,15 +1,17 @@
+use actix_session::config::{BrowserSession, TtlExtensionPolicy};
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
 use actix_web_lab::middleware::from_fn;
+use anyhow::Context;
 use std::any::Any;
 use std::collections::HashMap;
 use std::net::TcpListener;
 use std::sync::Arc;
 
-use actix_web::cookie::Key;
+use actix_web::cookie::{time, Key};
 use actix_web::dev::Server;
 use actix_web::web::Data;
-use actix_web::{web, App, HttpServer};
+use actix_web::{guard, web, App, HttpServer};
 use actix_web_flash_messages::storage::CookieMessageStore;
 use actix_web_flash_messages::FlashMessagesFramework;
 use lettre::AsyncTransport;
@@ -17,15 +19,37 @@ use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
 use tracing_actix_web::TracingLogger;
 
-use crate::authentication::reject_anonymous_users;
-use crate::configuration::Settings;
-use crate::domain::SubscriberName;
+use crate::anti_spam::SpamFilter;
+use crate::authentication::{
+    reject_anonymous_users, reject_forged_forms, reject_invalid_api_tokens, require_editor,
+    require_owner, set_seed_user_email, LoginThrottle,
+};
+use crate::configuration::{
+    LoginThrottleSettings, SecurityHeadersSettings, SessionSettings, Settings,
+    SubscriptionProtectionSettings,
+};
+use crate::domain::{SubscriberEmail, SubscriberName};
 use crate::email_client;
 use crate::email_client::{EmailClient, SenderInfo};
+use crate::email_domains::{EmailDomainPolicy, MxResolver};
+use crate::metrics::Metrics;
 use crate::routes::{
-    admin_dashboard, change_password, change_password_form, confirm, health_check, home, log_out,
-    login, login_form, publish_newsletter, publish_newsletter_form, subscribe,
+    accept_invitation, accept_invitation_form, admin_dashboard, api_tokens_form, api_v1_routes,
+    archive, archived_issue, audit_log, change_email, change_password, change_password_form,
+    confirm, confirm_data_request, confirm_email_change, create_api_token, create_list,
+    data_request_form, deactivate_user, disable_two_factor, email_domains_form, enable_two_factor,
+    erase_subscriber, export_issue_deliveries, export_subscriber, export_subscriptions,
+    health_check, home, invite_user, json_error_handler, lockouts_form, log_out, login, login_form,
+    manage_lists_form, manage_users_form, metrics, openapi_spec, path_error_handler,
+    preferences_form, publish_newsletter, publish_newsletter_form, query_error_handler,
+    remove_email_domain_rule, request_password_reset, request_password_reset_form,
+    request_subscriber_data, reset_password, reset_password_form, revoke_api_token,
+    revoke_other_sessions, revoke_session, save_email_domain_rule, sessions_form, subscribe,
+    subscriber_data_form, two_factor_form, two_factor_settings, unlock, unsubscribe,
+    update_preferences, verify_two_factor,
 };
+use crate::security_headers::{add_security_headers, SecurityHeaders};
+use crate::session_state::persist_remembered_sessions;
 
 pub struct Application {
     port: u16,
@@ -36,6 +60,7 @@ impl Application {
     pub async fn build<T>(
         configuration: Settings,
         email_client: Arc<EmailClient<T>>,
+        mx_resolver: Option<Arc<dyn MxResolver>>,
     ) -> Result<Self, anyhow::Error>
     where
         T: 'static + AsyncTransport + Send + Sync,
@@ -47,6 +72,13 @@ impl Application {
             configuration.application.host, configuration.application.port
         );
         let connection_pool = get_connection_pool(&configuration).await;
+        if let Some(admin_email) = configuration.application.admin_email {
+            let admin_email = SubscriberEmail::parse(admin_email)
+                .map_err(anyhow::Error::msg)
+                .context("Invalid admin email address")?;
+            set_seed_user_email(&connection_pool, &admin_email).await?;
+        }
+        let domain_policy = EmailDomainPolicy::new(configuration.email_domains, mx_resolver)?;
 
         tracing::info!("listening on {}", &address);
         let listener = TcpListener::bind(address).expect("Failed to bind random port");
@@ -58,7 +90,12 @@ impl Application {
             email_client,
             configuration.application.base_url,
             configuration.application.hmac_secret,
+            configuration.application.session,
             configuration.redis_uri,
+            configuration.login_throttle,
+            configuration.security_headers,
+            configuration.subscription_protection,
+            domain_policy,
         )
         .await?;
 
@@ -91,6 +128,7 @@ pub enum ApplicationData {
 pub struct ApplicationBuilder {
     configuration: Settings,
     items: HashMap<ApplicationData, Arc<dyn Any + Send + Sync>>,
+    mx_resolver: Option<Arc<dyn MxResolver>>,
 }
 
 impl ApplicationBuilder {
@@ -98,9 +136,16 @@ impl ApplicationBuilder {
         ApplicationBuilder {
             configuration,
             items: HashMap::new(),
+            mx_resolver: None,
         }
     }
 
+    /// Replaces DNS when checking that subscriber domains receive email.
+    pub fn set_mx_resolver(mut self, resolver: Arc<dyn MxResolver>) -> Self {
+        self.mx_resolver = Some(resolver);
+        self
+    }
+
     pub fn store<T: Any + Send + Sync + 'static>(
         mut self,
         key: ApplicationData,
@@ -137,7 +182,7 @@ impl ApplicationBuilder {
     {
         let email_client = self.get_item::<EmailClient<T>>(ApplicationData::EmailClient);
 
-        Application::build(self.configuration, email_client)
+        Application::build(self.configuration, email_client, self.mx_resolver)
             .await
             .expect("Failed to build the application")
     }
@@ -145,13 +190,19 @@ impl ApplicationBuilder {
 
 pub struct ApplicationBaseUrl(pub String);
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run<E>(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: Arc<EmailClient<E>>,
     base_url: String,
     hmac_secret: Secret<String>,
+    session_settings: SessionSettings,
     redis_uri: Secret<String>,
+    login_throttle: LoginThrottleSettings,
+    security_headers: SecurityHeadersSettings,
+    subscription_protection: SubscriptionProtectionSettings,
+    domain_policy: EmailDomainPolicy,
 ) -> Result<Server, anyhow::Error>
 where
     E: 'static + AsyncTransport + Send + Sync,
@@ -165,36 +216,205 @@ where
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    let login_throttle = Data::new(LoginThrottle::new(&redis_uri, login_throttle).await?);
+    let security_headers = Data::new(SecurityHeaders::try_from(&security_headers)?);
+    let spam_filter =
+        Data::new(SpamFilter::new(&redis_uri, subscription_protection, hmac_secret.clone()).await?);
+    let metrics_registry = Data::new(Metrics::default());
+    let domain_policy = Data::new(domain_policy);
     let server = HttpServer::new(move || {
         App::new()
             .wrap(message_framework.clone())
-            .wrap(SessionMiddleware::new(
+            .wrap(session_middleware(
                 redis_store.clone(),
                 secret_key.clone(),
+                &session_settings,
             ))
+            .wrap(from_fn(persist_remembered_sessions))
+            .wrap(from_fn(add_security_headers))
             .wrap(TracingLogger::default())
             .route("/health_check", web::get().to(health_check))
+            .route("/metrics", web::get().to(metrics))
+            .route("/openapi.json", web::get().to(openapi_spec))
             .route("/subscriptions", web::post().to(subscribe::<E>))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route(
+                "/subscriptions/preferences",
+                web::get().to(preferences_form),
+            )
+            .route(
+                "/subscriptions/preferences",
+                web::post().to(update_preferences::<E>),
+            )
+            .route(
+                "/subscriptions/preferences/email",
+                web::post().to(change_email::<E>),
+            )
+            .route(
+                "/subscriptions/preferences/email/confirm",
+                web::get().to(confirm_email_change),
+            )
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route(
+                "/subscriptions/data-requests",
+                web::post().to(request_subscriber_data::<E>),
+            )
+            .route(
+                "/subscriptions/data-requests/confirm",
+                web::get().to(data_request_form),
+            )
+            .route(
+                "/subscriptions/data-requests/confirm",
+                web::post().to(confirm_data_request),
+            )
             .route("/", web::get().to(home))
-            .route("/login", web::get().to(login_form))
-            .route("/login", web::post().to(login))
+            .route("/archive", web::get().to(archive))
+            .route("/archive/{slug}", web::get().to(archived_issue))
+            .route("/invitations/accept", web::get().to(accept_invitation_form))
+            .route("/invitations/accept", web::post().to(accept_invitation))
+            .route(
+                "/password-reset",
+                web::get().to(request_password_reset_form),
+            )
+            .route(
+                "/password-reset",
+                web::post().to(request_password_reset::<E>),
+            )
+            .route(
+                "/password-reset/confirm",
+                web::get().to(reset_password_form),
+            )
+            .route("/password-reset/confirm", web::post().to(reset_password))
+            .service(
+                web::scope("/login")
+                    .wrap(from_fn(reject_forged_forms))
+                    .route("", web::get().to(login_form))
+                    .route("", web::post().to(login))
+                    .route("/two-factor", web::get().to(two_factor_form))
+                    .route("/two-factor", web::post().to(verify_two_factor)),
+            )
+            .service(
+                api_v1_routes::<E>().into_iter().fold(
+                    web::scope("/api/v1")
+                        .wrap(from_fn(reject_invalid_api_tokens))
+                        .app_data(web::JsonConfig::default().error_handler(json_error_handler))
+                        .app_data(web::QueryConfig::default().error_handler(query_error_handler))
+                        .app_data(web::PathConfig::default().error_handler(path_error_handler)),
+                    |scope, (method, path, route)| scope.route(path, route.method(method)),
+                ),
+            )
             .service(
                 web::scope("/admin")
+                    .wrap(from_fn(reject_forged_forms))
                     .wrap(from_fn(reject_anonymous_users))
-                    .route("/newsletters", web::post().to(publish_newsletter::<E>))
-                    .route("/newsletters", web::get().to(publish_newsletter_form))
+                    .service(
+                        web::resource("/newsletters")
+                            .wrap(from_fn(require_editor))
+                            .route(web::post().to(publish_newsletter::<E>))
+                            .route(web::get().to(publish_newsletter_form)),
+                    )
                     .route("/dashboard", web::get().to(admin_dashboard))
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
-                    .route("/logout", web::post().to(log_out)),
+                    .route("/logout", web::post().to(log_out))
+                    .route("/sessions", web::get().to(sessions_form))
+                    .route(
+                        "/sessions/revoke-others",
+                        web::post().to(revoke_other_sessions),
+                    )
+                    .route(
+                        "/sessions/{session_id}/revoke",
+                        web::post().to(revoke_session),
+                    )
+                    .route("/api-tokens", web::get().to(api_tokens_form))
+                    .route("/api-tokens", web::post().to(create_api_token))
+                    .route(
+                        "/api-tokens/{token_id}/revoke",
+                        web::post().to(revoke_api_token),
+                    )
+                    .route("/two-factor", web::get().to(two_factor_settings))
+                    .route("/two-factor/enable", web::post().to(enable_two_factor))
+                    .route("/two-factor/disable", web::post().to(disable_two_factor))
+                    .route("/lists", web::get().to(manage_lists_form))
+                    .service(
+                        web::resource("/lists")
+                            .guard(guard::Post())
+                            .wrap(from_fn(require_editor))
+                            .to(create_list),
+                    )
+                    .service(
+                        web::scope("/export")
+                            .wrap(from_fn(require_editor))
+                            .route("/subscriptions", web::get().to(export_subscriptions))
+                            .route(
+                                "/issues/{issue_id}/deliveries",
+                                web::get().to(export_issue_deliveries),
+                            ),
+                    )
+                    .service(
+                        web::scope("/subscribers")
+                            .wrap(from_fn(require_owner))
+                            .route("", web::get().to(subscriber_data_form))
+                            .route("/export", web::post().to(export_subscriber))
+                            .route("/erase", web::post().to(erase_subscriber)),
+                    )
+                    .service(
+                        web::scope("/audit-log")
+                            .wrap(from_fn(require_owner))
+                            .route("", web::get().to(audit_log)),
+                    )
+                    .service(
+                        web::scope("/email-domains")
+                            .wrap(from_fn(require_owner))
+                            .route("", web::get().to(email_domains_form))
+                            .route("", web::post().to(save_email_domain_rule))
+                            .route("/remove", web::post().to(remove_email_domain_rule)),
+                    )
+                    .service(
+                        web::scope("/lockouts")
+                            .wrap(from_fn(require_owner))
+                            .route("", web::get().to(lockouts_form))
+                            .route("/unlock", web::post().to(unlock)),
+                    )
+                    .service(
+                        web::scope("/users")
+                            .wrap(from_fn(require_owner))
+                            .route("", web::get().to(manage_users_form))
+                            .route("", web::post().to(invite_user::<E>))
+                            .route("/{user_id}/deactivate", web::post().to(deactivate_user)),
+                    ),
             )
             .app_data(connection.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(login_throttle.clone())
+            .app_data(Data::new(session_settings.clone()))
+            .app_data(security_headers.clone())
+            .app_data(spam_filter.clone())
+            .app_data(metrics_registry.clone())
+            .app_data(domain_policy.clone())
             .app_data(Data::new(HmacSecret(hmac_secret.clone())))
     })
     .listen(listener)?
     .run();
     Ok(server)
 }
+
+/// Session state outlives neither the idle timeout nor the browser: every
+/// request pushes the expiry back, and remembered sessions are made to
+/// persist by `persist_remembered_sessions`.
+fn session_middleware(
+    store: RedisSessionStore,
+    key: Key,
+    settings: &SessionSettings,
+) -> SessionMiddleware<RedisSessionStore> {
+    let lifecycle = BrowserSession::default()
+        .state_ttl(time::Duration::seconds(settings.idle_timeout_seconds))
+        .state_ttl_extension_policy(TtlExtensionPolicy::OnEveryRequest);
+    SessionMiddleware::builder(store, key)
+        .cookie_name(settings.cookie_name.clone())
+        .cookie_secure(settings.cookie_secure)
+        .cookie_same_site(settings.cookie_same_site.into())
+        .session_lifecycle(lifecycle)
+        .build()
+}
@@ -0,0 +1,273 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::audit::{record_audit_event, AuditAction, AuditContext};
+use crate::domain::SubscriberEmail;
+
+#[derive(serde::Serialize)]
+pub struct SubscriberDataExport {
+    subscriber: SubscriberRecord,
+    lists: Vec<ListRecord>,
+    attributes: Vec<AttributeRecord>,
+    subscription_tokens: Vec<String>,
+    preference_token: Option<String>,
+    archive_token: Option<String>,
+    email_change_requests: Vec<EmailChangeRecord>,
+    data_requests: Vec<DataRequestRecord>,
+    deliveries: Vec<DeliveryRecord>,
+    pending_deliveries: Vec<Uuid>,
+}
+
+#[derive(serde::Serialize)]
+struct SubscriberRecord {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    locale: String,
+}
+
+#[derive(serde::Serialize)]
+struct ListRecord {
+    slug: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct AttributeRecord {
+    key: String,
+    value: String,
+}
+
+#[derive(serde::Serialize)]
+struct EmailChangeRecord {
+    new_email: String,
+    requested_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct DataRequestRecord {
+    kind: String,
+    requested_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct DeliveryRecord {
+    newsletter_issue_id: Uuid,
+    title: String,
+    outcome: String,
+    recorded_at: DateTime<Utc>,
+}
+
+impl SubscriberDataExport {
+    pub fn subscriber_id(&self) -> Uuid {
+        self.subscriber.id
+    }
+}
+
+/// `email` as we would have stored it, so that admins can type it as the
+/// subscriber did.
+fn normalize(email: &str) -> String {
+    SubscriberEmail::parse(email.to_string())
+        .map(|e| e.to_string())
+        .unwrap_or_else(|_| email.to_string())
+}
+
+/// Collects everything stored about the subscriber using `email`.
+/// Idempotency records are left out: they belong to the admin users who
+/// published issues, and only hold the responses to those publications.
+#[tracing::instrument(name = "Export subscriber data", skip_all)]
+pub async fn export_subscriber_data(
+    pool: &PgPool,
+    email: &str,
+) -> Result<Option<SubscriberDataExport>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        SubscriberRecord,
+        r#"SELECT id, email, name, status, subscribed_at, locale FROM subscriptions WHERE lower(email) = lower($1)"#,
+        normalize(email)
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscriber.")?;
+    let subscriber = match subscriber {
+        Some(subscriber) => subscriber,
+        None => return Ok(None),
+    };
+
+    let lists = sqlx::query_as!(
+        ListRecord,
+        r#"
+        SELECT l.slug, l.name, s.status, s.subscribed_at
+        FROM list_subscriptions s
+        JOIN lists l ON l.list_id = s.list_id
+        WHERE s.subscriber_id = $1
+        "#,
+        subscriber.id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the subscriber's lists.")?;
+    let attributes = sqlx::query_as!(
+        AttributeRecord,
+        r#"SELECT key, value FROM subscriber_attributes WHERE subscriber_id = $1"#,
+        subscriber.id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the subscriber's attributes.")?;
+    let subscription_tokens = sqlx::query!(
+        r#"SELECT subscription_token FROM subscription_tokens WHERE subscription_id = $1"#,
+        subscriber.id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the subscriber's subscription tokens.")?
+    .into_iter()
+    .map(|r| r.subscription_token)
+    .collect();
+    let preference_token = sqlx::query!(
+        r#"SELECT preference_token FROM preference_tokens WHERE subscriber_id = $1"#,
+        subscriber.id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscriber's preference token.")?
+    .map(|r| r.preference_token);
+    let archive_token = sqlx::query!(
+        r#"SELECT archive_token FROM archive_tokens WHERE subscriber_id = $1"#,
+        subscriber.id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscriber's archive token.")?
+    .map(|r| r.archive_token);
+    let email_change_requests = sqlx::query_as!(
+        EmailChangeRecord,
+        r#"SELECT new_email, requested_at FROM email_change_requests WHERE subscriber_id = $1"#,
+        subscriber.id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the subscriber's email change requests.")?;
+    let data_requests = sqlx::query_as!(
+        DataRequestRecord,
+        r#"SELECT kind, requested_at FROM data_requests WHERE subscriber_id = $1"#,
+        subscriber.id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the subscriber's data requests.")?;
+    let deliveries = sqlx::query_as!(
+        DeliveryRecord,
+        r#"
+        SELECT h.newsletter_issue_id, i.title, h.outcome, h.recorded_at
+        FROM issue_delivery_history h
+        JOIN newsletter_issues i ON i.newsletter_issue_id = h.newsletter_issue_id
+        WHERE lower(h.subscriber_email) = lower($1)
+        ORDER BY h.recorded_at
+        "#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the subscriber's delivery history.")?;
+    let pending_deliveries = sqlx::query!(
+        r#"SELECT newsletter_issue_id FROM issue_delivery_queue WHERE lower(subscriber_email) = lower($1)"#,
+        email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the subscriber's pending deliveries.")?
+    .into_iter()
+    .map(|r| r.newsletter_issue_id)
+    .collect();
+
+    Ok(Some(SubscriberDataExport {
+        subscriber,
+        lists,
+        attributes,
+        subscription_tokens,
+        preference_token,
+        archive_token,
+        email_change_requests,
+        data_requests,
+        deliveries,
+        pending_deliveries,
+    }))
+}
+
+/// Deletes the subscriber using `email` and everything linked to them,
+/// and records the erasure in the audit log in the same transaction.
+/// Delivery history is kept under a random pseudonym so that per-issue
+/// statistics do not change.
+/// Returns the id the subscriber had, or `None` if there was no such subscriber.
+#[tracing::instrument(name = "Erase subscriber data", skip(pool, email, audit))]
+pub async fn erase_subscriber_data(
+    pool: &PgPool,
+    email: &str,
+    audit: &AuditContext,
+    actor_id: Option<Uuid>,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a postgres connection from the pool")?;
+    let subscriber = sqlx::query!(
+        r#"SELECT id, email FROM subscriptions WHERE lower(email) = lower($1)"#,
+        normalize(email)
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve the subscriber.")?;
+    let (subscriber_id, email) = match subscriber {
+        Some(r) => (r.id, r.email),
+        None => return Ok(None),
+    };
+
+    erase_delivery_records(&mut transaction, &email).await?;
+    // Tokens, list memberships, attributes and pending requests cascade.
+    sqlx::query!(r#"DELETE FROM subscriptions WHERE id = $1"#, subscriber_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the subscriber.")?;
+    record_audit_event(
+        &mut transaction,
+        audit,
+        actor_id,
+        AuditAction::SubscriberErased,
+        Some(&format!("subscriber:{}", subscriber_id)),
+    )
+    .await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a subscriber.")?;
+    Ok(Some(subscriber_id))
+}
+
+async fn erase_delivery_records(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"DELETE FROM issue_delivery_queue WHERE lower(subscriber_email) = lower($1)"#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete pending deliveries.")?;
+    sqlx::query!(
+        r#"UPDATE issue_delivery_history SET subscriber_email = $2 WHERE lower(subscriber_email) = lower($1)"#,
+        email,
+        format!("erased:{}", Uuid::new_v4())
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to anonymize the delivery history.")?;
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use actix_web::{http::header, HttpResponse};
+use actix_web::{http::header, HttpRequest, HttpResponse};
 
 pub fn e400<T>(e: T) -> actix_web::Error
 where
@@ -19,3 +19,28 @@ pub fn see_other(location: &str) -> HttpResponse {
         .insert_header((header::LOCATION, location))
         .finish()
 }
+
+/// The peer address: forwarding headers are not trusted, since anyone
+/// could set them to dodge per-IP limits or forge audit log entries.
+pub fn client_ip(request: &HttpRequest) -> String {
+    request
+        .peer_addr()
+        .map(|a| a.ip().to_string())
+        .unwrap_or_default()
+}
+
+/// For text that may have been chosen by someone else, such as a user agent.
+pub fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,226 @@
+use std::time::Duration;
+
+use crate::helpers::{spawn_app, TestApp, TestAppConfiguration};
+use zero2prod::configuration::ChallengeSettings;
+
+fn extract_form_stamp(html: &str) -> String {
+    let start = html
+        .find(r#"name="form_stamp" value=""#)
+        .expect("The page has no form stamp")
+        + r#"name="form_stamp" value=""#.len();
+    let end = start + html[start..].find('"').unwrap();
+    html[start..end].to_string()
+}
+
+fn form(email: &str, extra: &[(&str, &str)]) -> String {
+    let mut fields = vec![("name", "le guin"), ("email", email)];
+    fields.extend_from_slice(extra);
+    serde_urlencoded::to_string(fields).unwrap()
+}
+
+async fn subscriber_count(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+async fn sent_emails(app: &TestApp) -> usize {
+    app.email_client.get_transport_ref().messages().await.len()
+}
+
+fn assert_dropped(metrics: &str, reason: &str, count: u64) {
+    let line = format!(
+        r#"subscriptions_dropped_total{{reason="{}"}} {}"#,
+        reason, count
+    );
+    assert!(
+        metrics.contains(&line),
+        "{} not found in:\n{}",
+        line,
+        metrics
+    );
+}
+
+#[tokio::test]
+async fn a_filled_in_honeypot_is_dropped_quietly() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    let response = app
+        .post_subscription(form(
+            "ursula_le_guin@gmail.com",
+            &[("website", "https://cheap-pills.example")],
+        ))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_count(&app).await, 0);
+    assert_eq!(sent_emails(&app).await, 0);
+    assert_dropped(&app.get_metrics().await, "honeypot", 1);
+}
+
+#[tokio::test]
+async fn the_home_page_form_carries_a_honeypot_and_a_stamp() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    let html = app.get_home_html().await;
+
+    assert!(html.contains(r#"name="website""#));
+    assert!(!extract_form_stamp(&html).is_empty());
+}
+
+#[tokio::test]
+async fn forms_sent_back_too_fast_are_dropped() {
+    let mut configuration = TestAppConfiguration::new();
+    configuration
+        .configuration
+        .subscription_protection
+        .min_seconds_to_submit = 60;
+    let app = spawn_app(configuration).await;
+    let stamp = extract_form_stamp(&app.get_home_html().await);
+
+    let response = app
+        .post_subscription(form("ursula_le_guin@gmail.com", &[("form_stamp", &stamp)]))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_count(&app).await, 0);
+    assert_dropped(&app.get_metrics().await, "too_fast", 1);
+}
+
+#[tokio::test]
+async fn forms_without_a_genuine_stamp_are_dropped() {
+    let mut configuration = TestAppConfiguration::new();
+    configuration
+        .configuration
+        .subscription_protection
+        .min_seconds_to_submit = 1;
+    let app = spawn_app(configuration).await;
+
+    app.post_subscription(form("ursula_le_guin@gmail.com", &[]))
+        .await;
+    app.post_subscription(form(
+        "ursula_le_guin@gmail.com",
+        &[("form_stamp", "1690000000.0123456789abcdef")],
+    ))
+    .await;
+
+    assert_eq!(subscriber_count(&app).await, 0);
+    assert_dropped(&app.get_metrics().await, "invalid_form_stamp", 2);
+}
+
+#[tokio::test]
+async fn a_form_filled_in_at_a_human_pace_subscribes() {
+    let mut configuration = TestAppConfiguration::new();
+    configuration
+        .configuration
+        .subscription_protection
+        .min_seconds_to_submit = 1;
+    let app = spawn_app(configuration).await;
+    let stamp = extract_form_stamp(&app.get_home_html().await);
+    tokio::time::sleep(Duration::from_millis(2100)).await;
+
+    let response = app
+        .post_subscription(form(
+            "ursula_le_guin@gmail.com",
+            &[("form_stamp", &stamp), ("website", "")],
+        ))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_count(&app).await, 1);
+    assert_eq!(sent_emails(&app).await, 1);
+}
+
+#[tokio::test]
+async fn subscriptions_beyond_the_limit_per_ip_are_dropped() {
+    let mut configuration = TestAppConfiguration::new();
+    configuration
+        .configuration
+        .subscription_protection
+        .max_per_ip = 2;
+    let app = spawn_app(configuration).await;
+
+    for email in ["ursula@gmail.com", "octavia@example.com", "nk@example.org"] {
+        let response = app.post_subscription(form(email, &[])).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    assert_eq!(subscriber_count(&app).await, 2);
+    assert_eq!(sent_emails(&app).await, 2);
+    assert_dropped(&app.get_metrics().await, "ip_rate_limit", 1);
+}
+
+#[tokio::test]
+async fn subscriptions_beyond_the_limit_per_domain_are_dropped() {
+    let mut configuration = TestAppConfiguration::new();
+    configuration
+        .configuration
+        .subscription_protection
+        .max_per_domain = 2;
+    let app = spawn_app(configuration).await;
+
+    for email in [
+        "one@spam.example",
+        "two@SPAM.example",
+        "three@spam.example",
+        "ursula@gmail.com",
+    ] {
+        app.post_subscription(form(email, &[])).await;
+    }
+
+    assert_eq!(subscriber_count(&app).await, 3);
+    assert_dropped(&app.get_metrics().await, "domain_rate_limit", 1);
+}
+
+#[tokio::test]
+async fn the_challenge_must_be_answered_when_configured() {
+    let mut configuration = TestAppConfiguration::new();
+    configuration
+        .configuration
+        .subscription_protection
+        .challenge = ChallengeSettings::Stub {
+        answer: "42".to_string(),
+    };
+    let app = spawn_app(configuration).await;
+
+    app.post_subscription(form("missing@example.com", &[]))
+        .await;
+    app.post_subscription(form("wrong@example.com", &[("challenge_response", "41")]))
+        .await;
+    let response = app
+        .post_subscription(form("right@example.com", &[("challenge_response", "42")]))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "right@example.com");
+    assert_dropped(&app.get_metrics().await, "challenge_failed", 2);
+}
+
+#[tokio::test]
+async fn anti_spam_fields_are_not_stored_as_attributes() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    app.post_subscription(form(
+        "ursula_le_guin@gmail.com",
+        &[
+            ("website", ""),
+            ("form_stamp", "ignored"),
+            ("attr_source", "home"),
+        ],
+    ))
+    .await;
+
+    let keys = sqlx::query!("SELECT key FROM subscriber_attributes")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0].key, "source");
+}
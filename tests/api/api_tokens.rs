@@ -0,0 +1,179 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestAppConfiguration, TestUser};
+
+#[tokio::test]
+async fn a_new_token_authenticates_its_owner() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+
+    let token = app
+        .create_api_token(&["newsletters:read", "newsletters:write"])
+        .await;
+    let response = app.get_api_me(&token).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let me: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(me["username"], app.test_user.username.as_str());
+    assert_eq!(me["role"], "owner");
+    assert_eq!(
+        me["scopes"],
+        serde_json::json!(["newsletters:read", "newsletters:write"])
+    );
+}
+
+#[tokio::test]
+async fn tokens_are_only_stored_hashed() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+
+    let token = app.create_api_token(&["newsletters:read"]).await;
+
+    let saved = sqlx::query!("SELECT token_hash FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(saved.token_hash, token);
+    assert!(!app.get_api_tokens_html().await.contains(&token));
+}
+
+#[tokio::test]
+async fn requests_without_a_valid_token_are_rejected() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/api/v1/me", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response.headers()["WWW-Authenticate"],
+        r#"Bearer realm="api""#
+    );
+
+    let response = app.get_api_me("z2p_not-a-real-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn revoked_tokens_are_rejected() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token(&["newsletters:read"]).await;
+    let token_id = sqlx::query!("SELECT token_id FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .token_id;
+
+    let response = app
+        .api_client
+        .post(format!(
+            "{}/admin/api-tokens/{}/revoke",
+            &app.address, token_id
+        ))
+        .form(&app.with_csrf_token(&serde_json::json!({})).await)
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/api-tokens");
+    assert!(app
+        .get_api_tokens_html()
+        .await
+        .contains("<p><i>The token ci has been revoked.</i></p>"));
+
+    let response = app.get_api_me(&token).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn users_cannot_grant_scopes_their_role_does_not_allow() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let viewer = TestUser::generate_with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    viewer.login(&app).await;
+
+    let response = app
+        .post_create_api_token("name=ci&scope=newsletters:write")
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/api-tokens");
+    assert!(app
+        .get_api_tokens_html()
+        .await
+        .contains("<p><i>A viewer cannot create tokens with the newsletters:write scope.</i></p>"));
+    let tokens = sqlx::query!("SELECT token_id FROM api_tokens")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(tokens.is_empty());
+}
+
+#[tokio::test]
+async fn tokens_lose_scopes_when_their_owner_is_demoted() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    let token = app
+        .create_api_token(&["newsletters:read", "subscribers:write"])
+        .await;
+
+    sqlx::query!(
+        "UPDATE users SET role = 'viewer' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let me: serde_json::Value = app.get_api_me(&token).await.json().await.unwrap();
+
+    assert_eq!(me["role"], "viewer");
+    assert_eq!(me["scopes"], serde_json::json!(["newsletters:read"]));
+}
+
+#[tokio::test]
+async fn tokens_of_deactivated_users_are_rejected() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token(&["newsletters:read"]).await;
+
+    sqlx::query!(
+        "UPDATE users SET deactivated_at = now() WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let response = app.get_api_me(&token).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn token_names_are_escaped() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    app.post_create_api_token("name=%22%3E%3Cb%3Ex&scope=newsletters:read")
+        .await;
+    let token_id = sqlx::query!("SELECT token_id FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .token_id;
+
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains("<td>&quot;&gt;&lt;b&gt;x</td>"));
+    assert!(!html_page.contains("<b>x"));
+
+    app.api_client
+        .post(format!(
+            "{}/admin/api-tokens/{}/revoke",
+            &app.address, token_id
+        ))
+        .form(&app.with_csrf_token(&serde_json::json!({})).await)
+        .send()
+        .await
+        .unwrap();
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains("The token &quot;&gt;&lt;b&gt;x has been revoked."));
+    assert!(!html_page.contains("<b>x"));
+}
@@ -0,0 +1,249 @@
+use reqwest::Method;
+use uuid::Uuid;
+
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp, TestAppConfiguration};
+
+async fn owner_token(app: &TestApp, scopes: &[&str]) -> String {
+    app.test_user.login(app).await;
+    app.create_api_token(scopes).await
+}
+
+fn new_issue() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+#[tokio::test]
+async fn issues_can_be_published_and_tracked() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    let token = owner_token(&app, &["newsletters:read", "newsletters:write"]).await;
+
+    let response = app
+        .api_request(Method::POST, "/newsletters", &token)
+        .json(&new_issue())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 202);
+    let created: serde_json::Value = response.json().await.unwrap();
+    let issue_id = created["id"].as_str().unwrap().to_string();
+
+    let issue: serde_json::Value = app
+        .api_request(Method::GET, &format!("/newsletters/{}", issue_id), &token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(issue["title"], "Newsletter title");
+    assert_eq!(issue["delivery"]["pending"], 1);
+
+    app.dispatch_all_pending_emails().await;
+    let issue: serde_json::Value = app
+        .api_request(Method::GET, &format!("/newsletters/{}", issue_id), &token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(issue["delivery"]["pending"], 0);
+    assert_eq!(issue["delivery"]["delivered"], 1);
+
+    let page: serde_json::Value = app
+        .api_request(Method::GET, "/newsletters", &token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["items"][0]["id"], issue_id.as_str());
+}
+
+#[tokio::test]
+async fn publishing_is_idempotent_with_an_idempotency_key() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    let token = owner_token(&app, &["newsletters:write"]).await;
+    let key = Uuid::new_v4().to_string();
+
+    let mut bodies = Vec::new();
+    for _ in 0..2 {
+        let response = app
+            .api_request(Method::POST, "/newsletters", &token)
+            .header("Idempotency-Key", &key)
+            .json(&new_issue())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 202);
+        bodies.push(response.text().await.unwrap());
+    }
+
+    assert_eq!(bodies[0], bodies[1]);
+    let issues = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.len(), 1);
+    let queued = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.len(), 1);
+}
+
+#[tokio::test]
+async fn issues_for_no_list_or_unknown_lists_are_rejected() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let token = owner_token(&app, &["newsletters:write"]).await;
+
+    for list_ids in [serde_json::json!([]), serde_json::json!([Uuid::new_v4()])] {
+        let mut issue = new_issue();
+        issue["list_ids"] = list_ids;
+        let response = app
+            .api_request(Method::POST, "/newsletters", &token)
+            .json(&issue)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 400);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["code"], "validation_error");
+    }
+}
+
+#[tokio::test]
+async fn tokens_without_the_scope_are_forbidden() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let token = owner_token(&app, &["newsletters:read"]).await;
+
+    let response = app
+        .api_request(Method::POST, "/newsletters", &token)
+        .json(&new_issue())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 403);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({
+            "error": {
+                "code": "forbidden",
+                "message": "This token does not have the newsletters:write scope."
+            }
+        })
+    );
+}
+
+#[tokio::test]
+async fn errors_are_reported_as_json() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let token = owner_token(&app, &["newsletters:read", "subscribers:write"]).await;
+
+    let test_cases = vec![
+        (
+            app.api_request(Method::POST, "/subscribers", &token)
+                .json(&serde_json::json!({ "name": "le guin" })),
+            400,
+            "validation_error",
+        ),
+        (
+            app.api_request(Method::POST, "/subscribers", &token)
+                .json(&serde_json::json!({ "name": "le guin", "email": "not-an-email" })),
+            400,
+            "validation_error",
+        ),
+        (
+            app.api_request(Method::GET, "/newsletters?per_page=0", &token),
+            400,
+            "validation_error",
+        ),
+        (
+            app.api_request(
+                Method::GET,
+                &format!("/newsletters/{}", Uuid::new_v4()),
+                &token,
+            ),
+            404,
+            "not_found",
+        ),
+        (
+            app.api_request(Method::GET, "/newsletters", "z2p_not-a-real-token"),
+            401,
+            "unauthorized",
+        ),
+    ];
+
+    for (request, status, code) in test_cases {
+        let response = request.send().await.unwrap();
+        assert_eq!(response.status().as_u16(), status);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["code"], code);
+    }
+}
+
+#[tokio::test]
+async fn subscribers_can_be_managed() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let token = owner_token(&app, &["subscribers:read", "subscribers:write"]).await;
+
+    let response = app
+        .api_request(Method::POST, "/subscribers", &token)
+        .json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "attributes": { "country": "us" }
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 202);
+    let subscriber: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(subscriber["status"], "pending_confirmation");
+    assert_eq!(subscriber["attributes"]["country"], "us");
+    assert_eq!(
+        app.email_client.get_transport_ref().messages().await.len(),
+        1
+    );
+    let subscriber_id = subscriber["id"].as_str().unwrap().to_string();
+
+    let page: serde_json::Value = app
+        .api_request(
+            Method::GET,
+            "/subscribers?status=pending_confirmation&per_page=1",
+            &token,
+        )
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["per_page"], 1);
+    assert_eq!(page["items"][0]["email"], "ursula_le_guin@gmail.com");
+
+    let path = format!("/subscribers/{}", subscriber_id);
+    let response = app
+        .api_request(Method::DELETE, &path, &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 204);
+    let response = app
+        .api_request(Method::GET, &path, &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
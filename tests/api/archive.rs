@@ -0,0 +1,276 @@
+use mail_parser::Message;
+use reqwest::Method;
+
+use crate::helpers::{
+    create_confirmed_subscriber, get_preference_token, spawn_app, TestApp, TestAppConfiguration,
+};
+
+async fn publish(app: &TestApp, title: &str, visibility: &str) {
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": title,
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "visibility": visibility,
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+}
+
+/// Sends out the pending issues, which hands the subscriber an archive token.
+async fn get_archive_token(app: &TestApp) -> String {
+    app.dispatch_all_pending_emails().await;
+    sqlx::query!("SELECT archive_token FROM archive_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .archive_token
+}
+
+#[tokio::test]
+async fn public_issues_are_listed_and_served_at_their_slug() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    publish(&app, "Hello, World!", "public").await;
+
+    let archive = app.get_archive("", None).await.text().await.unwrap();
+    assert!(archive.contains(r#"<a href="/archive/hello-world" dir="auto">Hello, World!</a>"#));
+
+    let response = app.get_archive("/hello-world", None).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    assert!(page.contains("<title>Hello, World!</title>"));
+    assert!(page.contains("<p>Newsletter body as HTML</p>"));
+}
+
+#[tokio::test]
+async fn the_archive_says_when_there_is_nothing_in_it() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    let archive = app.get_archive("", None).await.text().await.unwrap();
+
+    assert!(archive.contains("No issues have been published yet."));
+}
+
+#[tokio::test]
+async fn subscribers_only_issues_need_a_confirmed_subscriber_token() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish(&app, "For subscribers", "subscribers").await;
+    let token = get_archive_token(&app).await;
+
+    let archive = app.get_archive("", None).await.text().await.unwrap();
+    assert!(!archive.contains("For subscribers"));
+    let response = app.get_archive("/for-subscribers", None).await;
+    assert_eq!(response.status().as_u16(), 404);
+    let response = app
+        .get_archive("/for-subscribers", Some("not-a-token"))
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    let archive = app
+        .get_archive("", Some(&token))
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(archive.contains(&format!(
+        r#"<a href="/archive/for-subscribers?archive_token={}""#,
+        token
+    )));
+    let response = app.get_archive("/for-subscribers", Some(&token)).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn unsubscribed_readers_lose_access_to_subscribers_only_issues() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish(&app, "For subscribers", "subscribers").await;
+    let token = get_archive_token(&app).await;
+
+    app.post_unsubscribe(
+        &serde_json::json!({ "preference_token": get_preference_token(&app).await }),
+    )
+    .await;
+
+    let response = app.get_archive("/for-subscribers", Some(&token)).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn preference_tokens_do_not_open_subscribers_only_issues() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    publish(&app, "For subscribers", "subscribers").await;
+    let preference_token = get_preference_token(&app).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/archive/for-subscribers", &app.address))
+        .query(&[("preference_token", &preference_token)])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+    let response = app
+        .get_archive("/for-subscribers", Some(&preference_token))
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn unknown_tokens_are_not_echoed_back() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    publish(&app, "Hello", "public").await;
+
+    let archive = app
+        .get_archive("", Some(r#""><script>alert(1)</script>"#))
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    assert!(!archive.contains("<script>"));
+    assert!(archive.contains(r#"<a href="/archive/hello" dir="auto">"#));
+}
+
+#[tokio::test]
+async fn issues_with_the_same_title_get_distinct_slugs() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+
+    for _ in 0..3 {
+        publish(&app, "Weekly digest", "public").await;
+    }
+
+    let mut slugs: Vec<String> = sqlx::query!("SELECT slug FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.slug)
+        .collect();
+    slugs.sort();
+    assert_eq!(
+        slugs,
+        ["weekly-digest", "weekly-digest-2", "weekly-digest-3"]
+    );
+}
+
+#[tokio::test]
+async fn issues_with_non_latin_titles_are_served_at_their_slug() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    publish(&app, "شماره ۱", "public").await;
+
+    let archive = app.get_archive("", None).await.text().await.unwrap();
+    assert!(archive.contains(r#"<a href="/archive/%D8%B4%D9%85%D8%A7%D8%B1%D9%87-%DB%B1""#));
+
+    let response = app.get_archive("/شماره-۱", None).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn sent_issues_link_to_their_archive_page() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let preference_token = get_preference_token(&app).await;
+
+    publish(&app, "Public issue", "public").await;
+    publish(&app, "Private issue", "subscribers").await;
+    let token = get_archive_token(&app).await;
+
+    let messages = app.email_client.get_transport_ref().messages().await;
+    let bodies: Vec<(String, String)> = messages
+        .iter()
+        .rev()
+        .take(2)
+        .map(|(_, raw)| {
+            let raw = raw.clone().into_bytes();
+            let email = Message::parse(&raw).unwrap();
+            (
+                email.body_text(0).unwrap().to_string(),
+                email.body_html(0).unwrap().to_string(),
+            )
+        })
+        .collect();
+    let public_link = format!("{}/archive/public-issue", app.base_url);
+    let private_link = format!(
+        "{}/archive/private-issue?archive_token={}",
+        app.base_url, token
+    );
+    for link in [public_link, private_link] {
+        let (text, html) = bodies
+            .iter()
+            .find(|(text, _)| text.contains(&link))
+            .unwrap_or_else(|| panic!("No email links to {}", link));
+        assert!(text.starts_with(&format!("View this issue in your browser: {}\n", link)));
+        assert!(html.contains(&format!(r#"<a href="{}">"#, link)));
+    }
+    for (text, _) in &bodies {
+        let view_link = text.lines().next().unwrap();
+        assert!(!view_link.contains(&preference_token));
+    }
+}
+
+#[tokio::test]
+async fn the_api_publishes_issues_with_a_visibility() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    let token = app
+        .create_api_token(&["newsletters:read", "newsletters:write"])
+        .await;
+    let new_issue = |visibility: &str| {
+        serde_json::json!({
+            "title": "From the API",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "visibility": visibility,
+        })
+    };
+
+    let response = app
+        .api_request(Method::POST, "/newsletters", &token)
+        .json(&new_issue("secret"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .api_request(Method::POST, "/newsletters", &token)
+        .json(&new_issue("public"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 202);
+    let created: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(created["slug"], "from-the-api");
+    let issue: serde_json::Value = app
+        .api_request(
+            Method::GET,
+            &format!("/newsletters/{}", created["id"].as_str().unwrap()),
+            &token,
+        )
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(issue["visibility"], "public");
+    assert_eq!(
+        app.get_archive("/from-the-api", None)
+            .await
+            .status()
+            .as_u16(),
+        200
+    );
+}
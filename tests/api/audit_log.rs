@@ -0,0 +1,170 @@
+use uuid::Uuid;
+
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestAppConfiguration, TestUser};
+
+struct Entry {
+    actor_id: Option<Uuid>,
+    action: String,
+    target: Option<String>,
+}
+
+async fn audit_entries(pool: &sqlx::PgPool) -> Vec<Entry> {
+    sqlx::query_as!(
+        Entry,
+        "SELECT actor_id, action, target FROM audit_log ORDER BY audit_id"
+    )
+    .fetch_all(pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn logins_and_logouts_are_recorded() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password"
+    }))
+    .await;
+    app.test_user.login(&app).await;
+    app.post_logout().await;
+
+    let entries = audit_entries(&app.db_pool).await;
+    let actions: Vec<_> = entries.iter().map(|e| e.action.as_str()).collect();
+    assert_eq!(actions, vec!["login.failed", "login", "logout"]);
+    assert_eq!(entries[0].actor_id, None);
+    assert_eq!(
+        entries[0].target,
+        Some(format!("username:{}", app.test_user.username))
+    );
+    assert_eq!(entries[1].actor_id, Some(app.test_user.user_id));
+    assert_eq!(entries[2].actor_id, Some(app.test_user.user_id));
+}
+
+#[tokio::test]
+async fn publishing_a_newsletter_is_recorded() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    let entry = audit_entries(&app.db_pool).await.pop().unwrap();
+    assert_eq!(entry.action, "newsletter.published");
+    assert_eq!(entry.actor_id, Some(app.test_user.user_id));
+    assert_eq!(entry.target, Some(format!("issue:{}", issue_id)));
+}
+
+#[tokio::test]
+async fn password_changes_are_recorded() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    app.post_change_password(&serde_json::json!({
+        "current_password": &app.test_user.password,
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+    }))
+    .await;
+
+    let entry = audit_entries(&app.db_pool).await.pop().unwrap();
+    assert_eq!(entry.action, "password.changed");
+    assert_eq!(entry.actor_id, Some(app.test_user.user_id));
+}
+
+#[tokio::test]
+async fn erasures_are_recorded_without_the_email_address() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber = sqlx::query!("SELECT id, email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    app.test_user.login(&app).await;
+
+    app.post_erase_subscriber(&serde_json::json!({ "email": subscriber.email }))
+        .await;
+
+    let entry = audit_entries(&app.db_pool).await.pop().unwrap();
+    assert_eq!(entry.action, "subscriber.erased");
+    assert_eq!(entry.target, Some(format!("subscriber:{}", subscriber.id)));
+}
+
+#[tokio::test]
+async fn the_audit_log_is_append_only() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+
+    let update = sqlx::query!("UPDATE audit_log SET action = 'nothing'")
+        .execute(&app.db_pool)
+        .await;
+    let delete = sqlx::query!("DELETE FROM audit_log")
+        .execute(&app.db_pool)
+        .await;
+
+    assert!(update.is_err());
+    assert!(delete.is_err());
+    assert_eq!(audit_entries(&app.db_pool).await.len(), 1);
+}
+
+#[tokio::test]
+async fn owners_can_filter_the_audit_log() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.api_client
+        .post(format!("{}/login", &app.address))
+        .header("User-Agent", "<script>alert(1)</script>")
+        .form(
+            &app.with_csrf_token(
+                &serde_json::json!({ "username": "nobody", "password": "nothing" }),
+            )
+            .await,
+        )
+        .send()
+        .await
+        .unwrap();
+    app.test_user.login(&app).await;
+
+    let html_page = app
+        .get_audit_log_html("action=login.failed")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("username:nobody"));
+    assert!(html_page.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+    assert!(!html_page.contains("<script>"));
+
+    let html_page = app
+        .get_audit_log_html(&format!("actor={}", app.test_user.username))
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(!html_page.contains("username:nobody"));
+    assert!(html_page.contains("<td>login</td>"));
+}
+
+#[tokio::test]
+async fn only_owners_can_see_the_audit_log() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+
+    let response = app.get_audit_log_html("").await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
@@ -0,0 +1,108 @@
+use crate::helpers::{assert_is_redirect_to, extract_csrf_token, spawn_app, TestAppConfiguration};
+
+const REJECTED: &str = "This form has expired or was not sent from this site.";
+
+#[tokio::test]
+async fn admin_forms_without_a_token_are_rejected() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(response.text().await.unwrap().contains(REJECTED));
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_token_from_another_session_is_rejected() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let other = app.login_elsewhere(&app.test_user).await;
+    let other_html = other
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    app.test_user.login(&app).await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/admin/password", &app.address))
+        .form(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": "a-new-password",
+            "new_password_check": "a-new-password",
+            "csrf_token": extract_csrf_token(&other_html)
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn logging_in_requires_a_token() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn anonymous_posts_are_sent_to_the_login_page() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn admin_forms_embed_the_session_token() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    let token = app.csrf_token().await;
+
+    let dashboard = app.get_admin_dashboard_html().await;
+    let newsletter = app.get_publish_newsletter_html().await;
+    let password = app.get_change_password_html().await;
+
+    for html_page in [dashboard, newsletter, password] {
+        assert_eq!(extract_csrf_token(&html_page), token);
+    }
+}
+
+#[tokio::test]
+async fn logging_in_issues_a_new_token() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let before = app.csrf_token().await;
+
+    app.test_user.login(&app).await;
+
+    assert_ne!(app.csrf_token().await, before);
+}
@@ -0,0 +1,193 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, publish_and_dispatch_newsletter, spawn_app,
+    TestApp, TestAppConfiguration,
+};
+
+async fn get_subscriber_email(app: &TestApp) -> String {
+    sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email
+}
+
+#[tokio::test]
+async fn data_requests_for_unknown_addresses_look_the_same_and_send_nothing() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let transport = app.email_client.get_transport_ref();
+
+    let response = app
+        .post_data_request(&serde_json::json!({
+            "email": "nobody@example.com",
+            "kind": "export"
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("If nobody@example.com is subscribed"));
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    assert!(transport.messages().await.is_empty());
+}
+
+#[tokio::test]
+async fn a_confirmed_export_request_returns_the_subscriber_data_as_json() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    let email = get_subscriber_email(&app).await;
+    app.test_user.login(&app).await;
+    let issue_id = publish_and_dispatch_newsletter(&app).await;
+
+    let transport = app.email_client.get_transport_ref();
+    let sent_before = transport.messages().await.len();
+    app.post_data_request(&serde_json::json!({ "email": &email, "kind": "export" }))
+        .await
+        .error_for_status()
+        .unwrap();
+    app.wait_for_email(sent_before).await;
+    let confirmation_links = app.get_confirmation_links(transport).await;
+    let response = app.confirm_data_request(confirmation_links.html).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscriber"]["email"], email.as_str());
+    assert_eq!(export["lists"][0]["slug"], "newsletter");
+    assert_eq!(
+        export["deliveries"][0]["newsletter_issue_id"],
+        issue_id.to_string()
+    );
+    assert!(export["preference_token"].is_string());
+}
+
+#[tokio::test]
+async fn a_confirmed_erasure_request_deletes_the_subscriber_but_keeps_delivery_stats() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    let email = get_subscriber_email(&app).await;
+    app.test_user.login(&app).await;
+    publish_and_dispatch_newsletter(&app).await;
+
+    let transport = app.email_client.get_transport_ref();
+    let sent_before = transport.messages().await.len();
+    app.post_data_request(&serde_json::json!({ "email": &email, "kind": "erasure" }))
+        .await
+        .error_for_status()
+        .unwrap();
+    app.wait_for_email(sent_before).await;
+    let confirmation_links = app.get_confirmation_links(transport).await;
+    let response = app
+        .confirm_data_request(confirmation_links.html.clone())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let subscribers = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(subscribers.is_empty());
+    let tokens = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(tokens.is_empty());
+    let history = sqlx::query!("SELECT subscriber_email, outcome FROM issue_delivery_history")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(history.subscriber_email, email);
+    assert_eq!(history.outcome, "delivered");
+
+    // The link can only be used once.
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn following_an_erasure_link_only_asks_for_confirmation() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    let email = get_subscriber_email(&app).await;
+
+    let transport = app.email_client.get_transport_ref();
+    let sent_before = transport.messages().await.len();
+    app.post_data_request(&serde_json::json!({ "email": &email, "kind": "erasure" }))
+        .await
+        .error_for_status()
+        .unwrap();
+    app.wait_for_email(sent_before).await;
+    let confirmation_links = app.get_confirmation_links(transport).await;
+    // What a mail scanner would do.
+    for _ in 0..2 {
+        let response = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        let html_page = response.text().await.unwrap();
+        assert!(html_page
+            .contains(r#"<form action="/subscriptions/data-requests/confirm" method="post">"#));
+        assert!(html_page.contains("Erase my data"));
+    }
+
+    let subscribers = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers.len(), 1);
+    let response = app.confirm_data_request(confirmation_links.html).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn confirming_a_data_request_needs_a_valid_token() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    let response = app.post_data_request_confirmation("not-a-token").await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn admins_can_export_and_erase_a_subscriber() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    let email = get_subscriber_email(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_subscriber_export(&email).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscriber"]["email"], email.as_str());
+
+    let response = app
+        .post_erase_subscriber(&serde_json::json!({ "email": &email }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    let response = app.post_subscriber_export(&email).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn erasure_messages_escape_the_address() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+
+    app.post_erase_subscriber(&serde_json::json!({ "email": "<b>ursula</b>@example.com" }))
+        .await;
+
+    let html_page = app.get_subscriber_data_html().await;
+    assert!(
+        html_page.contains("There is no subscriber using &lt;b&gt;ursula&lt;/b&gt;@example.com.")
+    );
+    assert!(!html_page.contains("<b>ursula</b>"));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_subscriber_data() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    let response = app.post_subscriber_export("ursula@example.com").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,258 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use reqwest::Method;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestAppConfiguration, TestUser};
+use zero2prod::email_domains::MxResolver;
+
+/// Stands in for DNS: every domain receives email but `no-mail.example`,
+/// and looking up `dns-down.example` fails.
+struct FakeMxResolver;
+
+#[async_trait]
+impl MxResolver for FakeMxResolver {
+    async fn accepts_mail(&self, domain: &str) -> Result<bool, anyhow::Error> {
+        match domain {
+            "no-mail.example" => Ok(false),
+            "dns-down.example" => Err(anyhow::anyhow!("SERVFAIL")),
+            _ => Ok(true),
+        }
+    }
+}
+
+async fn subscribe(app: &TestApp, email: &str) -> reqwest::Response {
+    app.post_subscription(
+        serde_urlencoded::to_string([("name", "le guin"), ("email", email)]).unwrap(),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn disposable_addresses_are_rejected_with_a_clear_error() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    for email in ["ursula@mailinator.com", "ursula@eu.Mailinator.com"] {
+        let response = subscribe(&app, email).await;
+
+        assert_eq!(response.status().as_u16(), 400);
+        assert!(response
+            .text()
+            .await
+            .unwrap()
+            .contains("hands out temporary addresses"));
+    }
+    assert!(app
+        .email_client
+        .get_transport_ref()
+        .messages()
+        .await
+        .is_empty());
+}
+
+#[tokio::test]
+async fn disposable_addresses_are_accepted_when_the_list_is_turned_off() {
+    let mut configuration = TestAppConfiguration::new();
+    configuration.configuration.email_domains.block_disposable = false;
+    let app = spawn_app(configuration).await;
+
+    let response = subscribe(&app, "ursula@mailinator.com").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn blocked_domains_and_their_subdomains_are_rejected() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_email_domain_rule("@Spam.example", "block").await;
+    assert_is_redirect_to(&response, "/admin/email-domains");
+    let html_page = app.get_email_domains_html().await;
+    assert!(html_page.contains("<p><i>spam.example is now blocked.</i></p>"));
+    assert!(html_page.contains("<td>spam.example</td><td>block</td>"));
+
+    for email in ["ursula@spam.example", "ursula@mail.spam.example"] {
+        let response = subscribe(&app, email).await;
+        assert_eq!(response.status().as_u16(), 400);
+        assert!(response
+            .text()
+            .await
+            .unwrap()
+            .starts_with("We do not accept addresses at"));
+    }
+    assert_eq!(
+        subscribe(&app, "ursula@not-spam.example")
+            .await
+            .status()
+            .as_u16(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn blocking_an_internationalized_domain_rejects_its_addresses() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+
+    app.post_email_domain_rule("bücher.example", "block").await;
+    let html_page = app.get_email_domains_html().await;
+    assert!(html_page.contains("<td>xn--bcher-kva.example</td><td>block</td>"));
+
+    for email in ["ursula@Bücher.example", "ursula@xn--bcher-kva.example"] {
+        let response = subscribe(&app, email).await;
+        assert_eq!(response.status().as_u16(), 400);
+        assert!(response
+            .text()
+            .await
+            .unwrap()
+            .starts_with("We do not accept addresses at"));
+    }
+}
+
+#[tokio::test]
+async fn an_allowed_domain_overrides_the_disposable_list_and_broader_blocks() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+
+    app.post_email_domain_rule("mailinator.com", "allow").await;
+    app.post_email_domain_rule("example.com", "block").await;
+    app.post_email_domain_rule("trusted.example.com", "allow")
+        .await;
+
+    assert_eq!(
+        subscribe(&app, "a@mailinator.com").await.status().as_u16(),
+        200
+    );
+    assert_eq!(
+        subscribe(&app, "b@trusted.example.com")
+            .await
+            .status()
+            .as_u16(),
+        200
+    );
+    assert_eq!(
+        subscribe(&app, "c@example.com").await.status().as_u16(),
+        400
+    );
+}
+
+#[tokio::test]
+async fn removing_a_rule_lets_the_domain_back_in_and_is_audited() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    app.post_email_domain_rule("spam.example", "block").await;
+
+    let response = app.post_remove_email_domain_rule("spam.example").await;
+    assert_is_redirect_to(&response, "/admin/email-domains");
+    let html_page = app.get_email_domains_html().await;
+    assert!(html_page.contains("<p><i>The rule for spam.example has been removed.</i></p>"));
+
+    assert_eq!(
+        subscribe(&app, "ursula@spam.example")
+            .await
+            .status()
+            .as_u16(),
+        200
+    );
+    let actions: Vec<String> = sqlx::query!(
+        "SELECT action FROM audit_log WHERE target = 'email_domain:spam.example' ORDER BY audit_id"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.action)
+    .collect();
+    assert_eq!(
+        actions,
+        ["email_domain.blocked", "email_domain.rule_removed"]
+    );
+}
+
+#[tokio::test]
+async fn invalid_rules_are_refused() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+
+    app.post_email_domain_rule("not a domain", "block").await;
+    let html_page = app.get_email_domains_html().await;
+    assert!(html_page.contains("<p><i>not a domain is not a valid domain.</i></p>"));
+
+    app.post_email_domain_rule("spam.example", "maybe").await;
+    let html_page = app.get_email_domains_html().await;
+    assert!(html_page.contains("<p><i>maybe is not a valid rule.</i></p>"));
+
+    app.post_remove_email_domain_rule("spam.example").await;
+    let html_page = app.get_email_domains_html().await;
+    assert!(html_page.contains("<p><i>There is no rule for spam.example.</i></p>"));
+}
+
+#[tokio::test]
+async fn only_owners_can_manage_domain_rules() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/admin/email-domains", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    let response = app.post_email_domain_rule("spam.example", "block").await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn domains_without_a_mail_server_are_rejected_when_checking_mx() {
+    let mut configuration = TestAppConfiguration::new();
+    configuration.configuration.email_domains.check_mx = true;
+    configuration.mx_resolver = Some(Arc::new(FakeMxResolver));
+    let app = spawn_app(configuration).await;
+
+    let response = subscribe(&app, "ursula@no-mail.example").await;
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("does not receive email"));
+
+    // Our DNS troubles are not the subscriber's fault.
+    assert_eq!(
+        subscribe(&app, "ursula@dns-down.example")
+            .await
+            .status()
+            .as_u16(),
+        200
+    );
+    assert_eq!(
+        subscribe(&app, "octavia@example.com")
+            .await
+            .status()
+            .as_u16(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn the_api_rejects_disposable_addresses_as_a_validation_error() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    let token = app.create_api_token(&["subscribers:write"]).await;
+
+    let response = app
+        .api_request(Method::POST, "/subscribers", &token)
+        .json(&serde_json::json!({ "name": "le guin", "email": "ursula@yopmail.com" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "validation_error");
+}
@@ -0,0 +1,86 @@
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscribers, spawn_app,
+    TestAppConfiguration,
+};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_subscriptions() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    let response = app.get_export_subscriptions("format=csv").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscriptions_are_exported_as_csv() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_export_subscriptions("format=csv").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "text/csv; charset=utf-8"
+    );
+    let body = response.text().await.unwrap();
+    let lines: Vec<_> = body.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0], "id,email,name,status,subscribed_at");
+    assert!(lines[1].contains(",confirmed,"));
+}
+
+#[tokio::test]
+async fn subscriptions_export_can_be_filtered_by_status() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    create_unconfirmed_subscribers(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .get_export_subscriptions("format=ndjson&status=pending_confirmation")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    let records: Vec<serde_json::Value> = body
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0]["status"], "pending_confirmation");
+}
+
+#[tokio::test]
+async fn delivery_history_of_an_issue_is_exported() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    let response = app.get_export_deliveries(issue_id, "format=ndjson").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    let records: Vec<serde_json::Value> = body
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0]["outcome"], "delivered");
+}
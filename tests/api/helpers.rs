@@ -1,8 +1,14 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
 use std::sync::Arc;
 
 use argon2::password_hash::SaltString;
 use argon2::Algorithm::Argon2id;
 use argon2::{Argon2, Params, PasswordHasher, Version};
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::name::en::Name;
+use fake::Fake;
+use lettre::AsyncTransport;
 use mail_parser::Message;
 use once_cell::sync::Lazy;
 use reqwest::Url;
@@ -12,8 +18,10 @@ use uuid::Uuid;
 use zero2prod::configuration::{get_configuration, DatabaseSettings, Settings};
 use zero2prod::domain::SubscriberName;
 use zero2prod::email_client::{
-    create_email_client_stub_which_accepts_all_messages, EmailClient, SenderInfo, StubMailTransport,
+    create_email_client, create_email_client_stub_which_accepts_all_messages, EmailClient,
+    MailTransport, SenderInfo, StubMailTransport,
 };
+use zero2prod::email_domains::MxResolver;
 use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
 use zero2prod::startup::{ApplicationBuilder, ApplicationData};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
@@ -32,6 +40,7 @@ static TRACING: Lazy<()> = Lazy::new(|| {
 
 pub struct TestApp {
     pub address: String,
+    pub base_url: String,
     pub db_pool: PgPool,
     pub port: u16,
     pub email_client: Arc<EmailClient<StubMailTransport>>,
@@ -48,7 +57,7 @@ impl TestApp {
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
             if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
+                try_execute_task(&self.db_pool, &self.email_client, &self.base_url)
                     .await
                     .unwrap()
             {
@@ -56,6 +65,22 @@ impl TestApp {
             }
         }
     }
+    /// Runs the delivery worker against an SMTP relay rather than the stub.
+    pub async fn dispatch_all_pending_emails_through(
+        &self,
+        email_client: &EmailClient<MailTransport>,
+    ) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue =
+                try_execute_task(&self.db_pool, email_client, &self.base_url)
+                    .await
+                    .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
     pub async fn post_subscription(&self, body: String) -> reqwest::Response {
         self.api_client
             .post(format!("{}/subscriptions", &self.address))
@@ -66,11 +91,35 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    pub async fn get_home_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_metrics(&self) -> String {
+        self.api_client
+            .get(format!("{}/metrics", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
     pub async fn get_confirmation_links(&self, transport: &StubMailTransport) -> ConfirmationLinks {
         let get_link = |s: &str| {
             let links: Vec<_> = linkify::LinkFinder::new()
                 .links(s)
                 .filter(|l| *l.kind() == linkify::LinkKind::Url)
+                // Subscriber emails end with a link to their preferences.
+                .filter(|l| !l.as_str().contains("/subscriptions/preferences?"))
                 .collect();
             assert_eq!(links.len(), 1);
             let raw_link = links[0].as_str().to_owned();
@@ -81,28 +130,41 @@ impl TestApp {
         };
 
         let received_messages = transport.messages().await;
-        let raw_message = received_messages[0].1.to_owned().into_bytes();
+        let raw_message = received_messages.last().unwrap().1.to_owned().into_bytes();
         let message = Message::parse(&raw_message).unwrap();
         let plain_text = get_link(&message.body_html(0).unwrap());
         let html = get_link(&message.body_text(0).unwrap());
         ConfirmationLinks { html, plain_text }
     }
 
+    /// Sends the issue to the default list, unless `body` picks lists.
     pub async fn post_newsletters<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
     {
+        let mut form = self.with_csrf_token(body).await;
+        if form.get("list_id").is_none() {
+            form["list_id"] = self.default_list_id().await.to_string().into();
+        }
         self.api_client
-            .post(&format!("{}/admin/newsletters", &self.address))
-            .form(body)
+            .post(format!("{}/admin/newsletters", &self.address))
+            .form(&form)
             .send()
             .await
             .expect("Failed to execute request")
     }
 
+    pub async fn default_list_id(&self) -> Uuid {
+        sqlx::query!("SELECT list_id FROM lists WHERE slug = 'newsletter'")
+            .fetch_one(&self.db_pool)
+            .await
+            .unwrap()
+            .list_id
+    }
+
     pub async fn get_publish_newsletter(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/newsletters", &self.address))
+            .get(format!("{}/admin/newsletters", &self.address))
             .send()
             .await
             .expect("Failed to execute request")
@@ -117,8 +179,8 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/login", &self.address))
-            .form(body)
+            .post(format!("{}/login", &self.address))
+            .form(&self.with_csrf_token(body).await)
             .send()
             .await
             .expect("Failed to execute request")
@@ -134,6 +196,209 @@ impl TestApp {
             .unwrap()
     }
 
+    /// The CSRF token of the client's session, as embedded in every form.
+    pub async fn csrf_token(&self) -> String {
+        extract_csrf_token(&self.get_login_html().await)
+    }
+
+    /// Adds the session's CSRF token to a form body.
+    pub async fn with_csrf_token<Body>(&self, body: &Body) -> serde_json::Value
+    where
+        Body: serde::Serialize,
+    {
+        let mut form = serde_json::to_value(body).unwrap();
+        form["csrf_token"] = self.csrf_token().await.into();
+        form
+    }
+
+    pub async fn get_lockouts_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/lockouts", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_email_domains_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/email-domains", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_email_domain_rule(&self, domain: &str, kind: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/email-domains", &self.address))
+            .form(
+                &self
+                    .with_csrf_token(&serde_json::json!({ "domain": domain, "kind": kind }))
+                    .await,
+            )
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_remove_email_domain_rule(&self, domain: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/email-domains/remove", &self.address))
+            .form(
+                &self
+                    .with_csrf_token(&serde_json::json!({ "domain": domain }))
+                    .await,
+            )
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_unlock(&self, subject: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/lockouts/unlock", &self.address))
+            .form(
+                &self
+                    .with_csrf_token(&serde_json::json!({ "subject": subject }))
+                    .await,
+            )
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_sessions_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_revoke_session(&self, session_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/sessions/{}/revoke",
+                &self.address, session_id
+            ))
+            .form(&self.with_csrf_token(&serde_json::json!({})).await)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_revoke_other_sessions(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/sessions/revoke-others", &self.address))
+            .form(&self.with_csrf_token(&serde_json::json!({})).await)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Logs `user` in from a separate client, as if from another device.
+    pub async fn login_elsewhere(&self, user: &TestUser) -> reqwest::Client {
+        let client = reqwest::Client::builder()
+            .cookie_store(true)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        let login_html = client
+            .get(format!("{}/login", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap();
+        let response = client
+            .post(format!("{}/login", &self.address))
+            .form(&serde_json::json!({
+                "username": &user.username,
+                "password": &user.password,
+                "csrf_token": extract_csrf_token(&login_html)
+            }))
+            .send()
+            .await
+            .expect("Failed to execute request");
+        assert_is_redirect_to(&response, "/admin/dashboard");
+        client
+    }
+
+    pub async fn get_api_tokens_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/api-tokens", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_api_token(&self, body: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/api-tokens", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(format!("{}&csrf_token={}", body, self.csrf_token().await))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Creates a token for the logged in user and returns it.
+    pub async fn create_api_token(&self, scopes: &[&str]) -> String {
+        let mut body = "name=ci".to_string();
+        for scope in scopes {
+            body.push_str(&format!("&scope={}", scope));
+        }
+        let html_page = self
+            .post_create_api_token(&body)
+            .await
+            .text()
+            .await
+            .unwrap();
+        let start = html_page.find("<code>z2p_").expect("No token in the page") + 6;
+        let end = start + html_page[start..].find("</code>").unwrap();
+        html_page[start..end].to_string()
+    }
+
+    pub async fn get_api_me(&self, token: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/api/v1/me", &self.address))
+            .bearer_auth(token)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub fn api_request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        token: &str,
+    ) -> reqwest::RequestBuilder {
+        self.api_client
+            .request(method, format!("{}/api/v1{}", &self.address, path))
+            .bearer_auth(token)
+    }
+
+    pub async fn get_audit_log_html(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/audit-log?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
             .get(format!("{}/admin/dashboard", &self.address))
@@ -147,7 +412,7 @@ impl TestApp {
 
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/password", &self.address))
+            .get(format!("{}/admin/password", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -158,8 +423,8 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/password", &self.address))
-            .form(body)
+            .post(format!("{}/admin/password", &self.address))
+            .form(&self.with_csrf_token(body).await)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -171,7 +436,332 @@ impl TestApp {
 
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/admin/logout", &self.address))
+            .post(format!("{}/admin/logout", &self.address))
+            .form(&self.with_csrf_token(&serde_json::json!({})).await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_lists_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/lists", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_lists<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/lists", &self.address))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_preferences(&self, preference_token: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/subscriptions/preferences", &self.address))
+            .query(&[("preference_token", preference_token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_preferences_html(&self, preference_token: &str) -> String {
+        self.get_preferences(preference_token)
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// `path` is relative to `/archive`.
+    pub async fn get_archive(&self, path: &str, archive_token: Option<&str>) -> reqwest::Response {
+        let mut request = self
+            .api_client
+            .get(format!("{}/archive{}", &self.address, path));
+        if let Some(archive_token) = archive_token {
+            request = request.query(&[("archive_token", archive_token)]);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
+    pub async fn post_preferences<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/subscriptions/preferences", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_email<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/subscriptions/preferences/email", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_unsubscribe<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/subscriptions/unsubscribe", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_data_request<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/subscriptions/data-requests", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Data request emails are sent in the background - wait until more
+    /// than `sent_before` emails have gone out.
+    pub async fn wait_for_email(&self, sent_before: usize) {
+        let transport = self.email_client.get_transport_ref();
+        for _ in 0..50 {
+            if transport.messages().await.len() > sent_before {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        panic!("No email was sent.");
+    }
+
+    /// Follows the link in a data request confirmation email, then confirms.
+    pub async fn confirm_data_request(&self, link: Url) -> reqwest::Response {
+        let response = reqwest::get(link.clone()).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        let request_token = link
+            .query_pairs()
+            .find(|(k, _)| k == "request_token")
+            .unwrap()
+            .1
+            .into_owned();
+        self.post_data_request_confirmation(&request_token).await
+    }
+
+    pub async fn post_data_request_confirmation(&self, request_token: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/subscriptions/data-requests/confirm",
+                &self.address
+            ))
+            .form(&[("request_token", request_token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_subscriber_export(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/subscribers/export", &self.address))
+            .form(
+                &self
+                    .with_csrf_token(&serde_json::json!({ "email": email }))
+                    .await,
+            )
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscriber_data_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/subscribers", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_erase_subscriber<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/subscribers/erase", &self.address))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_users_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/users", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_users<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/users", &self.address))
+            .form(&self.with_csrf_token(body).await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_deactivate_user(&self, user_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/users/{}/deactivate",
+                &self.address, user_id
+            ))
+            .form(&self.with_csrf_token(&serde_json::json!({})).await)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_accept_invitation(&self, link: &Url) -> reqwest::Response {
+        self.api_client
+            .get(link.as_str())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_accept_invitation<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/invitations/accept", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset_request<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/password-reset", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/password-reset/confirm", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_two_factor_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/two-factor", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_enable_two_factor(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/two-factor/enable", &self.address))
+            .form(
+                &self
+                    .with_csrf_token(&serde_json::json!({ "code": code }))
+                    .await,
+            )
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_disable_two_factor(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/two-factor/disable", &self.address))
+            .form(
+                &self
+                    .with_csrf_token(&serde_json::json!({ "code": code }))
+                    .await,
+            )
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_login_two_factor(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/login/two-factor", &self.address))
+            .form(
+                &self
+                    .with_csrf_token(&serde_json::json!({ "code": code }))
+                    .await,
+            )
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_export_subscriptions(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/export/subscriptions?{}",
+                &self.address, query
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_export_deliveries(&self, issue_id: Uuid, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/export/issues/{}/deliveries?{}",
+                &self.address, issue_id, query
+            ))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -181,17 +771,23 @@ impl TestApp {
 pub struct TestAppConfiguration {
     pub email_client: Arc<EmailClient<StubMailTransport>>,
     pub configuration: Settings,
+    /// Used for the MX check when `email_domains.check_mx` is on.
+    pub mx_resolver: Option<Arc<dyn MxResolver>>,
 }
 
 impl TestAppConfiguration {
     pub fn new() -> TestAppConfiguration {
-        let configuration = get_configuration().expect("Failed to read configuration");
+        let mut configuration = get_configuration().expect("Failed to read configuration");
+        // Most tests post the subscription form without rendering it
+        // first; the ones about spam turn the check back on.
+        configuration.subscription_protection.min_seconds_to_submit = 0;
         let sender = configuration.email_client.sender().unwrap();
         let sender = SenderInfo(SubscriberName::parse("test".into()).unwrap(), sender);
 
         TestAppConfiguration {
             email_client: Arc::new(create_email_client_stub_which_accepts_all_messages(sender)),
             configuration,
+            mx_resolver: None,
         }
     }
 
@@ -212,19 +808,25 @@ pub struct TestUser {
     pub user_id: Uuid,
     pub username: String,
     pub password: String,
+    pub role: &'static str,
 }
 
 impl TestUser {
     pub fn generate() -> Self {
+        Self::generate_with_role("owner")
+    }
+
+    pub fn generate_with_role(role: &'static str) -> Self {
         Self {
             user_id: Uuid::new_v4(),
             username: Uuid::new_v4().to_string(),
             password: Uuid::new_v4().to_string(),
+            role,
             // password: "everythingstartssomewhere".into(),
         }
     }
 
-    async fn store(&self, pool: &PgPool) {
+    pub async fn store(&self, pool: &PgPool) {
         let salt = SaltString::generate(&mut rand::thread_rng());
         let password_hash = Argon2::new(
             Argon2id,
@@ -236,11 +838,12 @@ impl TestUser {
         .to_string();
 
         sqlx::query!(
-            "INSERT INTO users(user_id, username, password_hash)
-            VALUES ($1, $2, $3)",
+            "INSERT INTO users(user_id, username, password_hash, role)
+            VALUES ($1, $2, $3, $4)",
             self.user_id,
             self.username,
-            password_hash
+            password_hash,
+            self.role
         )
         .execute(pool)
         .await
@@ -257,22 +860,43 @@ impl TestUser {
 }
 
 pub async fn spawn_app(test_app_configuration: TestAppConfiguration) -> TestApp {
+    let email_client = test_app_configuration.get_email_client();
+    spawn_app_sending_through(test_app_configuration, email_client).await
+}
+
+/// Like `spawn_app`, but the application sends its emails with
+/// `email_client` instead of the stub kept in `TestApp::email_client`.
+pub async fn spawn_app_sending_through<T>(
+    test_app_configuration: TestAppConfiguration,
+    app_email_client: Arc<EmailClient<T>>,
+) -> TestApp
+where
+    T: AsyncTransport + Send + Sync + 'static,
+    <T as AsyncTransport>::Error: 'static + Send + Sync,
+    <T as AsyncTransport>::Error: std::error::Error,
+{
     Lazy::force(&TRACING);
 
     let configuration = {
         let mut c = test_app_configuration.get_configuration();
         c.database.database_name = Uuid::new_v4().to_string();
         c.application.port = 0;
+        // Redis is shared by every test: keep each app's counters apart.
+        c.login_throttle.key_prefix = Uuid::new_v4().to_string();
+        c.subscription_protection.key_prefix = Uuid::new_v4().to_string();
         c
     };
 
     let connection_pool = configure_database(&configuration.database).await;
+    let base_url = configuration.application.base_url.clone();
 
     let email_client = test_app_configuration.get_email_client();
-    let application = ApplicationBuilder::new(configuration)
-        .store(ApplicationData::EmailClient, email_client.clone())
-        .build::<StubMailTransport>()
-        .await;
+    let mut builder = ApplicationBuilder::new(configuration)
+        .store(ApplicationData::EmailClient, app_email_client);
+    if let Some(resolver) = test_app_configuration.mx_resolver {
+        builder = builder.set_mx_resolver(resolver);
+    }
+    let application = builder.build::<T>().await;
 
     let api_client = reqwest::Client::builder()
         .cookie_store(true)
@@ -281,10 +905,11 @@ pub async fn spawn_app(test_app_configuration: TestAppConfiguration) -> TestApp
         .unwrap();
 
     let application_port = application.port();
-    let _ = tokio::spawn(application.run_until_stopped());
+    drop(tokio::spawn(application.run_until_stopped()));
 
     let test_app = TestApp {
         address: format!("http://localhost:{}", application_port),
+        base_url,
         port: application_port,
         db_pool: connection_pool,
         email_client,
@@ -316,6 +941,157 @@ pub async fn configure_database(config: &DatabaseSettings) -> PgPool {
     connection_pool
 }
 
+pub async fn create_unconfirmed_subscribers(app: &TestApp) -> ConfirmationLinks {
+    let name: String = Name().fake();
+    let email: String = SafeEmail().fake();
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": name,
+        "email": email
+    }))
+    .unwrap();
+
+    let transport = app.email_client.get_transport_ref();
+    let received_messages_count_before_sending_subscribe_request = transport.messages().await.len();
+    let request = app
+        .post_subscription(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let received_messages_count_after_sending_subscriber_request = transport.messages().await.len();
+    assert_eq!(
+        received_messages_count_before_sending_subscribe_request + 1,
+        received_messages_count_after_sending_subscriber_request
+    );
+
+    assert_eq!(request.status(), 200);
+    app.get_confirmation_links(transport).await
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_link = create_unconfirmed_subscribers(app).await;
+
+    reqwest::get(confirmation_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+/// Subscribes `email` through the public form, with `fields` added to it,
+/// and follows the link to confirm.
+pub async fn subscribe_and_confirm(app: &TestApp, email: &str, fields: serde_json::Value) {
+    let mut form = serde_json::json!({ "name": "le guin", "email": email });
+    form.as_object_mut()
+        .unwrap()
+        .extend(fields.as_object().unwrap().clone());
+    let body = serde_urlencoded::to_string(form).unwrap();
+    app.post_subscription(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    let links = app
+        .get_confirmation_links(app.email_client.get_transport_ref())
+        .await;
+    reqwest::get(links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+/// The preference token of the only subscriber.
+pub async fn get_preference_token(app: &TestApp) -> String {
+    sqlx::query!("SELECT preference_token FROM preference_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .preference_token
+}
+
+/// Publishes an issue to the default list, delivers it and returns its id.
+pub async fn publish_and_dispatch_newsletter(app: &TestApp) -> Uuid {
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+    sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues ORDER BY published_at DESC LIMIT 1"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id
+}
+
+/// An SMTP relay on a random port which accepts everything but recipients,
+/// answered with `rcpt_reply`, and returns an email client that uses it.
+pub async fn email_client_for_fake_relay(rcpt_reply: &'static str) -> EmailClient<MailTransport> {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { break };
+            std::thread::spawn(move || serve_smtp(stream, rcpt_reply));
+        }
+    });
+
+    let mut configuration = get_configuration().unwrap().email_client;
+    configuration.smtp_server = "127.0.0.1".into();
+    configuration.port = port;
+    let sender = SenderInfo(
+        SubscriberName::parse("test".into()).unwrap(),
+        configuration.sender().unwrap(),
+    );
+    create_email_client(configuration, sender).await
+}
+
+fn serve_smtp(stream: std::net::TcpStream, rcpt_reply: &str) {
+    let mut writer = stream.try_clone().unwrap();
+    let _ = writer.write_all(b"220 relay.test ESMTP\r\n");
+    let mut in_data = false;
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else { break };
+        let reply = match line.get(..4).unwrap_or("") {
+            _ if in_data => {
+                if line != "." {
+                    continue;
+                }
+                in_data = false;
+                "250 queued"
+            }
+            "EHLO" => "250-relay.test\r\n250 AUTH PLAIN LOGIN",
+            "AUTH" => "235 authenticated",
+            "RCPT" => rcpt_reply,
+            "DATA" => {
+                in_data = true;
+                "354 go ahead"
+            }
+            "QUIT" => "221 bye",
+            _ => "250 ok",
+        };
+        if writer
+            .write_all(format!("{}\r\n", reply).as_bytes())
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+pub fn extract_csrf_token(html: &str) -> String {
+    let start = html
+        .find(r#"name="csrf_token" value=""#)
+        .expect("The page has no CSRF token")
+        + r#"name="csrf_token" value=""#.len();
+    let end = start + html[start..].find('"').unwrap();
+    html[start..end].to_string()
+}
+
 pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
     assert_eq!(response.status().as_u16(), 303);
     assert_eq!(response.headers().get("Location").unwrap(), location);
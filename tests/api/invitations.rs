@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestAppConfiguration};
+
+/// Invites `email` as the test user and returns the fields of the setup link.
+async fn invite(app: &TestApp, email: &str) -> HashMap<String, String> {
+    app.test_user.login(app).await;
+    let response = app
+        .post_users(&serde_json::json!({
+            "email": email,
+            "role": "editor"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    app.post_logout().await;
+
+    let transport = app.email_client.get_transport_ref();
+    let links = app.get_confirmation_links(transport).await;
+    assert_eq!(links.html.path(), "/invitations/accept");
+    links.html.query_pairs().into_owned().collect()
+}
+
+fn setup_form(
+    invitation: &HashMap<String, String>,
+    username: &str,
+    password: &str,
+) -> HashMap<String, String> {
+    let mut form = invitation.clone();
+    form.insert("username".into(), username.into());
+    form.insert("password".into(), password.into());
+    form.insert("password_check".into(), password.into());
+    form
+}
+
+#[tokio::test]
+async fn invited_users_can_set_up_their_account_and_log_in() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let invitation = invite(&app, "ursula@example.com").await;
+
+    let response = app
+        .post_accept_invitation(&setup_form(&invitation, "ursula", "earthsea"))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "ursula",
+            "password": "earthsea"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let role = sqlx::query!("SELECT role FROM users WHERE username = 'ursula'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .role;
+    assert_eq!(role, "editor");
+}
+
+#[tokio::test]
+async fn the_setup_form_is_shown_for_a_valid_link() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    app.post_users(&serde_json::json!({
+        "email": "ursula@example.com",
+        "role": "viewer"
+    }))
+    .await;
+
+    let transport = app.email_client.get_transport_ref();
+    let links = app.get_confirmation_links(transport).await;
+    let response = app.get_accept_invitation(&links.html).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("ursula@example.com"));
+}
+
+#[tokio::test]
+async fn a_setup_link_can_only_be_used_once() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let invitation = invite(&app, "ursula@example.com").await;
+
+    app.post_accept_invitation(&setup_form(&invitation, "ursula", "earthsea"))
+        .await;
+    let response = app
+        .post_accept_invitation(&setup_form(&invitation, "mallory", "hunter2"))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_tampered_setup_link_is_rejected() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let mut invitation = invite(&app, "ursula@example.com").await;
+    let expires_at: i64 = invitation["expires_at"].parse().unwrap();
+    invitation.insert("expires_at".into(), (expires_at + 3600).to_string());
+
+    let response = app
+        .post_accept_invitation(&setup_form(&invitation, "ursula", "earthsea"))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn invited_users_cannot_take_an_existing_username() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let invitation = invite(&app, "ursula@example.com").await;
+
+    let response = app
+        .post_accept_invitation(&setup_form(
+            &invitation,
+            &app.test_user.username,
+            "earthsea",
+        ))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 303);
+    let location = response.headers()["Location"].to_str().unwrap().to_owned();
+    let html_page = app
+        .api_client
+        .get(format!("{}{}", app.address, location))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(&format!(
+        "<p><i>The username {} is already taken.</i></p>",
+        app.test_user.username
+    )));
+}
+
+#[tokio::test]
+async fn a_setup_link_used_twice_at_once_sets_up_one_account() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let invitation = invite(&app, "ursula@example.com").await;
+    let ursula = setup_form(&invitation, "ursula", "earthsea");
+    let mallory = setup_form(&invitation, "mallory", "hunter2");
+
+    let (first, second) = tokio::join!(
+        app.post_accept_invitation(&ursula),
+        app.post_accept_invitation(&mallory)
+    );
+
+    let mut statuses = [first.status().as_u16(), second.status().as_u16()];
+    statuses.sort();
+    assert_eq!(statuses, [303, 401]);
+    let usernames = sqlx::query!("SELECT username FROM users WHERE email = 'ursula@example.com'")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(usernames.len(), 1);
+    assert!(usernames[0].username.is_some());
+}
+
+#[tokio::test]
+async fn usernames_are_escaped_on_the_users_page() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let invitation = invite(&app, "ursula@example.com").await;
+    app.post_accept_invitation(&setup_form(&invitation, r#""><b>x"#, "earthsea"))
+        .await;
+
+    app.test_user.login(&app).await;
+    let html_page = app.get_users_html().await;
+
+    assert!(html_page.contains("<td>&quot;&gt;&lt;b&gt;x</td>"));
+    assert!(!html_page.contains("<b>x"));
+}
@@ -0,0 +1,196 @@
+use fake::faker::internet::en::SafeEmail;
+use fake::Fake;
+use mail_parser::Message;
+use uuid::Uuid;
+
+use crate::helpers::{
+    assert_is_redirect_to, spawn_app, subscribe_and_confirm, TestApp, TestAppConfiguration,
+};
+
+async fn create_list(app: &TestApp, slug: &str) -> Uuid {
+    let response = app
+        .post_lists(&serde_json::json!({
+            "slug": slug,
+            "name": "Engineering digest"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/lists");
+    sqlx::query!("SELECT list_id FROM lists WHERE slug = $1", slug)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_lists() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    let response = app
+        .post_lists(&serde_json::json!({
+            "slug": "engineering",
+            "name": "Engineering digest"
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_new_list_is_shown_on_the_lists_page() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+
+    create_list(&app, "engineering").await;
+
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("<p><i>The list engineering has been created.</i></p>"));
+    assert!(html_page.contains("<td>engineering</td>"));
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&list=does-not-exist";
+    let response = app.post_subscription(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_same_email_can_join_several_lists() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    create_list(&app, "engineering").await;
+    let email: String = SafeEmail().fake();
+
+    subscribe_and_confirm(&app, &email, serde_json::json!({ "list": "newsletter" })).await;
+    subscribe_and_confirm(&app, &email, serde_json::json!({ "list": "engineering" })).await;
+
+    let saved = sqlx::query!(
+        r#"SELECT COUNT(*) as "count!" FROM list_subscriptions WHERE status = 'confirmed'"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.count, 2);
+}
+
+#[tokio::test]
+async fn newsletters_are_only_delivered_to_the_selected_lists() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    let engineering_list_id = create_list(&app, "engineering").await;
+    subscribe_and_confirm(
+        &app,
+        &SafeEmail().fake::<String>(),
+        serde_json::json!({ "list": "newsletter" }),
+    )
+    .await;
+    let engineer: String = SafeEmail().fake();
+    subscribe_and_confirm(
+        &app,
+        &engineer,
+        serde_json::json!({ "list": "engineering" }),
+    )
+    .await;
+    let transport = app.email_client.get_transport_ref();
+    let sent_before = transport.messages().await.len();
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "list_id": engineering_list_id
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    let messages = transport.messages().await;
+    assert_eq!(messages.len(), sent_before + 1);
+    assert_eq!(messages.last().unwrap().0.to()[0].to_string(), engineer);
+}
+
+#[tokio::test]
+async fn issues_must_go_to_at_least_one_existing_list() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    subscribe_and_confirm(
+        &app,
+        &SafeEmail().fake::<String>(),
+        serde_json::json!({ "list": "newsletter" }),
+    )
+    .await;
+    let issue = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+
+    // No list ticked.
+    let response = app
+        .api_client
+        .post(format!("{}/admin/newsletters", &app.address))
+        .form(&app.with_csrf_token(&issue).await)
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("Pick at least one list"));
+
+    let mut unknown_list = issue.clone();
+    unknown_list["list_id"] = Uuid::new_v4().to_string().into();
+    let response = app.post_newsletters(&unknown_list).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("Some of the selected lists do not exist."));
+
+    let issues = sqlx::query!(r#"SELECT count(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.count, 0);
+}
+
+#[tokio::test]
+async fn list_names_are_escaped() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    app.post_lists(&serde_json::json!({
+        "slug": "engineering",
+        "name": "<b>Engineering</b>"
+    }))
+    .await;
+
+    for html_page in [
+        app.get_lists_html().await,
+        app.get_publish_newsletter_html().await,
+    ] {
+        assert!(html_page.contains("&lt;b&gt;Engineering&lt;/b&gt;"));
+        assert!(!html_page.contains("<b>Engineering"));
+    }
+
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+        "list": "engineering"
+    }))
+    .unwrap();
+    app.post_subscription(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    let messages = app.email_client.get_transport_ref().messages().await;
+    let raw = messages.last().unwrap().1.clone().into_bytes();
+    let email = Message::parse(&raw).unwrap();
+    let html = email.body_html(0).unwrap();
+    assert!(html.contains("&lt;b&gt;Engineering&lt;/b&gt;"));
+    assert!(!html.contains("<b>Engineering"));
+    assert!(email.body_text(0).unwrap().contains("<b>Engineering</b>"));
+}
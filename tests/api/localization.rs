@@ -0,0 +1,185 @@
+use mail_parser::Message;
+
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp, TestAppConfiguration};
+
+async fn get_html(app: &TestApp, path: &str, accept_language: &str) -> String {
+    app.api_client
+        .get(format!("{}{}", &app.address, path))
+        .header("Accept-Language", accept_language)
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .text()
+        .await
+        .unwrap()
+}
+
+async fn subscribe(app: &TestApp, accept_language: &str, extra: &[(&str, &str)]) -> u16 {
+    let mut fields = vec![("name", "le guin"), ("email", "ursula_le_guin@gmail.com")];
+    fields.extend_from_slice(extra);
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept-Language", accept_language)
+        .body(serde_urlencoded::to_string(fields).unwrap())
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .status()
+        .as_u16()
+}
+
+async fn stored_locale(app: &TestApp) -> String {
+    sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .locale
+}
+
+#[tokio::test]
+async fn the_home_page_is_in_the_language_the_browser_prefers() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    let persian = get_html(&app, "/", "fa-IR,fa;q=0.9,en;q=0.8").await;
+    let english = get_html(&app, "/", "de, en;q=0.5").await;
+
+    assert!(persian.contains(r#"<html lang="fa" dir="rtl">"#));
+    assert!(persian.contains("به خبرنامهٔ ما خوش آمدید"));
+    assert!(persian.contains(r#"name="locale" value="fa""#));
+    assert!(english.contains(r#"<html lang="en" dir="ltr">"#));
+    assert!(english.contains("Welcome to our newsletter"));
+}
+
+#[tokio::test]
+async fn subscribers_get_their_confirmation_in_their_language() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    assert_eq!(subscribe(&app, "fa", &[]).await, 200);
+
+    assert_eq!(stored_locale(&app).await, "fa");
+    let messages = app.email_client.get_transport_ref().messages().await;
+    let raw = messages[0].1.clone().into_bytes();
+    let email = Message::parse(&raw).unwrap();
+    assert_eq!(email.subject(), Some("خوش آمدید"));
+    assert!(email.body_html(0).unwrap().contains(r#"dir="rtl""#));
+    assert!(email.body_text(0).unwrap().contains("برای تأیید عضویت"));
+}
+
+#[tokio::test]
+async fn the_form_field_wins_over_the_browser_language() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    assert_eq!(subscribe(&app, "fa", &[("locale", "en")]).await, 200);
+
+    assert_eq!(stored_locale(&app).await, "en");
+}
+
+#[tokio::test]
+async fn unsupported_languages_are_rejected() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    assert_eq!(subscribe(&app, "en", &[("locale", "tlh")]).await, 400);
+}
+
+#[tokio::test]
+async fn login_errors_are_shown_in_the_browser_language() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let body = app
+        .with_csrf_token(&serde_json::json!({
+            "username": "random-username",
+            "password": "random-password"
+        }))
+        .await;
+    app.api_client
+        .post(format!("{}/login", &app.address))
+        .header("Accept-Language", "fa")
+        .form(&body)
+        .send()
+        .await
+        .unwrap();
+
+    let html = get_html(&app, "/login", "fa").await;
+
+    assert!(html.contains(r#"dir="rtl""#));
+    assert!(html.contains("<p><i>احراز هویت ناموفق بود.</i></p>"));
+}
+
+#[tokio::test]
+async fn newsletters_end_with_a_footer_in_the_subscriber_language() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscriptions SET locale = 'fa'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    }))
+    .await;
+
+    app.dispatch_all_pending_emails().await;
+
+    let messages = app.email_client.get_transport_ref().messages().await;
+    let raw = messages.last().unwrap().1.clone().into_bytes();
+    let email = Message::parse(&raw).unwrap();
+    assert!(email.body_text(0).unwrap().contains("مدیریت عضویت: "));
+    assert!(email
+        .body_html(0)
+        .unwrap()
+        .contains(r#"<p dir="rtl"><a href=""#));
+}
+
+async fn last_email_subject(app: &TestApp) -> String {
+    let messages = app.email_client.get_transport_ref().messages().await;
+    let raw = messages.last().unwrap().1.clone().into_bytes();
+    let email = Message::parse(&raw).unwrap();
+    email.subject().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn the_preference_center_speaks_the_subscriber_language() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    assert_eq!(subscribe(&app, "fa", &[]).await, 200);
+    let preference_token = sqlx::query!("SELECT preference_token FROM preference_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .preference_token;
+
+    let html = app.get_preferences_html(&preference_token).await;
+    assert!(html.contains(r#"<html lang="fa" dir="rtl">"#));
+    assert!(html.contains("<title>تنظیمات عضویت</title>"));
+
+    app.post_change_email(&serde_json::json!({
+        "preference_token": &preference_token,
+        "new_email": "new_address@example.com"
+    }))
+    .await;
+    assert_eq!(
+        last_email_subject(&app).await,
+        "نشانی ایمیل جدید خود را تأیید کنید"
+    );
+    let html = app.get_preferences_html(&preference_token).await;
+    assert!(html.contains("پیوند تأیید را به new_address@example.com فرستادیم."));
+
+    let sent_before = app.email_client.get_transport_ref().messages().await.len();
+    app.post_data_request(&serde_json::json!({
+        "email": "ursula_le_guin@gmail.com",
+        "kind": "export"
+    }))
+    .await;
+    app.wait_for_email(sent_before).await;
+    assert_eq!(
+        last_email_subject(&app).await,
+        "درخواست دریافت داده‌های خود را تأیید کنید"
+    );
+}
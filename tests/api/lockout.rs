@@ -0,0 +1,120 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestAppConfiguration, TestUser};
+
+const LOCKED_OUT: &str = "Too many failed login attempts. Please try again in 15 minutes.";
+
+async fn fail_logins(app: &TestApp, username: &str, attempts: usize) {
+    for _ in 0..attempts {
+        let response = app
+            .post_login(&serde_json::json!({
+                "username": username,
+                "password": "wrong-password"
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/login");
+    }
+}
+
+#[tokio::test]
+async fn repeated_failures_lock_the_account_out() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    fail_logins(&app, &app.test_user.username, 5).await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert!(app.get_login_html().await.contains(LOCKED_OUT));
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn unknown_usernames_are_locked_out_like_real_ones() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    fail_logins(&app, "no-such-user", 6).await;
+
+    assert!(app.get_login_html().await.contains(LOCKED_OUT));
+}
+
+#[tokio::test]
+async fn a_successful_login_resets_the_failure_count() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    fail_logins(&app, &app.test_user.username, 4).await;
+    app.test_user.login(&app).await;
+    app.post_logout().await;
+    fail_logins(&app, &app.test_user.username, 4).await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn owners_can_lift_a_lockout() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let locked = TestUser::generate_with_role("editor");
+    locked.store(&app.db_pool).await;
+    fail_logins(&app, &locked.username, 5).await;
+
+    app.test_user.login(&app).await;
+    let html_page = app.get_lockouts_html().await;
+    assert!(html_page.contains(&format!("username {}", locked.username)));
+
+    let response = app
+        .post_unlock(&format!("username:{}", locked.username))
+        .await;
+    assert_is_redirect_to(&response, "/admin/lockouts");
+    let html_page = app.get_lockouts_html().await;
+    assert!(html_page.contains(&format!(
+        "<p><i>The username {} has been unlocked.</i></p>",
+        locked.username
+    )));
+    app.post_logout().await;
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &locked.username,
+            "password": &locked.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn locked_out_usernames_are_escaped() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    fail_logins(&app, r#""><b>x"#, 5).await;
+
+    app.test_user.login(&app).await;
+    let html_page = app.get_lockouts_html().await;
+
+    assert!(!html_page.contains("<b>x"));
+    assert!(html_page.contains("username &quot;&gt;&lt;b&gt;x</td>"));
+    assert!(html_page.contains(r#"value="username:&quot;&gt;&lt;b&gt;x""#));
+}
+
+#[tokio::test]
+async fn only_owners_can_see_lockouts() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/admin/lockouts", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 403);
+}
@@ -1,8 +1,29 @@
 mod admin_dashboard;
+mod anti_spam;
+mod api_tokens;
+mod api_v1;
+mod archive;
+mod audit_log;
 mod change_password;
+mod csrf;
+mod data_requests;
+mod email_domains;
+mod export;
 mod health_check;
 mod helpers;
+mod invitations;
+mod lists;
+mod localization;
+mod lockout;
 mod login;
 mod newsletter;
+mod openapi;
+mod password_reset;
+mod preferences;
+mod security_headers;
+mod segmentation;
+mod sessions;
 mod subscription;
 mod subscription_confirm;
+mod two_factor;
+mod users;
@@ -1,50 +1,10 @@
-use fake::{
-    faker::{internet::en::SafeEmail, name::en::Name},
-    Fake,
-};
 use lettre::transport::stub::AsyncStubTransport;
 
 use crate::helpers::{
-    assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp, TestAppConfiguration,
+    assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscribers,
+    email_client_for_fake_relay, spawn_app, TestApp, TestAppConfiguration,
 };
 
-async fn create_unconfirmed_subscribers(app: &TestApp) -> ConfirmationLinks {
-    let name: String = Name().fake();
-    let email: String = SafeEmail().fake();
-    let body = serde_urlencoded::to_string(&serde_json::json!({
-        "name": name,
-        "email": email
-    }))
-    .unwrap();
-
-    let transport = app.email_client.get_transport_ref();
-    let received_messages_count_before_sending_subscribe_request = transport.messages().await.len();
-    let request = app
-        .post_subscription(body)
-        .await
-        .error_for_status()
-        .unwrap();
-
-    let received_messages_count_after_sending_subscriber_request = transport.messages().await.len();
-    assert_eq!(
-        received_messages_count_before_sending_subscribe_request + 1,
-        received_messages_count_after_sending_subscriber_request
-    );
-
-    assert_eq!(request.status(), 200);
-    app.get_confirmation_links(transport).await
-}
-
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscribers(app).await;
-
-    reqwest::get(confirmation_link.html)
-        .await
-        .unwrap()
-        .error_for_status()
-        .unwrap();
-}
-
 struct AsyncStubTransportSpy<'a> {
     transport_ref: &'a AsyncStubTransport,
     received_messages_before_assert: usize,
@@ -52,7 +12,7 @@ struct AsyncStubTransportSpy<'a> {
 }
 
 impl<'a> AsyncStubTransportSpy<'a> {
-    pub async fn new(transport_ref: &'a AsyncStubTransport) -> AsyncStubTransportSpy {
+    pub async fn new(transport_ref: &'a AsyncStubTransport) -> AsyncStubTransportSpy<'a> {
         let recieved_messages_count = transport_ref.messages().await.len();
         Self {
             transport_ref,
@@ -245,6 +205,104 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     spy.assert().await;
 }
 
+async fn publish_an_issue(app: &TestApp) {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await;
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn deliveries_the_relay_defers_are_retried_later() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    publish_an_issue(&app).await;
+    let relay = email_client_for_fake_relay("451 mailbox busy").await;
+
+    app.dispatch_all_pending_emails_through(&relay).await;
+
+    let queued = sqlx::query!(
+        r#"SELECT n_retries, execute_after > now() AS "later!" FROM issue_delivery_queue"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(queued.n_retries, 1);
+    assert!(queued.later);
+    let recorded = sqlx::query!("SELECT outcome FROM issue_delivery_history")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(recorded.is_empty());
+}
+
+#[tokio::test]
+async fn deliveries_the_relay_rejects_are_not_retried() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    publish_an_issue(&app).await;
+    let relay = email_client_for_fake_relay("550 no such user").await;
+
+    app.dispatch_all_pending_emails_through(&relay).await;
+
+    let queued = sqlx::query!("SELECT n_retries FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(queued.is_empty());
+    let recorded = sqlx::query!("SELECT outcome FROM issue_delivery_history")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(recorded.outcome, "failed");
+}
+
+#[tokio::test]
+async fn deferred_deliveries_give_up_after_the_last_retry() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    publish_an_issue(&app).await;
+    sqlx::query!("UPDATE issue_delivery_queue SET n_retries = 5")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let relay = email_client_for_fake_relay("451 mailbox busy").await;
+
+    app.dispatch_all_pending_emails_through(&relay).await;
+
+    let recorded = sqlx::query!("SELECT outcome FROM issue_delivery_history")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(recorded.outcome, "failed");
+}
+
+#[tokio::test]
+async fn deliveries_through_a_relay_that_accepts_them_are_recorded() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    publish_an_issue(&app).await;
+    let relay = email_client_for_fake_relay("250 ok").await;
+
+    app.dispatch_all_pending_emails_through(&relay).await;
+
+    let recorded = sqlx::query!("SELECT outcome FROM issue_delivery_history")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(recorded.outcome, "delivered");
+}
+
 // TODO
 // #[tokio::test]
 // async fn transient_errors_do_not_cause_duplicate_deliveries_on_retries() {
@@ -0,0 +1,138 @@
+use reqwest::Method;
+use zero2prod::domain::Segment;
+use zero2prod::email_client::StubMailTransport;
+use zero2prod::routes::api_v1_routes;
+
+use crate::helpers::{spawn_app, TestAppConfiguration};
+
+/// The spec integrators work from, committed next to the code.
+const SPEC_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+#[tokio::test]
+async fn the_served_spec_matches_the_committed_one() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/openapi.json", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let served: serde_json::Value = response.json().await.unwrap();
+
+    if std::env::var("UPDATE_OPENAPI").is_ok() {
+        let spec = serde_json::to_string_pretty(&served).unwrap();
+        std::fs::write(SPEC_PATH, spec + "\n").unwrap();
+        return;
+    }
+    let committed: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(SPEC_PATH).unwrap()).unwrap();
+    assert!(
+        served == committed,
+        "The API has changed: review the difference and run \
+        `UPDATE_OPENAPI=1 cargo test the_served_spec_matches_the_committed_one` \
+        to update openapi.json"
+    );
+}
+
+#[tokio::test]
+async fn every_documented_operation_is_routed() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    let token = app
+        .create_api_token(&[
+            "newsletters:read",
+            "newsletters:write",
+            "subscribers:read",
+            "subscribers:write",
+        ])
+        .await;
+    let spec: serde_json::Value = app
+        .api_client
+        .get(format!("{}/openapi.json", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    for (path, operations) in spec["paths"].as_object().unwrap() {
+        // Path parameters are all ids: any well-formed one will do.
+        let url = path
+            .split('/')
+            .map(|segment| {
+                if segment.starts_with('{') {
+                    uuid::Uuid::nil().to_string()
+                } else {
+                    segment.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        for method in operations.as_object().unwrap().keys() {
+            let method = Method::from_bytes(method.to_uppercase().as_bytes()).unwrap();
+            let response = app
+                .api_client
+                .request(method.clone(), format!("{}{}", &app.address, url))
+                .bearer_auth(&token)
+                .send()
+                .await
+                .unwrap();
+            let status = response.status().as_u16();
+            let is_json = response
+                .headers()
+                .get("Content-Type")
+                .is_some_and(|v| v == "application/json");
+            // Handlers answer 404 for unknown ids, but always with a body.
+            assert!(
+                status != 405 && (status != 404 || is_json),
+                "{} {} is documented but not routed (got {})",
+                method,
+                path,
+                status
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn every_routed_operation_is_documented() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let spec: serde_json::Value = app
+        .api_client
+        .get(format!("{}/openapi.json", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    for (method, path, _) in api_v1_routes::<StubMailTransport>() {
+        let path = format!("/api/v1{}", path);
+        assert!(
+            spec["paths"][&path][method.as_str().to_lowercase()].is_object(),
+            "{} {} is routed but missing from the spec",
+            method,
+            path
+        );
+    }
+}
+
+#[test]
+fn the_segment_example_is_a_valid_segment() {
+    let spec: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(SPEC_PATH).unwrap()).unwrap();
+
+    let example = spec["components"]["schemas"]["NewIssue"]["properties"]["segment"]["example"]
+        .as_str()
+        .unwrap();
+
+    assert!(
+        Segment::parse(example).is_ok(),
+        "{} does not parse",
+        example
+    );
+}
@@ -0,0 +1,178 @@
+use std::time::Duration;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestAppConfiguration};
+
+async fn give_test_user_an_email(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE users SET email = 'ursula@example.com' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+/// Reset links are sent in the background - wait for the email to show up.
+async fn get_reset_token(app: &TestApp) -> String {
+    let transport = app.email_client.get_transport_ref();
+    for _ in 0..50 {
+        if !transport.messages().await.is_empty() {
+            let links = app.get_confirmation_links(transport).await;
+            assert_eq!(links.html.path(), "/password-reset/confirm");
+            return links
+                .html
+                .query_pairs()
+                .find(|(k, _)| k == "reset_token")
+                .unwrap()
+                .1
+                .into_owned();
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("No password reset email was sent.");
+}
+
+async fn request_reset_token(app: &TestApp) -> String {
+    let response = app
+        .post_password_reset_request(&serde_json::json!({
+            "username": &app.test_user.username
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    get_reset_token(app).await
+}
+
+#[tokio::test]
+async fn a_password_can_be_reset_with_the_emailed_link() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    give_test_user_an_email(&app).await;
+    let reset_token = request_reset_token(&app).await;
+
+    let response = app
+        .post_password_reset(&serde_json::json!({
+            "reset_token": &reset_token,
+            "new_password": "a-brand-new-password",
+            "new_password_check": "a-brand-new-password"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": "a-brand-new-password"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn the_seeded_admin_can_reset_its_password_once_given_an_email() {
+    let mut configuration = TestAppConfiguration::new();
+    configuration.configuration.application.admin_email = Some("admin@example.com".into());
+    let app = spawn_app(configuration).await;
+
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "username": "admin" }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let reset_token = get_reset_token(&app).await;
+    let messages = app.email_client.get_transport_ref().messages().await;
+    assert_eq!(messages[0].0.to()[0].to_string(), "admin@example.com");
+    let response = app
+        .post_password_reset(&serde_json::json!({
+            "reset_token": &reset_token,
+            "new_password": "a-brand-new-password",
+            "new_password_check": "a-brand-new-password"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "admin",
+            "password": "a-brand-new-password"
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn unknown_usernames_get_the_same_answer_and_no_email() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    give_test_user_an_email(&app).await;
+
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "username": "nobody" }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("If that account exists, we have emailed it a link"));
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let transport = app.email_client.get_transport_ref();
+    assert!(transport.messages().await.is_empty());
+}
+
+#[tokio::test]
+async fn a_reset_link_can_only_be_used_once() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    give_test_user_an_email(&app).await;
+    let reset_token = request_reset_token(&app).await;
+    let body = serde_json::json!({
+        "reset_token": &reset_token,
+        "new_password": "a-brand-new-password",
+        "new_password_check": "a-brand-new-password"
+    });
+
+    app.post_password_reset(&body).await;
+    let response = app.post_password_reset(&body).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn an_expired_reset_link_is_rejected() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    give_test_user_an_email(&app).await;
+    let reset_token = request_reset_token(&app).await;
+    sqlx::query!("UPDATE password_reset_tokens SET requested_at = now() - interval '2 hours'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .post_password_reset(&serde_json::json!({
+            "reset_token": &reset_token,
+            "new_password": "a-brand-new-password",
+            "new_password_check": "a-brand-new-password"
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn resetting_a_password_logs_out_existing_sessions() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    give_test_user_an_email(&app).await;
+    app.test_user.login(&app).await;
+    let reset_token = request_reset_token(&app).await;
+
+    app.post_password_reset(&serde_json::json!({
+        "reset_token": &reset_token,
+        "new_password": "a-brand-new-password",
+        "new_password_check": "a-brand-new-password"
+    }))
+    .await;
+    let response = app.get_admin_dashboard().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
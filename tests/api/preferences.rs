@@ -0,0 +1,242 @@
+use mail_parser::Message;
+
+use crate::helpers::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscribers,
+    get_preference_token, publish_and_dispatch_newsletter, spawn_app, TestAppConfiguration,
+};
+
+fn preferences_redirect(preference_token: &str) -> String {
+    format!(
+        "/subscriptions/preferences?preference_token={}",
+        preference_token
+    )
+}
+
+#[tokio::test]
+async fn newsletter_issues_link_to_the_preference_center() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let preference_token = get_preference_token(&app).await;
+
+    publish_and_dispatch_newsletter(&app).await;
+
+    let messages = app.email_client.get_transport_ref().messages().await;
+    let raw_message = messages.last().unwrap().1.to_owned().into_bytes();
+    let message = Message::parse(&raw_message).unwrap();
+    let expected_link = format!(
+        "{}/subscriptions/preferences?preference_token={}",
+        app.base_url, preference_token
+    );
+    assert!(message.body_text(0).unwrap().contains(&expected_link));
+    assert!(message.body_html(0).unwrap().contains(&expected_link));
+}
+
+#[tokio::test]
+async fn confirmation_emails_link_to_the_preference_center() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_unconfirmed_subscribers(&app).await;
+    let preference_token = get_preference_token(&app).await;
+
+    let messages = app.email_client.get_transport_ref().messages().await;
+    let raw_message = messages.last().unwrap().1.to_owned().into_bytes();
+    let message = Message::parse(&raw_message).unwrap();
+    let expected_link = format!(
+        "{}/subscriptions/preferences?preference_token={}",
+        app.base_url, preference_token
+    );
+    assert!(message.body_text(0).unwrap().contains(&expected_link));
+    assert!(message.body_html(0).unwrap().contains(&expected_link));
+}
+
+#[tokio::test]
+async fn lists_picked_before_confirming_need_confirming() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_unconfirmed_subscribers(&app).await;
+    let preference_token = get_preference_token(&app).await;
+    let list_id = sqlx::query!("SELECT list_id FROM lists")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id;
+    // They drop the list, then pick it again.
+    for mut body in [
+        serde_json::json!({}),
+        serde_json::json!({ "list_id": list_id }),
+    ] {
+        body["preference_token"] = preference_token.clone().into();
+        body["name"] = "le guin".into();
+        let response = app.post_preferences(&body).await;
+        assert_is_redirect_to(&response, &preferences_redirect(&preference_token));
+    }
+
+    let saved = sqlx::query!("SELECT status FROM list_subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+    let html_page = app.get_preferences_html(&preference_token).await;
+    assert!(html_page.contains("(waiting for you to confirm)"));
+
+    let transport = app.email_client.get_transport_ref();
+    let links = app.get_confirmation_links(transport).await;
+    reqwest::get(links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let saved = sqlx::query!("SELECT status FROM list_subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn an_unknown_preference_token_is_rejected_with_a_401() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    let response = app.get_preferences("not-a-real-token").await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn subscribers_can_change_their_name() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    let preference_token = get_preference_token(&app).await;
+    let list_id = sqlx::query!("SELECT list_id FROM lists")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .list_id;
+
+    let response = app
+        .post_preferences(&serde_json::json!({
+            "preference_token": &preference_token,
+            "name": "Ursula K. Le Guin",
+            "list_id": list_id
+        }))
+        .await;
+    assert_is_redirect_to(&response, &preferences_redirect(&preference_token));
+
+    let html_page = app.get_preferences_html(&preference_token).await;
+    assert!(html_page.contains("<p><i>Your preferences have been updated.</i></p>"));
+    assert!(html_page.contains(r#"value="Ursula K. Le Guin""#));
+}
+
+#[tokio::test]
+async fn the_preference_page_escapes_what_subscribers_typed() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    let preference_token = get_preference_token(&app).await;
+    sqlx::query!(r#"UPDATE subscriptions SET name = '"><b>x'"#)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let html_page = app.get_preferences_html(&preference_token).await;
+
+    assert!(!html_page.contains("<b>x"));
+    assert!(html_page.contains(r#"value="&quot;&gt;&lt;b&gt;x""#));
+}
+
+#[tokio::test]
+async fn an_invalid_name_is_rejected() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    let preference_token = get_preference_token(&app).await;
+
+    let response = app
+        .post_preferences(&serde_json::json!({
+            "preference_token": &preference_token,
+            "name": "<script>"
+        }))
+        .await;
+    assert_is_redirect_to(&response, &preferences_redirect(&preference_token));
+
+    let html_page = app.get_preferences_html(&preference_token).await;
+    assert!(html_page.contains("not a valid subscriber name"));
+    assert!(!html_page.contains("<script>"));
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_do_not_receive_newsletters() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let preference_token = get_preference_token(&app).await;
+
+    let response = app
+        .post_unsubscribe(&serde_json::json!({ "preference_token": &preference_token }))
+        .await;
+    assert_is_redirect_to(&response, &preferences_redirect(&preference_token));
+    let transport = app.email_client.get_transport_ref();
+    let sent_before = transport.messages().await.len();
+    publish_and_dispatch_newsletter(&app).await;
+
+    assert_eq!(transport.messages().await.len(), sent_before);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn changing_email_requires_confirming_the_new_address() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    let preference_token = get_preference_token(&app).await;
+    let new_email = "new_address@example.com";
+
+    let response = app
+        .post_change_email(&serde_json::json!({
+            "preference_token": &preference_token,
+            "new_email": new_email
+        }))
+        .await;
+    assert_is_redirect_to(&response, &preferences_redirect(&preference_token));
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(saved.email, new_email);
+
+    let transport = app.email_client.get_transport_ref();
+    let messages = transport.messages().await;
+    assert_eq!(messages.last().unwrap().0.to()[0].to_string(), new_email);
+    let links = app.get_confirmation_links(transport).await;
+    let response = reqwest::get(links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, new_email);
+}
+
+#[tokio::test]
+async fn changing_email_to_a_disposable_address_is_refused() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    create_confirmed_subscriber(&app).await;
+    let preference_token = get_preference_token(&app).await;
+    let sent_before = app.email_client.get_transport_ref().messages().await.len();
+
+    let response = app
+        .post_change_email(&serde_json::json!({
+            "preference_token": &preference_token,
+            "new_email": "new_address@mailinator.com"
+        }))
+        .await;
+    assert_is_redirect_to(&response, &preferences_redirect(&preference_token));
+
+    let html_page = app.get_preferences_html(&preference_token).await;
+    assert!(html_page.contains("mailinator.com hands out temporary addresses"));
+    assert_eq!(
+        app.email_client.get_transport_ref().messages().await.len(),
+        sent_before
+    );
+}
@@ -0,0 +1,140 @@
+use crate::helpers::{spawn_app, TestApp, TestAppConfiguration};
+
+const ADMIN_ROUTES: [&str; 18] = [
+    "/admin/dashboard",
+    "/admin/newsletters",
+    "/admin/password",
+    "/admin/sessions",
+    "/admin/api-tokens",
+    "/admin/two-factor",
+    "/admin/lists",
+    "/admin/export/subscriptions",
+    "/admin/subscribers",
+    "/admin/audit-log",
+    "/admin/lockouts",
+    "/admin/email-domains",
+    "/admin/users",
+    "/login",
+    "/login/two-factor",
+    "/password-reset",
+    "/password-reset/confirm",
+    "/invitations/accept",
+];
+
+const PUBLIC_ROUTES: [&str; 10] = [
+    "/",
+    "/health_check",
+    "/metrics",
+    "/openapi.json",
+    "/subscriptions/confirm",
+    "/subscriptions/preferences",
+    "/subscriptions/data-requests/confirm",
+    "/api/v1/me",
+    "/api/v1/newsletters",
+    "/no-such-page",
+];
+
+fn assert_security_headers(response: &reqwest::Response, content_security_policy: &str) {
+    let headers = response.headers();
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .unwrap_or_else(|| panic!("{} has no {} header", response.url(), name))
+            .to_str()
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(header("Content-Security-Policy"), content_security_policy);
+    assert_eq!(header("X-Frame-Options"), "DENY");
+    assert_eq!(header("Referrer-Policy"), "same-origin");
+    assert_eq!(header("X-Content-Type-Options"), "nosniff");
+}
+
+async fn get(app: &TestApp, path: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}{}", &app.address, path))
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+#[tokio::test]
+async fn admin_pages_get_the_strict_policy() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let policy = TestAppConfiguration::new()
+        .configuration
+        .security_headers
+        .admin_content_security_policy;
+    app.test_user.login(&app).await;
+
+    for path in ADMIN_ROUTES {
+        assert_security_headers(&get(&app, path).await, &policy);
+    }
+}
+
+#[tokio::test]
+async fn redirects_of_anonymous_users_carry_the_headers() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let policy = TestAppConfiguration::new()
+        .configuration
+        .security_headers
+        .admin_content_security_policy;
+
+    for path in ADMIN_ROUTES.iter().filter(|p| p.starts_with("/admin")) {
+        let response = get(&app, path).await;
+        assert_eq!(response.status().as_u16(), 303);
+        assert_security_headers(&response, &policy);
+    }
+}
+
+#[tokio::test]
+async fn public_pages_get_the_relaxed_policy() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let policy = TestAppConfiguration::new()
+        .configuration
+        .security_headers
+        .public_content_security_policy;
+
+    for path in PUBLIC_ROUTES {
+        assert_security_headers(&get(&app, path).await, &policy);
+    }
+}
+
+#[tokio::test]
+async fn form_submissions_carry_the_headers() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let settings = TestAppConfiguration::new().configuration.security_headers;
+
+    let response = app.post_subscription("name=&email=".into()).await;
+    assert_security_headers(&response, &settings.public_content_security_policy);
+
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_security_headers(&response, &settings.admin_content_security_policy);
+}
+
+#[tokio::test]
+async fn hsts_is_only_sent_when_configured() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let response = get(&app, "/").await;
+    assert!(response
+        .headers()
+        .get("Strict-Transport-Security")
+        .is_none());
+
+    let mut configuration = TestAppConfiguration::new();
+    configuration
+        .configuration
+        .security_headers
+        .hsts_max_age_seconds = 31536000;
+    let app = spawn_app(configuration).await;
+    let response = get(&app, "/").await;
+    assert_eq!(
+        response.headers()["Strict-Transport-Security"],
+        "max-age=31536000; includeSubDomains"
+    );
+}
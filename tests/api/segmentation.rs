@@ -0,0 +1,122 @@
+use fake::faker::internet::en::SafeEmail;
+use fake::Fake;
+use uuid::Uuid;
+
+use crate::helpers::{
+    assert_is_redirect_to, spawn_app, subscribe_and_confirm, TestAppConfiguration,
+};
+
+/// The hidden fields of a landing page for subscribers from `country`.
+fn form_attributes(country: &str) -> serde_json::Value {
+    serde_json::json!({
+        "attr_country": country,
+        "attr_source": "landing page"
+    })
+}
+
+fn newsletter_request_body(segment: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "segment": segment
+    })
+}
+
+#[tokio::test]
+async fn prefixed_form_fields_are_stored_as_subscriber_attributes() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&attr_country=IR&attr_plan=pro&submit=Subscribe";
+    let response = app.post_subscription(body.into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT key, value FROM subscriber_attributes ORDER BY key")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let saved: Vec<_> = saved.into_iter().map(|r| (r.key, r.value)).collect();
+    assert_eq!(
+        saved,
+        vec![
+            ("country".to_string(), "IR".to_string()),
+            ("plan".to_string(), "pro".to_string())
+        ]
+    );
+}
+
+#[tokio::test]
+async fn subscribing_again_does_not_change_the_attributes() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    subscribe_and_confirm(&app, "ursula_le_guin@gmail.com", form_attributes("IR")).await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&attr_country=US&attr_plan=pro";
+    let response = app.post_subscription(body.into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT key, value FROM subscriber_attributes ORDER BY key")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let saved: Vec<_> = saved.into_iter().map(|r| (r.key, r.value)).collect();
+    assert_eq!(
+        saved,
+        vec![
+            ("country".to_string(), "IR".to_string()),
+            ("source".to_string(), "landing page".to_string())
+        ]
+    );
+}
+
+#[tokio::test]
+async fn invalid_attribute_names_are_rejected_with_a_400() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&attr_utm-source=x";
+    let response = app.post_subscription(body.into()).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn newsletters_are_only_delivered_to_subscribers_matching_the_segment() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let iranian: String = SafeEmail().fake();
+    subscribe_and_confirm(&app, &iranian, form_attributes("IR")).await;
+    subscribe_and_confirm(&app, &SafeEmail().fake::<String>(), form_attributes("DE")).await;
+    app.test_user.login(&app).await;
+    let transport = app.email_client.get_transport_ref();
+    let sent_before = transport.messages().await.len();
+
+    let response = app
+        .post_newsletters(&newsletter_request_body(
+            r#"country = IR and source = "landing page""#,
+        ))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    let messages = transport.messages().await;
+    assert_eq!(messages.len(), sent_before + 1);
+    assert_eq!(messages.last().unwrap().0.to()[0].to_string(), iranian);
+}
+
+#[tokio::test]
+async fn an_invalid_segment_is_reported_and_nothing_is_sent() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    subscribe_and_confirm(&app, &SafeEmail().fake::<String>(), form_attributes("IR")).await;
+    app.test_user.login(&app).await;
+    let transport = app.email_client.get_transport_ref();
+    let sent_before = transport.messages().await.len();
+
+    let response = app
+        .post_newsletters(&newsletter_request_body("country ="))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>Invalid segment: Expected a value"));
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(transport.messages().await.len(), sent_before);
+}
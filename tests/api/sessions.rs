@@ -0,0 +1,212 @@
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestAppConfiguration, TestUser};
+
+async fn get_dashboard(app: &TestApp, client: &reqwest::Client) -> reqwest::Response {
+    client
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+async fn oldest_session_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT session_id FROM user_sessions ORDER BY created_at LIMIT 1")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .session_id
+}
+
+#[tokio::test]
+async fn the_sessions_page_lists_every_active_login() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.login_elsewhere(&app.test_user).await;
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_sessions_html().await;
+
+    assert_eq!(html_page.matches("<tr><td>").count(), 2);
+    assert_eq!(html_page.matches("This session").count(), 1);
+    assert!(html_page.contains("127.0.0.1"));
+}
+
+#[tokio::test]
+async fn revoking_another_session_logs_it_out() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let other = app.login_elsewhere(&app.test_user).await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_revoke_session(oldest_session_id(&app).await).await;
+
+    assert_is_redirect_to(&response, "/admin/sessions");
+    assert!(app
+        .get_sessions_html()
+        .await
+        .contains("<p><i>The session has been logged out.</i></p>"));
+    assert_is_redirect_to(&get_dashboard(&app, &other).await, "/login");
+    assert_eq!(
+        get_dashboard(&app, &app.api_client).await.status().as_u16(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn users_cannot_revoke_the_sessions_of_others() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let owner = TestUser::generate();
+    owner.store(&app.db_pool).await;
+    let other = app.login_elsewhere(&owner).await;
+    app.test_user.login(&app).await;
+
+    app.post_revoke_session(oldest_session_id(&app).await).await;
+
+    assert!(app
+        .get_sessions_html()
+        .await
+        .contains("<p><i>There is no such session.</i></p>"));
+    assert_eq!(get_dashboard(&app, &other).await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn logging_out_other_sessions_keeps_the_current_one() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let first = app.login_elsewhere(&app.test_user).await;
+    let second = app.login_elsewhere(&app.test_user).await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_revoke_other_sessions().await;
+
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = app.get_sessions_html().await;
+    assert!(html_page.contains("<p><i>2 other sessions have been logged out.</i></p>"));
+    assert_eq!(html_page.matches("<tr><td>").count(), 1);
+    assert_is_redirect_to(&get_dashboard(&app, &first).await, "/login");
+    assert_is_redirect_to(&get_dashboard(&app, &second).await, "/login");
+}
+
+#[tokio::test]
+async fn changing_password_logs_out_other_sessions() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let other = app.login_elsewhere(&app.test_user).await;
+    app.test_user.login(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    app.post_change_password(&serde_json::json!({
+        "current_password": &app.test_user.password,
+        "new_password": &new_password,
+        "new_password_check": &new_password
+    }))
+    .await;
+
+    assert_is_redirect_to(&get_dashboard(&app, &other).await, "/login");
+    assert_eq!(
+        get_dashboard(&app, &app.api_client).await.status().as_u16(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn logging_out_ends_the_session() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+
+    app.post_logout().await;
+
+    let revoked_at = sqlx::query!("SELECT revoked_at FROM user_sessions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .revoked_at;
+    assert!(revoked_at.is_some());
+}
+
+fn session_cookie(response: &reqwest::Response) -> String {
+    response
+        .headers()
+        .get_all(reqwest::header::SET_COOKIE)
+        .iter()
+        .map(|v| v.to_str().unwrap().to_string())
+        .find(|v| v.starts_with("id="))
+        .expect("No session cookie was set")
+}
+
+#[tokio::test]
+async fn remembered_logins_get_a_persistent_cookie() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+            "remember_me": "on"
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let cookie = session_cookie(&response);
+    assert!(cookie.contains("Max-Age="), "{}", cookie);
+    assert!(cookie.contains("HttpOnly"), "{}", cookie);
+    assert!(cookie.contains("SameSite=Lax"), "{}", cookie);
+}
+
+#[tokio::test]
+async fn other_logins_end_with_the_browser() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    assert!(!session_cookie(&response).contains("Max-Age="));
+}
+
+#[tokio::test]
+async fn idle_sessions_are_logged_out() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+
+    sqlx::query!("UPDATE user_sessions SET last_seen_at = now() - interval '31 minutes'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn using_a_session_keeps_it_from_idling_out() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    sqlx::query!("UPDATE user_sessions SET last_seen_at = now() - interval '29 minutes'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    app.get_admin_dashboard().await;
+
+    let idle = sqlx::query!("SELECT now() - last_seen_at AS idle FROM user_sessions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .idle
+        .unwrap();
+    assert!(idle.microseconds < 60_000_000);
+}
+
+#[tokio::test]
+async fn expired_sessions_are_logged_out() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+
+    sqlx::query!("UPDATE user_sessions SET expires_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
@@ -1,6 +1,10 @@
 use actix_web::http::StatusCode;
 
-use crate::helpers::{spawn_app, TestAppConfiguration};
+use std::sync::Arc;
+
+use crate::helpers::{
+    email_client_for_fake_relay, spawn_app, spawn_app_sending_through, TestAppConfiguration,
+};
 
 #[tokio::test]
 async fn subscribe_returns_a_200_for_valid_form_data() {
@@ -114,3 +118,131 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
 
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn subscribe_stores_the_address_normalized() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    for email in [" Octavia@Example.COM ", "Ursula.Le.Guin@GoogleMail.com"] {
+        let body = serde_urlencoded::to_string([("name", "le guin"), ("email", email)]).unwrap();
+        let response = app.post_subscription(body).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let saved: Vec<String> = sqlx::query!("SELECT email FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.email)
+        .collect();
+    assert_eq!(
+        saved,
+        ["Octavia@example.com", "Ursula.Le.Guin@googlemail.com"]
+    );
+}
+
+#[tokio::test]
+async fn gmail_aliases_are_one_subscriber_if_the_settings_ask_for_it() {
+    let mut configuration = TestAppConfiguration::new();
+    configuration
+        .configuration
+        .email_domains
+        .merge_provider_aliases = true;
+    let app = spawn_app(configuration).await;
+
+    for email in ["Ursula.Le.Guin@GoogleMail.com", "ursulaleguin@gmail.com"] {
+        let body = serde_urlencoded::to_string([("name", "le guin"), ("email", email)]).unwrap();
+        let response = app.post_subscription(body).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "ursulaleguin@gmail.com");
+}
+
+#[tokio::test]
+async fn the_same_address_in_another_case_is_the_same_subscriber() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+
+    for email in ["Ursula@example.com", "URSULA@EXAMPLE.COM"] {
+        let body = serde_urlencoded::to_string([("name", "le guin"), ("email", email)]).unwrap();
+        let response = app.post_subscription(body).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "Ursula@example.com");
+    // Both attempts get a link to confirm.
+    assert_eq!(
+        app.email_client.get_transport_ref().messages().await.len(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn the_database_refuses_addresses_differing_only_in_case() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let insert = |email: &'static str| {
+        sqlx::query!(
+            "INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+            VALUES (gen_random_uuid(), $1, 'le guin', now(), 'confirmed')",
+            email
+        )
+        .execute(&app.db_pool)
+    };
+
+    insert("ursula@example.com").await.unwrap();
+
+    assert!(insert("Ursula@Example.com").await.is_err());
+}
+
+#[tokio::test]
+async fn subscribe_accepts_internationalized_addresses() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    let body =
+        serde_urlencoded::to_string([("name", "le guin"), ("email", "用户@例子.广告")]).unwrap();
+
+    let response = app.post_subscription(body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "用户@xn--fsqu00a.xn--4rr70v");
+    let messages = app.email_client.get_transport_ref().messages().await;
+    assert_eq!(messages[0].0.to()[0].to_string(), saved.email);
+}
+
+#[tokio::test]
+async fn internationalized_addresses_a_relay_cannot_take_leave_nothing_behind() {
+    let relay = email_client_for_fake_relay("250 ok").await;
+    let app = spawn_app_sending_through(TestAppConfiguration::new(), Arc::new(relay)).await;
+    let body =
+        serde_urlencoded::to_string([("name", "le guin"), ("email", "用户@例子.广告")]).unwrap();
+
+    for _ in 0..2 {
+        let response = app.post_subscription(body.clone()).await;
+        assert_eq!(response.status().as_u16(), 400);
+    }
+
+    let subscribers = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(subscribers.is_empty());
+    let tokens = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(tokens.is_empty());
+}
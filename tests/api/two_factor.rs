@@ -0,0 +1,198 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use secrecy::Secret;
+use totp_rs::TOTP;
+use zero2prod::authentication::totp;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp, TestAppConfiguration};
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Enrolls the logged-in test user and returns their generator
+/// along with the recovery codes that were shown.
+async fn enroll(app: &TestApp) -> (TOTP, Vec<String>) {
+    let html_page = app.get_two_factor_html().await;
+    let secret = html_page
+        .split("<code>")
+        .nth(1)
+        .unwrap()
+        .split("</code>")
+        .next()
+        .unwrap()
+        .to_owned();
+    let totp = totp(&Secret::new(secret), &app.test_user.username).unwrap();
+
+    let response = app.post_enable_two_factor(&totp.generate(now())).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    let recovery_codes = html_page
+        .split("<li><code>")
+        .skip(1)
+        .map(|s| s.split("</code>").next().unwrap().to_owned())
+        .collect();
+    (totp, recovery_codes)
+}
+
+async fn log_in_with_password(app: &TestApp) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn enrolling_shows_ten_recovery_codes() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+
+    let (_, recovery_codes) = enroll(&app).await;
+
+    assert_eq!(recovery_codes.len(), 10);
+    let html_page = app.get_two_factor_html().await;
+    assert!(html_page.contains("You have 10 unused recovery codes left."));
+}
+
+#[tokio::test]
+async fn enrolling_requires_a_valid_code() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    app.get_two_factor_html().await;
+
+    let response = app.post_enable_two_factor("000000").await;
+
+    assert_is_redirect_to(&response, "/admin/two-factor");
+    let html_page = app.get_two_factor_html().await;
+    assert!(html_page.contains("<p><i>The code you entered is not valid.</i></p>"));
+    assert!(html_page.contains("Enable two-factor authentication"));
+}
+
+#[tokio::test]
+async fn a_password_is_not_enough_once_two_factor_is_enabled() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    let (totp, _) = enroll(&app).await;
+    app.post_logout().await;
+
+    let response = log_in_with_password(&app).await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.post_login_two_factor("000000").await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+
+    // The enrollment code used up the current step.
+    let response = app.post_login_two_factor(&totp.generate(now() + 30)).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn totp_codes_cannot_be_replayed() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    let (totp, _) = enroll(&app).await;
+    let code = totp.generate(now() + 30);
+
+    app.post_logout().await;
+    log_in_with_password(&app).await;
+    let response = app.post_login_two_factor(&code).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    app.post_logout().await;
+    log_in_with_password(&app).await;
+    let response = app.post_login_two_factor(&code).await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn recovery_codes_work_exactly_once() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    let (_, recovery_codes) = enroll(&app).await;
+
+    app.post_logout().await;
+    log_in_with_password(&app).await;
+    let response = app.post_login_two_factor(&recovery_codes[0]).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    app.post_logout().await;
+    log_in_with_password(&app).await;
+    let response = app.post_login_two_factor(&recovery_codes[0]).await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn too_many_wrong_codes_require_the_password_again() {
+    // High enough for the login throttle to stay out of the way.
+    let mut configuration = TestAppConfiguration::new();
+    configuration
+        .configuration
+        .login_throttle
+        .max_failures_per_username = 100;
+    let app = spawn_app(configuration).await;
+    app.test_user.login(&app).await;
+    let (totp, _) = enroll(&app).await;
+    app.post_logout().await;
+
+    log_in_with_password(&app).await;
+    for _ in 0..4 {
+        let response = app.post_login_two_factor("000000").await;
+        assert_is_redirect_to(&response, "/login/two-factor");
+    }
+    let response = app.post_login_two_factor("000000").await;
+
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("Too many wrong codes - please log in again."));
+    let response = app.post_login_two_factor(&totp.generate(now() + 30)).await;
+    assert_is_redirect_to(&response, "/login");
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn a_correct_password_does_not_reset_wrong_codes() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    enroll(&app).await;
+    app.post_logout().await;
+
+    log_in_with_password(&app).await;
+    for _ in 0..4 {
+        let response = app.post_login_two_factor("000000").await;
+        assert_is_redirect_to(&response, "/login/two-factor");
+    }
+    let response = log_in_with_password(&app).await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+    app.post_login_two_factor("000000").await;
+
+    let response = log_in_with_password(&app).await;
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("Too many failed login attempts. Please try again in 15 minutes."));
+}
+
+#[tokio::test]
+async fn two_factor_can_be_disabled_with_a_code() {
+    let app = spawn_app(TestAppConfiguration::new()).await;
+    app.test_user.login(&app).await;
+    let (_, recovery_codes) = enroll(&app).await;
+
+    let response = app.post_disable_two_factor(&recovery_codes[0]).await;
+    assert_is_redirect_to(&response, "/admin/two-factor");
+
+    app.post_logout().await;
+    let response = log_in_with_password(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}